@@ -8,6 +8,8 @@ use std::io;
 use std::pin::Pin;
 use uuid::Uuid;
 
+pub use btleplug::platform::Adapter as BluetoothAdapter;
+
 // return bluetooth adapter
 pub async fn init_bluetooth() -> Result<Adapter, Box<dyn Error>> {
     let manager = Manager::new().await?;
@@ -33,6 +35,12 @@ async fn get_bt_events_listener(
     Ok(events)
 }
 
+/// Stops the scan started by [`bluetooth_devices_stream`] on the given adapter
+pub async fn stop_scan(adapter: &Adapter) -> Result<(), Box<dyn Error>> {
+    adapter.stop_scan().await?;
+    Ok(())
+}
+
 /// Struct representing a discovered Bluetooth device
 pub struct DiscoveredDevice {
     pub id: String,
@@ -57,26 +65,26 @@ pub fn bluetooth_devices_stream(
         while let Some(event) = events.next().await {
             match &event {
                 CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => {
-                    if let Some(target) = target_uuid {
-                        if id.to_string() != target.to_string() {
-                            continue;
-                        }
+                    if let Some(target) = target_uuid
+                        && id.to_string() != target.to_string()
+                    {
+                        continue;
                     }
-                    if let Ok(peripheral) = adapter.peripheral(id).await {
-                        if let Ok(properties) = peripheral.properties().await {
-                            let local_name = properties.as_ref().and_then(|p| p.local_name.clone());
-                            let rssi = properties.as_ref().and_then(|p| p.rssi);
-                            let event_type = match &event {
-                                CentralEvent::DeviceDiscovered(_) => "Discovered device",
-                                _ => "Device updated",
-                            };
-                            yield DiscoveredDevice {
-                                id: id.to_string(),
-                                local_name,
-                                rssi,
-                                event_type: event_type.to_string(),
-                            };
-                        }
+                    if let Ok(peripheral) = adapter.peripheral(id).await
+                        && let Ok(properties) = peripheral.properties().await
+                    {
+                        let local_name = properties.as_ref().and_then(|p| p.local_name.clone());
+                        let rssi = properties.as_ref().and_then(|p| p.rssi);
+                        let event_type = match &event {
+                            CentralEvent::DeviceDiscovered(_) => "Discovered device",
+                            _ => "Device updated",
+                        };
+                        yield DiscoveredDevice {
+                            id: id.to_string(),
+                            local_name,
+                            rssi,
+                            event_type: event_type.to_string(),
+                        };
                     }
                 }
                 _ => {}
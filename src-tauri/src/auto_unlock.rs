@@ -0,0 +1,134 @@
+use crate::lock_pipeline::LockContext;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+
+/// When the session locked by the app may be unlocked again as the device returns
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutoUnlock {
+    /// How long after the lock the device may return for the session to be unlocked
+    pub within_secs: u64,
+    /// RSSI the device must stay at or above
+    pub min_rssi: i16,
+    /// How long the device must stay at or above the minimum RSSI
+    pub dwell_secs: u64,
+}
+
+/// Where an unlock candidate stands after a reading
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dwell {
+    /// Too late to unlock, the candidate is dropped
+    Expired,
+    /// The device is not close enough or not for long enough yet
+    Waiting,
+    /// The device stayed close for the dwell period
+    Ready,
+}
+
+/// An automatic lock performed by the app, which the session may be unlocked from
+#[derive(Clone, Debug)]
+pub struct UnlockCandidate {
+    pub context: LockContext,
+    /// Lock generation of the session right after the lock, see
+    /// [`crate::lock_state::LockStateMonitor::lock_generation`]
+    pub lock_generation: u64,
+    locked_at: Instant,
+    strong_since: Option<Instant>,
+}
+
+impl UnlockCandidate {
+    pub fn new(context: LockContext, lock_generation: u64, locked_at: Instant) -> Self {
+        UnlockCandidate {
+            context,
+            lock_generation,
+            locked_at,
+            strong_since: None,
+        }
+    }
+
+    /// Takes a reading of the device into account, restarting the dwell period when the RSSI
+    /// falls below the minimum
+    pub fn observe(&mut self, settings: &AutoUnlock, rssi: i16, now: Instant) -> Dwell {
+        if now.duration_since(self.locked_at) > Duration::from_secs(settings.within_secs) {
+            return Dwell::Expired;
+        }
+        if rssi < settings.min_rssi {
+            self.strong_since = None;
+            return Dwell::Waiting;
+        }
+        let strong_since = *self.strong_since.get_or_insert(now);
+        if now.duration_since(strong_since) >= Duration::from_secs(settings.dwell_secs) {
+            Dwell::Ready
+        } else {
+            Dwell::Waiting
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pipeline::LockReason;
+
+    const SETTINGS: AutoUnlock = AutoUnlock {
+        within_secs: 300,
+        min_rssi: -60,
+        dwell_secs: 5,
+    };
+
+    fn candidate(locked_at: Instant) -> UnlockCandidate {
+        UnlockCandidate::new(LockContext::new(LockReason::OverDelta), 1, locked_at)
+    }
+
+    #[test]
+    fn test_ready_after_dwell() {
+        let start = Instant::now();
+        let mut candidate = candidate(start);
+        assert_eq!(candidate.observe(&SETTINGS, -55, start), Dwell::Waiting);
+        assert_eq!(
+            candidate.observe(&SETTINGS, -50, start + Duration::from_secs(4)),
+            Dwell::Waiting
+        );
+        assert_eq!(
+            candidate.observe(&SETTINGS, -55, start + Duration::from_secs(5)),
+            Dwell::Ready
+        );
+    }
+
+    #[test]
+    fn test_weak_reading_restarts_dwell() {
+        let start = Instant::now();
+        let mut candidate = candidate(start);
+        candidate.observe(&SETTINGS, -55, start);
+        assert_eq!(
+            candidate.observe(&SETTINGS, -70, start + Duration::from_secs(3)),
+            Dwell::Waiting
+        );
+        assert_eq!(
+            candidate.observe(&SETTINGS, -55, start + Duration::from_secs(6)),
+            Dwell::Waiting
+        );
+        assert_eq!(
+            candidate.observe(&SETTINGS, -55, start + Duration::from_secs(11)),
+            Dwell::Ready
+        );
+    }
+
+    #[test]
+    fn test_expired_after_window() {
+        let start = Instant::now();
+        let mut candidate = candidate(start);
+        assert_eq!(
+            candidate.observe(&SETTINGS, -40, start + Duration::from_secs(301)),
+            Dwell::Expired
+        );
+    }
+
+    #[test]
+    fn test_settings_deserialization() {
+        let json = r#"{"within_secs": 120, "min_rssi": -55, "dwell_secs": 10}"#;
+        let settings: AutoUnlock = serde_json::from_str(json).unwrap();
+        assert_eq!(settings.within_secs, 120);
+        assert_eq!(settings.min_rssi, -55);
+        assert_eq!(settings.dwell_secs, 10);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::read_write_settings::{FieldError, FieldErrorCode};
+use serde::{Deserialize, Serialize};
+
+/// Something done while the device is away
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AwayAction {
+    /// Shows a desktop notification
+    Notify,
+    /// Blanks the displays through the screen saver
+    Blank,
+    /// Locks the screen through the lock pipeline
+    Lock,
+    /// Suspends the machine through logind
+    Suspend,
+}
+
+/// An away action and how long after the device left it happens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AwayStep {
+    pub action: AwayAction,
+    pub delay_secs: u64,
+}
+
+/// The actions taken as absence grows, replacing the single immediate lock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct AwayActionPlan {
+    pub steps: Vec<AwayStep>,
+}
+
+impl AwayActionPlan {
+    /// Returns the steps in the order they happen
+    pub fn ordered_steps(&self) -> Vec<AwayStep> {
+        self.step_order()
+            .into_iter()
+            .map(|index| self.steps[index].clone())
+            .collect()
+    }
+
+    /// Returns the indexes of the steps in the order they happen
+    fn step_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.steps.len()).collect();
+        order.sort_by_key(|&index| self.steps[index].delay_secs);
+        order
+    }
+
+    /// Checks that the plan locks the screen, and does so before suspending the machine
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.steps.is_empty() {
+            errors.push(FieldError::new(
+                "away_action_plan.steps",
+                FieldErrorCode::InvalidValue,
+                "Away action plan has no steps, unset the plan to lock right away",
+            ));
+            return errors;
+        }
+        let order = self.step_order();
+        let Some(first_lock) = order
+            .iter()
+            .position(|&index| self.steps[index].action == AwayAction::Lock)
+        else {
+            // The plan replaces the lock, so without a lock step the session stays unlocked
+            errors.push(FieldError::new(
+                "away_action_plan.steps",
+                FieldErrorCode::InvalidValue,
+                "Away action plan has no lock step",
+            ));
+            return errors;
+        };
+        for &index in &order[..first_lock] {
+            if self.steps[index].action == AwayAction::Suspend {
+                errors.push(FieldError::new(
+                    format!("away_action_plan.steps[{}]", index),
+                    FieldErrorCode::InvalidValue,
+                    "Suspend step happens before the lock step",
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// An away action that was taken or attempted
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AwayActionEvent {
+    pub action: AwayAction,
+    pub delay_secs: u64,
+    pub dry_run: bool,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Performs an away action other than [`AwayAction::Lock`], which goes through the lock pipeline
+pub async fn perform_away_action(action: AwayAction) -> Result<(), String> {
+    match action {
+        AwayAction::Lock => Err("Lock is performed by the lock pipeline".to_string()),
+        #[cfg(target_os = "linux")]
+        AwayAction::Notify => {
+            let session_bus = zbus::Connection::session()
+                .await
+                .map_err(|e| format!("Failed to connect to session bus: {}", e))?;
+            crate::notifications::notify_away(&session_bus).await
+        }
+        #[cfg(target_os = "linux")]
+        AwayAction::Blank => {
+            let session_bus = zbus::Connection::session()
+                .await
+                .map_err(|e| format!("Failed to connect to session bus: {}", e))?;
+            crate::dbus_lock::blank_screen(&session_bus).await
+        }
+        #[cfg(target_os = "linux")]
+        AwayAction::Suspend => {
+            let system_bus = zbus::Connection::system()
+                .await
+                .map_err(|e| format!("Failed to connect to system bus: {}", e))?;
+            crate::logind::suspend(&system_bus).await
+        }
+        #[cfg(not(target_os = "linux"))]
+        _ => Err(format!(
+            "Away action {:?} is not supported on this platform",
+            action
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_deserialization() {
+        let json = r#"{"steps": [
+            {"action": "lock", "delay_secs": 30},
+            {"action": "notify", "delay_secs": 5},
+            {"action": "suspend", "delay_secs": 1800},
+            {"action": "blank", "delay_secs": 15}
+        ]}"#;
+        let plan: AwayActionPlan = serde_json::from_str(json).unwrap();
+        let actions: Vec<AwayAction> = plan
+            .ordered_steps()
+            .iter()
+            .map(|step| step.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                AwayAction::Notify,
+                AwayAction::Blank,
+                AwayAction::Lock,
+                AwayAction::Suspend
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordered_steps_keeps_order_of_equal_delays() {
+        let plan = AwayActionPlan {
+            steps: vec![
+                AwayStep {
+                    action: AwayAction::Blank,
+                    delay_secs: 0,
+                },
+                AwayStep {
+                    action: AwayAction::Lock,
+                    delay_secs: 0,
+                },
+            ],
+        };
+        assert_eq!(plan.ordered_steps(), plan.steps);
+    }
+
+    fn step(action: AwayAction, delay_secs: u64) -> AwayStep {
+        AwayStep { action, delay_secs }
+    }
+
+    fn invalid_fields(plan: &AwayActionPlan) -> Vec<String> {
+        plan.validate()
+            .into_iter()
+            .map(|error| error.field)
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let plan = AwayActionPlan {
+            steps: vec![
+                step(AwayAction::Notify, 0),
+                step(AwayAction::Lock, 30),
+                step(AwayAction::Suspend, 1800),
+            ],
+        };
+        assert!(plan.validate().is_empty());
+
+        assert_eq!(
+            invalid_fields(&AwayActionPlan::default()),
+            ["away_action_plan.steps"]
+        );
+        let plan = AwayActionPlan {
+            steps: vec![step(AwayAction::Notify, 0), step(AwayAction::Blank, 15)],
+        };
+        assert_eq!(invalid_fields(&plan), ["away_action_plan.steps"]);
+
+        // Steps are checked in the order they happen, not the order they are listed
+        let plan = AwayActionPlan {
+            steps: vec![
+                step(AwayAction::Lock, 60),
+                step(AwayAction::Notify, 0),
+                step(AwayAction::Suspend, 30),
+            ],
+        };
+        assert_eq!(invalid_fields(&plan), ["away_action_plan.steps[2]"]);
+    }
+
+    #[tokio::test]
+    async fn test_lock_is_not_performed_here() {
+        assert!(perform_away_action(AwayAction::Lock).await.is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::desktop_env::SessionInfo;
+use crate::read_write_settings::find_executable;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::process::Stdio;
+use tokio::time::{timeout, Duration};
+
+/// How long each hygiene action may take
+const HYGIENE_ACTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `SSH_AGENTC_REMOVE_ALL_IDENTITIES` request of the ssh-agent protocol
+const SSH_AGENTC_REMOVE_ALL_IDENTITIES: u8 = 19;
+/// `SSH_AGENT_SUCCESS` reply of the ssh-agent protocol
+const SSH_AGENT_SUCCESS: u8 = 6;
+
+/// Commands clearing the clipboard on Wayland, in the order they are tried
+const WAYLAND_CLIPBOARD_COMMANDS: [(&str, &[&str]); 1] = [("wl-copy", &["--clear"])];
+
+/// Commands clearing the clipboard on X11, in the order they are tried
+const X11_CLIPBOARD_COMMANDS: [(&str, &[&str]); 2] = [
+    ("xsel", &["--clipboard", "--clear"]),
+    ("xclip", &["-selection", "clipboard", "-i", "/dev/null"]),
+];
+
+/// Secrets cleaned up right after the screen is locked, each one opt-in
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AwayHygiene {
+    /// Locks the Secret Service collections (GNOME Keyring, KWallet, KeePassXC)
+    #[serde(default)]
+    pub lock_keyrings: bool,
+    /// Removes every identity from the running ssh-agent
+    #[serde(default)]
+    pub flush_ssh_agent: bool,
+    /// Empties the clipboard
+    #[serde(default)]
+    pub clear_clipboard: bool,
+}
+
+/// Runs the enabled hygiene actions, each within a few seconds, returning the errors of the
+/// failed ones
+pub async fn run_away_hygiene(hygiene: &AwayHygiene) -> Vec<String> {
+    let mut errors = Vec::new();
+    if hygiene.lock_keyrings {
+        match within_timeout("lock keyrings", HYGIENE_ACTION_TIMEOUT, lock_keyrings()).await {
+            Ok(count) => info!("Locked {} keyring collections", count),
+            Err(e) => errors.push(e),
+        }
+    }
+    if hygiene.flush_ssh_agent {
+        match within_timeout("flush ssh-agent", HYGIENE_ACTION_TIMEOUT, flush_ssh_agent()).await {
+            Ok(()) => info!("Removed ssh-agent identities"),
+            Err(e) => errors.push(e),
+        }
+    }
+    if hygiene.clear_clipboard {
+        match within_timeout("clear clipboard", HYGIENE_ACTION_TIMEOUT, clear_clipboard()).await {
+            Ok(()) => info!("Cleared clipboard"),
+            Err(e) => errors.push(e),
+        }
+    }
+    for error in &errors {
+        warn!("{}", error);
+    }
+    errors
+}
+
+/// Runs a hygiene action, failing it when it takes longer than `max_wait`
+async fn within_timeout<T>(
+    action: &str,
+    max_wait: Duration,
+    future: impl Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    timeout(max_wait, future)
+        .await
+        .unwrap_or_else(|_| Err(format!("Failed to {} within {:?}", action, max_wait)))
+}
+
+/// Locks every Secret Service collection on the session bus
+#[cfg(target_os = "linux")]
+pub async fn lock_keyrings() -> Result<usize, String> {
+    let session_bus = zbus::Connection::session()
+        .await
+        .map_err(|e| format!("Failed to connect to session bus: {}", e))?;
+    lock_secret_collections(&session_bus).await
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn lock_keyrings() -> Result<usize, String> {
+    Err("Locking keyrings is not supported on this platform".to_string())
+}
+
+/// Locks the collections of the `org.freedesktop.secrets` service, returning how many were
+/// locked
+#[cfg(target_os = "linux")]
+pub async fn lock_secret_collections(session_bus: &zbus::Connection) -> Result<usize, String> {
+    use zbus::zvariant::OwnedObjectPath;
+
+    let service = zbus::Proxy::new(
+        session_bus,
+        "org.freedesktop.secrets",
+        "/org/freedesktop/secrets",
+        "org.freedesktop.Secret.Service",
+    )
+    .await
+    .map_err(|e| format!("Failed to reach Secret Service: {}", e))?;
+    let collections: Vec<OwnedObjectPath> = service
+        .get_property("Collections")
+        .await
+        .map_err(|e| format!("Failed to list Secret Service collections: {}", e))?;
+    if collections.is_empty() {
+        return Ok(0);
+    }
+    let (locked, _prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) = service
+        .call("Lock", &(collections,))
+        .await
+        .map_err(|e| format!("Failed to lock Secret Service collections: {}", e))?;
+    Ok(locked.len())
+}
+
+/// Removes every identity from the ssh-agent listening on `SSH_AUTH_SOCK`
+#[cfg(unix)]
+pub async fn flush_ssh_agent() -> Result<(), String> {
+    let socket = std::env::var_os("SSH_AUTH_SOCK")
+        .filter(|socket| !socket.is_empty())
+        .ok_or_else(|| "No ssh-agent running (SSH_AUTH_SOCK is not set)".to_string())?;
+    remove_all_identities(std::path::Path::new(&socket)).await
+}
+
+#[cfg(not(unix))]
+pub async fn flush_ssh_agent() -> Result<(), String> {
+    Err("Flushing ssh-agent is not supported on this platform".to_string())
+}
+
+/// Sends `SSH_AGENTC_REMOVE_ALL_IDENTITIES` to the ssh-agent listening on the given socket
+#[cfg(unix)]
+pub async fn remove_all_identities(socket: &std::path::Path) -> Result<(), String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::UnixStream::connect(socket)
+        .await
+        .map_err(|e| format!("Failed to connect to ssh-agent: {}", e))?;
+    // Messages are framed by a big-endian length followed by the message type
+    let mut request = 1u32.to_be_bytes().to_vec();
+    request.push(SSH_AGENTC_REMOVE_ALL_IDENTITIES);
+    stream
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("Failed to write to ssh-agent: {}", e))?;
+
+    let mut length = [0u8; 4];
+    stream
+        .read_exact(&mut length)
+        .await
+        .map_err(|e| format!("Failed to read from ssh-agent: {}", e))?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length == 0 {
+        return Err("Empty reply from ssh-agent".to_string());
+    }
+    let mut reply = vec![0u8; length];
+    stream
+        .read_exact(&mut reply)
+        .await
+        .map_err(|e| format!("Failed to read from ssh-agent: {}", e))?;
+    match reply[0] {
+        SSH_AGENT_SUCCESS => Ok(()),
+        code => Err(format!(
+            "ssh-agent refused to remove identities (reply {})",
+            code
+        )),
+    }
+}
+
+/// Empties the clipboard with the first available clipboard tool
+pub async fn clear_clipboard() -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        return clear_clipboard_with(&[("pbcopy", &[])]).await;
+    }
+    if cfg!(target_os = "windows") {
+        return clear_clipboard_with(&[("clip", &[])]).await;
+    }
+    if SessionInfo::detect().is_wayland() {
+        clear_clipboard_with(&WAYLAND_CLIPBOARD_COMMANDS).await
+    } else {
+        clear_clipboard_with(&X11_CLIPBOARD_COMMANDS).await
+    }
+}
+
+/// Runs the first of the given clipboard commands that is installed, with an empty input
+pub async fn clear_clipboard_with(commands: &[(&str, &[&str])]) -> Result<(), String> {
+    let (program, args) = commands
+        .iter()
+        .find_map(|(program, args)| find_executable(program).map(|program| (program, args)))
+        .ok_or_else(|| "No clipboard tool found to clear the clipboard".to_string())?;
+    let status = tokio::process::Command::new(&program)
+        .args(*args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|e| format!("Failed to start '{}': {}", program.display(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to clear clipboard with '{}': {}",
+            program.display(),
+            status
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hygiene_disabled_by_default() {
+        let hygiene: AwayHygiene = serde_json::from_str("{}").unwrap();
+        assert_eq!(hygiene, AwayHygiene::default());
+        assert!(!hygiene.lock_keyrings && !hygiene.flush_ssh_agent && !hygiene.clear_clipboard);
+    }
+
+    #[tokio::test]
+    async fn test_nothing_runs_when_disabled() {
+        assert!(run_away_hygiene(&AwayHygiene::default()).await.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_all_identities() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        let agent = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            stream
+                .write_all(&[0, 0, 0, 1, SSH_AGENT_SUCCESS])
+                .await
+                .unwrap();
+            request
+        });
+
+        let result = remove_all_identities(&socket).await;
+
+        assert!(result.is_ok(), "Flushing should succeed: {:?}", result);
+        assert_eq!(
+            agent.await.unwrap(),
+            [0, 0, 0, 1, SSH_AGENTC_REMOVE_ALL_IDENTITIES]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unresponsive_action_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        // Accept the connection but never reply
+        let agent = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+        let result = within_timeout(
+            "flush ssh-agent",
+            Duration::from_millis(200),
+            remove_all_identities(&socket),
+        )
+        .await;
+
+        assert!(result.unwrap_err().contains("within"));
+        drop(agent.await.unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_all_identities_failure_reply() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            // SSH_AGENT_FAILURE
+            stream.write_all(&[0, 0, 0, 1, 5]).await.unwrap();
+        });
+
+        let result = remove_all_identities(&socket).await;
+
+        assert!(result.unwrap_err().contains("refused"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_clear_clipboard_with_first_installed_tool() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("cleared");
+        let tool = dir.path().join("clipboard-tool");
+        std::fs::write(
+            &tool,
+            format!(
+                "#!/bin/sh\ncat > /dev/null\necho \"$@\" > {}\n",
+                marker.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let tool = tool.to_string_lossy().to_string();
+
+        let result = clear_clipboard_with(&[
+            ("lock-4-me-missing-clipboard-tool", &[]),
+            (&tool, &["--clear"]),
+        ])
+        .await;
+
+        assert!(result.is_ok(), "Clearing should succeed: {:?}", result);
+        assert_eq!(std::fs::read_to_string(&marker).unwrap().trim(), "--clear");
+    }
+
+    #[tokio::test]
+    async fn test_clear_clipboard_without_tool() {
+        let result = clear_clipboard_with(&[("lock-4-me-missing-clipboard-tool", &[])]).await;
+        assert!(result.unwrap_err().contains("No clipboard tool"));
+    }
+}
@@ -0,0 +1,923 @@
+use crate::file_watcher::FileWatcher;
+use crate::read_write_settings::{FieldError, FieldErrorCode};
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How long a calendar file must stay untouched before it is read again
+const RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Most occurrences, and periods of the rule, of a recurring event looked through, so that a
+/// rule that never reaches the current time cannot loop forever
+const MAX_OCCURRENCES: usize = 100_000;
+
+/// What happens to automatic locks during a matching calendar event
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarAction {
+    /// Automatic locks are skipped
+    #[default]
+    Suppress,
+    /// Automatic locks are preceded by a warning of at least `warning_secs`, during which they
+    /// can be cancelled
+    Relax { warning_secs: u64 },
+}
+
+/// Local calendar files whose events suppress or relax automatic locking
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CalendarSettings {
+    /// Paths of `.ics` files, such as calendars exported from a mail client
+    pub files: Vec<PathBuf>,
+    /// Words looked for, regardless of case, in the summary and description of the events
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub action: CalendarAction,
+}
+
+impl CalendarSettings {
+    /// Checks the calendar settings, returning an error for each invalid field
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        for (index, file) in self.files.iter().enumerate() {
+            if file.as_os_str().is_empty() {
+                errors.push(FieldError::new(
+                    format!("calendar.files[{}]", index),
+                    FieldErrorCode::InvalidValue,
+                    "Calendar file path is empty",
+                ));
+            }
+        }
+        if self
+            .keywords
+            .iter()
+            .all(|keyword| keyword.trim().is_empty())
+        {
+            errors.push(FieldError::new(
+                "calendar.keywords",
+                FieldErrorCode::InvalidValue,
+                "At least one keyword is needed to match events",
+            ));
+        }
+        if self.action == (CalendarAction::Relax { warning_secs: 0 }) {
+            errors.push(FieldError::new(
+                "calendar.action.relax.warning_secs",
+                FieldErrorCode::OutOfRange,
+                "Relaxed lock warning must be greater than zero",
+            ));
+        }
+        errors
+    }
+}
+
+/// Time zone of the date-times of an event
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Zone {
+    Utc,
+    Named(Tz),
+    /// Local time of the system, for floating date-times and unknown time zones
+    Floating,
+}
+
+impl Zone {
+    /// Instant of a local date-time, the earlier one when clocks go back and the first valid
+    /// one when they go forward
+    fn to_utc(self, local: NaiveDateTime) -> DateTime<Utc> {
+        fn resolve<T: TimeZone>(zone: &T, local: NaiveDateTime) -> DateTime<Utc> {
+            (0..4)
+                .find_map(|hours| {
+                    zone.from_local_datetime(&(local + Duration::hours(hours)))
+                        .earliest()
+                })
+                .map(|at| at.with_timezone(&Utc))
+                .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+        }
+        match self {
+            Zone::Utc => Utc.from_utc_datetime(&local),
+            Zone::Named(tz) => resolve(&tz, local),
+            Zone::Floating => resolve(&Local, local),
+        }
+    }
+}
+
+/// How often an event repeats
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The supported subset of an `RRULE`: a frequency with an interval, a count or an end, and
+/// the days of the week for weekly rules
+#[derive(Clone, Debug, PartialEq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<usize>,
+    until: Option<DateTime<Utc>>,
+    by_day: Vec<Weekday>,
+}
+
+/// An event of a calendar file
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    start: NaiveDateTime,
+    zone: Zone,
+    duration: Duration,
+    recurrence: Option<Recurrence>,
+    excluded: Vec<DateTime<Utc>>,
+    /// Start of the occurrence of another event this event replaces
+    replaces: Option<DateTime<Utc>>,
+}
+
+impl CalendarEvent {
+    /// Returns whether the summary or description contains one of the keywords
+    pub fn matches(&self, keywords: &[String]) -> bool {
+        let text = format!("{}\n{}", self.summary, self.description).to_lowercase();
+        keywords
+            .iter()
+            .map(|keyword| keyword.trim().to_lowercase())
+            .any(|keyword| !keyword.is_empty() && text.contains(&keyword))
+    }
+
+    /// Returns whether an occurrence of the event is under way at the given instant
+    pub fn occurs_at(&self, now: DateTime<Utc>) -> bool {
+        for start in self.occurrences().take(MAX_OCCURRENCES) {
+            let start = self.zone.to_utc(start);
+            if start > now {
+                break;
+            }
+            if now < start + self.duration && !self.excluded.contains(&start) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Local start of each occurrence, in order
+    fn occurrences(&self) -> Box<dyn Iterator<Item = NaiveDateTime> + '_> {
+        let Some(rule) = &self.recurrence else {
+            return Box::new(std::iter::once(self.start));
+        };
+        let start = self.start;
+        let interval = i64::from(rule.interval.max(1));
+        let time = start.time();
+        let periods = (0..MAX_OCCURRENCES as i64).map_while(move |period| {
+            // Dates out of range end the occurrences
+            let after_days = |days: i64| {
+                start
+                    .date()
+                    .checked_add_signed(Duration::try_days(days.checked_mul(period * interval)?)?)
+            };
+            let dates: Vec<NaiveDate> = match rule.frequency {
+                Frequency::Daily => vec![after_days(1)?],
+                Frequency::Weekly if rule.by_day.is_empty() => vec![after_days(7)?],
+                Frequency::Weekly => {
+                    let monday = after_days(7)?
+                        - Duration::days(i64::from(start.weekday().num_days_from_monday()));
+                    let mut days: Vec<u32> = rule
+                        .by_day
+                        .iter()
+                        .map(|day| day.num_days_from_monday())
+                        .collect();
+                    days.sort_unstable();
+                    days.dedup();
+                    days.into_iter()
+                        .map(|day| monday + Duration::days(i64::from(day)))
+                        .collect()
+                }
+                Frequency::Monthly => {
+                    let months = i64::from(start.month0()) + period * interval;
+                    let year = i32::try_from(i64::from(start.year()) + months / 12).ok()?;
+                    NaiveDate::from_ymd_opt(year, (months % 12) as u32 + 1, start.day())
+                        .into_iter()
+                        .collect()
+                }
+                Frequency::Yearly => {
+                    let year = i32::try_from(i64::from(start.year()) + period * interval).ok()?;
+                    NaiveDate::from_ymd_opt(year, start.month(), start.day())
+                        .into_iter()
+                        .collect()
+                }
+            };
+            Some(dates)
+        });
+        let zone = self.zone;
+        let occurrences = periods
+            .flatten()
+            .map(move |date| date.and_time(time))
+            .filter(move |occurrence| *occurrence >= start)
+            .take_while(move |occurrence| {
+                rule.until
+                    .is_none_or(|until| zone.to_utc(*occurrence) <= until)
+            });
+        match rule.count {
+            Some(count) => Box::new(occurrences.take(count)),
+            None => Box::new(occurrences),
+        }
+    }
+}
+
+/// A content line of a calendar file
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl Property<'_> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+}
+
+/// Joins folded lines, which continue on the next line after a space or a tab
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property<'_>> {
+    // The value starts at the first colon outside of a quoted parameter value
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(index, c)| {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some(index),
+            _ => {}
+        }
+        None
+    })?;
+    let mut parts = line[..colon].split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (name.to_ascii_uppercase(), value))
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: &line[colon + 1..],
+    })
+}
+
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(other) => text.push(other),
+            None => {}
+        }
+    }
+    text
+}
+
+/// Parses a `DATE` or `DATE-TIME` value, returning whether it is a date
+fn parse_date_time(value: &str, tzid: Option<&str>) -> Result<(NaiveDateTime, Zone, bool), String> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok((date.and_time(NaiveTime::MIN), Zone::Floating, true));
+    }
+    let (local, utc) = match value.strip_suffix(['Z', 'z']) {
+        Some(local) => (local, true),
+        None => (value, false),
+    };
+    let local = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S")
+        .map_err(|e| format!("Invalid date '{}': {}", value, e))?;
+    let zone = if utc {
+        Zone::Utc
+    } else {
+        match tzid {
+            Some(tzid) => match tzid.parse::<Tz>() {
+                Ok(tz) => Zone::Named(tz),
+                Err(_) => {
+                    warn!("Unknown time zone '{}', using the system time zone", tzid);
+                    Zone::Floating
+                }
+            },
+            None => Zone::Floating,
+        }
+    };
+    Ok((local, zone, false))
+}
+
+/// Parses a duration such as `PT1H30M` or `P1D`
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}'", value);
+    let (negative, rest) = match value.trim().strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.trim().trim_start_matches('+')),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            _ => {
+                let amount: i64 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                duration += match (c, in_time) {
+                    ('W', false) => Duration::weeks(amount),
+                    ('D', false) => Duration::days(amount),
+                    ('H', true) => Duration::hours(amount),
+                    ('M', true) => Duration::minutes(amount),
+                    ('S', true) => Duration::seconds(amount),
+                    _ => return Err(invalid()),
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(if negative { -duration } else { duration })
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday, String> {
+    match value {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("Unsupported day '{}'", value)),
+    }
+}
+
+/// Parses an `RRULE`, rejecting the parts that are not supported rather than misreading them
+fn parse_recurrence(value: &str, zone: Zone) -> Result<Recurrence, String> {
+    let mut frequency = None;
+    let mut recurrence = Recurrence {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+    };
+    for part in value.split(';').filter(|part| !part.is_empty()) {
+        let (name, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid rule part '{}'", part))?;
+        match name.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    other => return Err(format!("Unsupported frequency '{}'", other)),
+                })
+            }
+            "INTERVAL" => {
+                recurrence.interval = value
+                    .parse()
+                    .map_err(|_| format!("Invalid interval '{}'", value))?
+            }
+            "COUNT" => {
+                recurrence.count = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid count '{}'", value))?,
+                )
+            }
+            "UNTIL" => {
+                let (local, until_zone, is_date) = parse_date_time(value, None)?;
+                recurrence.until = Some(if is_date {
+                    // The whole last day is included
+                    zone.to_utc(local + Duration::days(1) - Duration::seconds(1))
+                } else if until_zone == Zone::Utc {
+                    until_zone.to_utc(local)
+                } else {
+                    zone.to_utc(local)
+                });
+            }
+            "BYDAY" => {
+                recurrence.by_day = value
+                    .split(',')
+                    .map(|day| parse_weekday(&day.to_ascii_uppercase()))
+                    .collect::<Result<_, _>>()?
+            }
+            // Only changes weeks starting on another day for weekly rules with an interval
+            "WKST" => {}
+            other => return Err(format!("Unsupported rule part '{}'", other)),
+        }
+    }
+    recurrence.frequency = frequency.ok_or_else(|| "Rule without frequency".to_string())?;
+    if !recurrence.by_day.is_empty() && recurrence.frequency != Frequency::Weekly {
+        return Err("Days are only supported in weekly rules".to_string());
+    }
+    Ok(recurrence)
+}
+
+/// Properties of an event being read
+#[derive(Default)]
+struct EventBuilder {
+    uid: String,
+    summary: String,
+    description: String,
+    start: Option<(NaiveDateTime, Zone, bool)>,
+    end: Option<(NaiveDateTime, Zone, bool)>,
+    duration: Option<Duration>,
+    rule: Option<String>,
+    excluded: Vec<DateTime<Utc>>,
+    replaces: Option<DateTime<Utc>>,
+    cancelled: bool,
+}
+
+impl EventBuilder {
+    fn add(&mut self, property: &Property) -> Result<(), String> {
+        match property.name.as_str() {
+            "UID" => self.uid = property.value.to_string(),
+            "SUMMARY" => self.summary = unescape(property.value),
+            "DESCRIPTION" => self.description = unescape(property.value),
+            "DTSTART" => {
+                self.start = Some(parse_date_time(property.value, property.param("TZID"))?)
+            }
+            "DTEND" => self.end = Some(parse_date_time(property.value, property.param("TZID"))?),
+            "DURATION" => self.duration = Some(parse_duration(property.value)?),
+            "RRULE" => self.rule = Some(property.value.to_string()),
+            "EXDATE" => {
+                for value in property.value.split(',') {
+                    let (local, zone, _) = parse_date_time(value, property.param("TZID"))?;
+                    self.excluded.push(zone.to_utc(local));
+                }
+            }
+            "RECURRENCE-ID" => {
+                let (local, zone, _) = parse_date_time(property.value, property.param("TZID"))?;
+                self.replaces = Some(zone.to_utc(local));
+            }
+            "STATUS" => self.cancelled = property.value.eq_ignore_ascii_case("CANCELLED"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Option<CalendarEvent>, String> {
+        if self.cancelled {
+            return Ok(None);
+        }
+        let (start, zone, is_date) = self.start.ok_or("Event without start")?;
+        let duration = match (self.end, self.duration) {
+            // The end may be in another time zone than the start
+            (Some((end, end_zone, _)), _) => end_zone.to_utc(end) - zone.to_utc(start),
+            (None, Some(duration)) => duration,
+            // A date lasts the whole day, a date-time has no duration
+            (None, None) if is_date => Duration::days(1),
+            (None, None) => Duration::zero(),
+        };
+        let recurrence = match self.rule {
+            Some(rule) => match parse_recurrence(&rule, zone) {
+                Ok(recurrence) => Some(recurrence),
+                Err(e) => {
+                    warn!(
+                        "{} in the rule of event '{}', only its first occurrence is used",
+                        e, self.summary
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        Ok(Some(CalendarEvent {
+            uid: self.uid,
+            summary: self.summary,
+            description: self.description,
+            start,
+            zone,
+            duration,
+            recurrence,
+            excluded: self.excluded,
+            replaces: self.replaces,
+        }))
+    }
+}
+
+/// Reads the events of an iCalendar file. Events that cannot be read are skipped with a
+/// warning.
+///
+/// Time zones are expected to be IANA names, as written by most calendar applications; others
+/// are read in the time zone of the system. Occurrences moved with a `RECURRENCE-ID` replace
+/// the ones they were moved from.
+pub fn parse_calendar(text: &str) -> Result<Vec<CalendarEvent>, String> {
+    let lines = unfold(text);
+    if !lines
+        .iter()
+        .any(|line| line.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR"))
+    {
+        return Err("Not an iCalendar file".to_string());
+    }
+    let mut events = Vec::new();
+    let mut components: Vec<String> = Vec::new();
+    let mut event: Option<EventBuilder> = None;
+    let mut event_error: Option<String> = None;
+    for line in &lines {
+        let Some(property) = parse_property(line.trim_end()) else {
+            continue;
+        };
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.trim().to_ascii_uppercase();
+                if component == "VEVENT" && components.last().is_some_and(|c| c == "VCALENDAR") {
+                    event = Some(EventBuilder::default());
+                    event_error = None;
+                }
+                components.push(component);
+            }
+            "END" => {
+                if components.pop().as_deref() == Some("VEVENT") && components.len() == 1 {
+                    let built = match (event.take(), event_error.take()) {
+                        (Some(builder), None) => builder.build(),
+                        (_, Some(e)) => Err(e),
+                        (None, None) => Ok(None),
+                    };
+                    match built {
+                        Ok(Some(built)) => events.push(built),
+                        Ok(None) => {}
+                        Err(e) => warn!("Skipped calendar event: {}", e),
+                    }
+                }
+            }
+            _ if components.last().is_some_and(|c| c == "VEVENT") => {
+                if let (Some(builder), None) = (event.as_mut(), &event_error) {
+                    if let Err(e) = builder.add(&property) {
+                        event_error = Some(e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Moved occurrences are excluded from the recurring event they come from
+    let moved: Vec<(String, DateTime<Utc>)> = events
+        .iter()
+        .filter_map(|event| Some((event.uid.clone(), event.replaces?)))
+        .collect();
+    for (uid, start) in moved {
+        for event in events
+            .iter_mut()
+            .filter(|event| event.uid == uid && event.replaces.is_none())
+        {
+            event.excluded.push(start);
+        }
+    }
+    Ok(events)
+}
+
+/// The calendar event under way that affects automatic locks
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarMatch {
+    pub summary: String,
+    pub action: CalendarAction,
+}
+
+/// Keeps the events of the calendar files in memory, reading the files again when they change.
+///
+/// Cloning the monitor shares the same state.
+#[derive(Clone, Default)]
+pub struct CalendarMonitor {
+    settings: Arc<Mutex<Option<CalendarSettings>>>,
+    events: Arc<Mutex<BTreeMap<PathBuf, Vec<CalendarEvent>>>>,
+    watchers: Arc<Mutex<Vec<FileWatcher>>>,
+}
+
+impl CalendarMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the calendar files and watches them for changes, unless the settings did not
+    /// change
+    pub fn configure(&self, settings: Option<&CalendarSettings>) {
+        let mut current = self.settings.lock().unwrap();
+        if current.as_ref() == settings {
+            return;
+        }
+        *current = settings.cloned();
+        self.events.lock().unwrap().clear();
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.clear();
+        let Some(settings) = settings else {
+            return;
+        };
+        for path in &settings.files {
+            load_file(&self.events, path);
+            let events = self.events.clone();
+            let watched = path.clone();
+            match FileWatcher::start(path, RELOAD_DEBOUNCE, move || load_file(&events, &watched)) {
+                Ok(watcher) => watchers.push(watcher),
+                Err(e) => warn!(
+                    "Changes to calendar '{}' will be missed: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Returns the first event under way matching the keywords, if any
+    pub fn current_event(&self, now: DateTime<Utc>) -> Option<CalendarMatch> {
+        let settings = self.settings.lock().unwrap().clone()?;
+        let events = self.events.lock().unwrap();
+        events
+            .values()
+            .flatten()
+            .find(|event| event.matches(&settings.keywords) && event.occurs_at(now))
+            .map(|event| CalendarMatch {
+                summary: event.summary.clone(),
+                action: settings.action.clone(),
+            })
+    }
+}
+
+/// Reads the events of a calendar file. A file that cannot be read has no events, while one
+/// that is not a calendar keeps the events read before.
+fn load_file(events: &Mutex<BTreeMap<PathBuf, Vec<CalendarEvent>>>, path: &Path) {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Failed to read calendar '{}': {}", path.display(), e);
+            events.lock().unwrap().remove(path);
+            return;
+        }
+    };
+    match parse_calendar(&text) {
+        Ok(parsed) => {
+            info!(
+                "Read {} events from calendar '{}'",
+                parsed.len(),
+                path.display()
+            );
+            events.lock().unwrap().insert(path.to_path_buf(), parsed);
+        }
+        Err(e) => warn!("Failed to read calendar '{}': {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//EN\r\n{}END:VCALENDAR\r\n",
+            events
+        )
+    }
+
+    fn keywords(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_single_event_in_time_zone() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:demo\r\nSUMMARY:Customer Demo\r\n\
+             DTSTART;TZID=Europe/Paris:20261020T140000\r\n\
+             DTEND;TZID=Europe/Paris:20261020T153000\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert!(event.matches(&keywords(&["demo"])));
+        assert!(!event.matches(&keywords(&["presentation", " "])));
+        // Paris is UTC+2 in October
+        assert!(!event.occurs_at(utc(2026, 10, 20, 11, 59)));
+        assert!(event.occurs_at(utc(2026, 10, 20, 12, 0)));
+        assert!(event.occurs_at(utc(2026, 10, 20, 13, 29)));
+        assert!(!event.occurs_at(utc(2026, 10, 20, 13, 30)));
+    }
+
+    #[test]
+    fn test_end_in_other_time_zone() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:call\r\nSUMMARY:Call\r\n\
+             DTSTART;TZID=Europe/Paris:20261020T140000\r\n\
+             DTEND;TZID=America/New_York:20261020T090000\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+        let event = &events[0];
+
+        // 14:00 in Paris and 09:00 in New York are 12:00 and 13:00 UTC
+        assert!(event.occurs_at(utc(2026, 10, 20, 12, 30)));
+        assert!(!event.occurs_at(utc(2026, 10, 20, 13, 0)));
+    }
+
+    #[test]
+    fn test_weekly_rule_with_excluded_date() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:standup\r\nSUMMARY:Standup\r\n\
+             DTSTART;TZID=Europe/Paris:20261019T093000\r\nDURATION:PT15M\r\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,TH;UNTIL=20261130T000000Z\r\n\
+             EXDATE;TZID=Europe/Paris:20261022T093000\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+        let event = &events[0];
+
+        // Monday 19 October, first occurrence
+        assert!(event.occurs_at(utc(2026, 10, 19, 7, 35)));
+        // Thursday 22 October is excluded
+        assert!(!event.occurs_at(utc(2026, 10, 22, 7, 35)));
+        // Wednesday 28 October
+        assert!(!event.occurs_at(utc(2026, 10, 28, 8, 35)));
+        // Thursday 29 October, after the change to UTC+1
+        assert!(event.occurs_at(utc(2026, 10, 29, 8, 35)));
+        assert!(!event.occurs_at(utc(2026, 10, 29, 7, 35)));
+        // After the end of the rule
+        assert!(!event.occurs_at(utc(2026, 11, 30, 8, 35)));
+    }
+
+    #[test]
+    fn test_daily_rule_with_count_and_interval() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:review\r\nSUMMARY:Review\r\n\
+             DTSTART:20261001T100000Z\r\nDTEND:20261001T110000Z\r\n\
+             RRULE:FREQ=DAILY;INTERVAL=2;COUNT=3\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+        let event = &events[0];
+
+        assert!(event.occurs_at(utc(2026, 10, 3, 10, 30)));
+        assert!(!event.occurs_at(utc(2026, 10, 4, 10, 30)));
+        assert!(event.occurs_at(utc(2026, 10, 5, 10, 30)));
+        assert!(!event.occurs_at(utc(2026, 10, 7, 10, 30)));
+    }
+
+    #[test]
+    fn test_monthly_rule_skips_missing_days() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:closing\r\nSUMMARY:Closing\r\n\
+             DTSTART:20260131T100000Z\r\nDURATION:PT1H\r\n\
+             RRULE:FREQ=MONTHLY\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+        let event = &events[0];
+
+        assert!(!event.occurs_at(utc(2026, 2, 28, 10, 30)));
+        assert!(event.occurs_at(utc(2026, 3, 31, 10, 30)));
+    }
+
+    #[test]
+    fn test_moved_and_cancelled_occurrences() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:weekly\r\nSUMMARY:Demo\r\n\
+             DTSTART:20261019T100000Z\r\nDTEND:20261019T110000Z\r\n\
+             RRULE:FREQ=WEEKLY\r\nEND:VEVENT\r\n\
+             BEGIN:VEVENT\r\nUID:weekly\r\nSUMMARY:Demo\r\n\
+             RECURRENCE-ID:20261026T100000Z\r\n\
+             DTSTART:20261027T150000Z\r\nDTEND:20261027T160000Z\r\nEND:VEVENT\r\n\
+             BEGIN:VEVENT\r\nUID:cancelled\r\nSUMMARY:Demo\r\nSTATUS:CANCELLED\r\n\
+             DTSTART:20261020T100000Z\r\nDTEND:20261020T110000Z\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+
+        assert_eq!(events.len(), 2);
+        let occurs_at = |at| events.iter().any(|event| event.occurs_at(at));
+        assert!(!occurs_at(utc(2026, 10, 20, 10, 30)));
+        assert!(!occurs_at(utc(2026, 10, 26, 10, 30)));
+        assert!(occurs_at(utc(2026, 10, 27, 15, 30)));
+        assert!(occurs_at(utc(2026, 11, 2, 10, 30)));
+    }
+
+    #[test]
+    fn test_folded_lines_all_day_events_and_alarms() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:offsite\r\nSUMMARY:Team offsite\\, with \r\n \
+             PRESENTATION\r\nDTSTART;VALUE=DATE:20261021\r\n\
+             BEGIN:VALARM\r\nACTION:DISPLAY\r\nDESCRIPTION:Reminder\r\nEND:VALARM\r\n\
+             END:VEVENT\r\n",
+        ))
+        .unwrap();
+        let event = &events[0];
+
+        assert_eq!(event.summary, "Team offsite, with PRESENTATION");
+        assert_eq!(event.description, "");
+        assert_eq!(event.duration, Duration::days(1));
+        assert!(event.matches(&keywords(&["presentation"])));
+    }
+
+    #[test]
+    fn test_unsupported_rule_keeps_first_occurrence() {
+        let events = parse_calendar(&calendar(
+            "BEGIN:VEVENT\r\nUID:monthly\r\nSUMMARY:Demo\r\n\
+             DTSTART:20261005T100000Z\r\nDURATION:PT1H\r\n\
+             RRULE:FREQ=MONTHLY;BYDAY=1MO\r\nEND:VEVENT\r\n\
+             BEGIN:VEVENT\r\nUID:broken\r\nSUMMARY:Demo\r\nDTSTART:tomorrow\r\nEND:VEVENT\r\n",
+        ))
+        .unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert!(events[0].occurs_at(utc(2026, 10, 5, 10, 30)));
+        assert!(!events[0].occurs_at(utc(2026, 11, 2, 10, 30)));
+        assert!(parse_calendar("not a calendar").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT1H30M"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Ok(Duration::days(9)));
+        assert_eq!(parse_duration("-PT15M"), Ok(Duration::minutes(-15)));
+        assert!(parse_duration("PT1X").is_err());
+        assert!(parse_duration("1H").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let settings = CalendarSettings {
+            files: vec![PathBuf::new()],
+            keywords: vec![" ".to_string()],
+            action: CalendarAction::Relax { warning_secs: 0 },
+        };
+        let fields: Vec<String> = settings
+            .validate()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "calendar.files[0]",
+                "calendar.keywords",
+                "calendar.action.relax.warning_secs"
+            ]
+        );
+    }
+
+    /// A calendar with an event from an hour ago to an hour from now
+    fn current_event_calendar(summary: &str) -> String {
+        let now = Utc::now();
+        let format = "%Y%m%dT%H%M%SZ";
+        calendar(&format!(
+            "BEGIN:VEVENT\r\nUID:now\r\nSUMMARY:{}\r\nDTSTART:{}\r\nDTEND:{}\r\nEND:VEVENT\r\n",
+            summary,
+            (now - Duration::hours(1)).format(format),
+            (now + Duration::hours(1)).format(format)
+        ))
+    }
+
+    #[test]
+    fn test_monitor_reads_files_again_when_they_change() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work.ics");
+        fs::write(&path, current_event_calendar("Weekly sync")).unwrap();
+        let monitor = CalendarMonitor::new();
+        monitor.configure(Some(&CalendarSettings {
+            files: vec![path.clone()],
+            keywords: keywords(&["demo"]),
+            action: CalendarAction::Suppress,
+        }));
+        assert_eq!(monitor.current_event(Utc::now()), None);
+
+        fs::write(&path, current_event_calendar("Product demo")).unwrap();
+        std::thread::sleep(RELOAD_DEBOUNCE * 3);
+
+        assert_eq!(
+            monitor.current_event(Utc::now()),
+            Some(CalendarMatch {
+                summary: "Product demo".to_string(),
+                action: CalendarAction::Suppress,
+            })
+        );
+        monitor.configure(None);
+        assert_eq!(monitor.current_event(Utc::now()), None);
+    }
+}
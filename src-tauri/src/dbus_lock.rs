@@ -0,0 +1,216 @@
+use crate::logind;
+use log::{info, warn};
+use zbus::Connection;
+
+/// Screen saver services exposing a `Lock` method on the session bus, in the order they are tried.
+///
+/// Each entry is `(bus name, object path, interface)`.
+pub const SCREENSAVER_SERVICES: [(&str, &str, &str); 3] = [
+    (
+        "org.freedesktop.ScreenSaver",
+        "/org/freedesktop/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+    ),
+    (
+        "org.freedesktop.ScreenSaver",
+        "/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+    ),
+    (
+        "org.gnome.ScreenSaver",
+        "/org/gnome/ScreenSaver",
+        "org.gnome.ScreenSaver",
+    ),
+];
+
+/// Locks the graphical logind session of the current user (see
+/// [`logind::graphical_session_path`]) by calling `org.freedesktop.login1.Session.Lock` on the
+/// system bus.
+pub async fn lock_logind_session(system_bus: &Connection) -> Result<(), String> {
+    let path = logind::graphical_session_path(system_bus).await?;
+    let session = logind::session_proxy(system_bus, path.clone()).await?;
+    session
+        .lock()
+        .await
+        .map_err(|e| format!("Failed to lock logind session '{}': {}", path.as_str(), e))?;
+    info!("Locked logind session {}", path.as_str());
+    Ok(())
+}
+
+/// Unlocks the graphical logind session of the current user by calling
+/// `org.freedesktop.login1.Session.Unlock` on the system bus.
+pub async fn unlock_logind_session(system_bus: &Connection) -> Result<(), String> {
+    let path = logind::graphical_session_path(system_bus).await?;
+    let session = logind::session_proxy(system_bus, path.clone()).await?;
+    session
+        .unlock()
+        .await
+        .map_err(|e| format!("Failed to unlock logind session '{}': {}", path.as_str(), e))?;
+    info!("Unlocked logind session {}", path.as_str());
+    Ok(())
+}
+
+/// Locks every graphical logind session of the current user, returning how many were locked.
+///
+/// Fails only when no session could be locked; sessions failing to lock are logged.
+pub async fn lock_all_logind_sessions(system_bus: &Connection) -> Result<usize, String> {
+    let sessions = logind::user_sessions(system_bus).await?;
+    let mut locked = 0;
+    let mut errors = Vec::new();
+    for session in sessions.iter().filter(|session| session.is_graphical()) {
+        let result = async {
+            let proxy = logind::session_proxy(system_bus, session.path.clone()).await?;
+            proxy
+                .lock()
+                .await
+                .map_err(|e| format!("Failed to lock logind session '{}': {}", session.id, e))
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                info!("Locked logind session {}", session.path.as_str());
+                locked += 1;
+            }
+            Err(e) => {
+                warn!("{}", e);
+                errors.push(e);
+            }
+        }
+    }
+    if locked == 0 {
+        if errors.is_empty() {
+            return Err(
+                "Failed to lock logind sessions: no graphical session of the user".to_string(),
+            );
+        }
+        return Err(format!(
+            "Failed to lock logind sessions: {}",
+            errors.join("; ")
+        ));
+    }
+    Ok(locked)
+}
+
+/// Locks the screen through the first screen saver service on the session bus that accepts
+/// a `Lock` call (see [`SCREENSAVER_SERVICES`]).
+pub async fn lock_screensaver(session_bus: &Connection) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for (service, path, interface) in SCREENSAVER_SERVICES {
+        match call_lock(session_bus, service, path, interface).await {
+            Ok(()) => {
+                info!("Locked screen through {}", interface);
+                return Ok(());
+            }
+            Err(e) => errors.push(format!("{} at {}: {}", service, path, e)),
+        }
+    }
+    Err(format!(
+        "Failed to lock screen saver: {}",
+        errors.join("; ")
+    ))
+}
+
+/// Blanks the displays by activating the first screen saver service on the session bus that
+/// accepts a `SetActive` call (see [`SCREENSAVER_SERVICES`]).
+pub async fn blank_screen(session_bus: &Connection) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for (service, path, interface) in SCREENSAVER_SERVICES {
+        let result = async {
+            let proxy = zbus::Proxy::new(session_bus, service, path, interface).await?;
+            let activated: bool = proxy.call("SetActive", &(true,)).await?;
+            zbus::Result::Ok(activated)
+        }
+        .await;
+        match result {
+            Ok(true) => {
+                info!("Blanked screen through {}", interface);
+                return Ok(());
+            }
+            Ok(false) => errors.push(format!("{} at {}: not activated", service, path)),
+            Err(e) => errors.push(format!("{} at {}: {}", service, path, e)),
+        }
+    }
+    Err(format!("Failed to blank screen: {}", errors.join("; ")))
+}
+
+async fn call_lock(
+    connection: &Connection,
+    service: &str,
+    path: &str,
+    interface: &str,
+) -> zbus::Result<()> {
+    let proxy = zbus::Proxy::new(connection, service, path, interface).await?;
+    proxy.call_method("Lock", &()).await?;
+    Ok(())
+}
+
+/// Returns whether a bus name currently has an owner on the given bus
+pub async fn name_has_owner(connection: &Connection, name: &str) -> bool {
+    let Ok(name) = zbus::names::BusName::try_from(name) else {
+        return false;
+    };
+    match zbus::fdo::DBusProxy::new(connection).await {
+        Ok(dbus) => dbus.name_has_owner(name).await.unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// Returns whether logind is running on the given system bus
+pub async fn logind_available(system_bus: &Connection) -> bool {
+    name_has_owner(system_bus, "org.freedesktop.login1").await
+}
+
+/// Returns whether one of the screen saver services is running on the given session bus
+pub async fn screensaver_available(session_bus: &Connection) -> bool {
+    for (service, _, _) in SCREENSAVER_SERVICES {
+        if name_has_owner(session_bus, service).await {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screensaver_services_prefer_freedesktop() {
+        let (service, path, interface) = SCREENSAVER_SERVICES[0];
+        assert_eq!(service, "org.freedesktop.ScreenSaver");
+        assert_eq!(path, "/org/freedesktop/ScreenSaver");
+        assert_eq!(interface, "org.freedesktop.ScreenSaver");
+    }
+
+    #[test]
+    fn test_screensaver_services_include_gnome() {
+        assert!(SCREENSAVER_SERVICES
+            .iter()
+            .any(
+                |(service, path, interface)| *service == "org.gnome.ScreenSaver"
+                    && *path == "/org/gnome/ScreenSaver"
+                    && *interface == "org.gnome.ScreenSaver"
+            ));
+    }
+
+    #[test]
+    fn test_screensaver_services_are_well_formed() {
+        for (service, path, interface) in SCREENSAVER_SERVICES {
+            assert!(
+                service.contains('.'),
+                "Bus name should be qualified: {}",
+                service
+            );
+            assert!(
+                path.starts_with('/'),
+                "Object path should be absolute: {}",
+                path
+            );
+            assert!(
+                interface.contains('.'),
+                "Interface should be qualified: {}",
+                interface
+            );
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use serde::Serialize;
+
+/// Desktop environments and compositors with a known preferred way of locking
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Desktop {
+    Gnome,
+    Kde,
+    Cinnamon,
+    Mate,
+    Xfce,
+    Sway,
+    Hyprland,
+    I3,
+    Other,
+}
+
+impl Desktop {
+    /// Matches a desktop name as found in `XDG_CURRENT_DESKTOP` or `DESKTOP_SESSION`
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        let desktop = match name.as_str() {
+            "gnome" | "gnome-classic" | "gnome-xorg" | "gnome-wayland" | "ubuntu" | "pop" => {
+                Desktop::Gnome
+            }
+            "kde" | "plasma" | "plasmawayland" | "plasmax11" => Desktop::Kde,
+            "x-cinnamon" | "cinnamon" => Desktop::Cinnamon,
+            "mate" => Desktop::Mate,
+            "xfce" | "xfce4" => Desktop::Xfce,
+            "sway" => Desktop::Sway,
+            "hyprland" => Desktop::Hyprland,
+            "i3" | "i3wm" => Desktop::I3,
+            _ => return None,
+        };
+        Some(desktop)
+    }
+
+    /// Matches the name of a process that only runs in a given desktop session
+    fn from_process(process: &str) -> Option<Self> {
+        let desktop = match process {
+            "gnome-shell" => Desktop::Gnome,
+            "plasmashell" | "kwin_wayland" | "kwin_x11" => Desktop::Kde,
+            "cinnamon" => Desktop::Cinnamon,
+            "mate-session" => Desktop::Mate,
+            "xfce4-session" => Desktop::Xfce,
+            "sway" => Desktop::Sway,
+            "Hyprland" => Desktop::Hyprland,
+            "i3" => Desktop::I3,
+            _ => return None,
+        };
+        Some(desktop)
+    }
+}
+
+/// What is known about the graphical session the app runs in
+#[derive(Debug, Default, Clone)]
+pub struct SessionInfo {
+    /// Entries of `XDG_CURRENT_DESKTOP`
+    pub current_desktop: Vec<String>,
+    /// Value of `XDG_SESSION_TYPE`
+    pub session_type: Option<String>,
+    /// Whether `WAYLAND_DISPLAY` is set
+    pub wayland_display: bool,
+    /// Value of `DESKTOP_SESSION`
+    pub desktop_session: Option<String>,
+    /// Names of the running processes
+    pub processes: Vec<String>,
+}
+
+impl SessionInfo {
+    /// Inspects the environment and the running processes
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        SessionInfo {
+            current_desktop: var("XDG_CURRENT_DESKTOP")
+                .map(|value| value.split(':').map(str::to_string).collect())
+                .unwrap_or_default(),
+            session_type: var("XDG_SESSION_TYPE"),
+            wayland_display: var("WAYLAND_DISPLAY").is_some(),
+            desktop_session: var("DESKTOP_SESSION"),
+            processes: running_processes(),
+        }
+    }
+
+    /// Returns whether the session runs on Wayland
+    pub fn is_wayland(&self) -> bool {
+        self.wayland_display
+            || self
+                .session_type
+                .as_deref()
+                .is_some_and(|session_type| session_type.eq_ignore_ascii_case("wayland"))
+    }
+
+    /// Identifies the desktop, trusting `XDG_CURRENT_DESKTOP`, then `DESKTOP_SESSION`,
+    /// then the running processes
+    pub fn desktop(&self) -> Desktop {
+        self.current_desktop
+            .iter()
+            .find_map(|name| Desktop::from_name(name))
+            .or_else(|| {
+                self.desktop_session.as_deref().and_then(|session| {
+                    // DESKTOP_SESSION may be a path to the session file
+                    let name = session.rsplit('/').next().unwrap_or(session);
+                    Desktop::from_name(name)
+                })
+            })
+            .or_else(|| {
+                self.processes
+                    .iter()
+                    .find_map(|process| Desktop::from_process(process))
+            })
+            .unwrap_or(Desktop::Other)
+    }
+}
+
+/// Lists the names of the running processes
+#[cfg(target_os = "linux")]
+fn running_processes() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim().to_string())
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn running_processes() -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(current_desktop: &str) -> SessionInfo {
+        SessionInfo {
+            current_desktop: current_desktop
+                .split(':')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_desktop_from_current_desktop() {
+        assert_eq!(session("GNOME").desktop(), Desktop::Gnome);
+        assert_eq!(session("ubuntu:GNOME").desktop(), Desktop::Gnome);
+        assert_eq!(session("KDE").desktop(), Desktop::Kde);
+        assert_eq!(session("X-Cinnamon").desktop(), Desktop::Cinnamon);
+        assert_eq!(session("MATE").desktop(), Desktop::Mate);
+        assert_eq!(session("XFCE").desktop(), Desktop::Xfce);
+        assert_eq!(session("sway").desktop(), Desktop::Sway);
+        assert_eq!(session("Hyprland").desktop(), Desktop::Hyprland);
+        assert_eq!(session("i3").desktop(), Desktop::I3);
+        assert_eq!(session("Unknown").desktop(), Desktop::Other);
+    }
+
+    #[test]
+    fn test_desktop_from_desktop_session() {
+        let info = SessionInfo {
+            desktop_session: Some("/usr/share/xsessions/plasma".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(info.desktop(), Desktop::Kde);
+    }
+
+    #[test]
+    fn test_desktop_from_processes() {
+        let info = SessionInfo {
+            processes: vec!["systemd".to_string(), "Hyprland".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(info.desktop(), Desktop::Hyprland);
+    }
+
+    #[test]
+    fn test_current_desktop_takes_precedence() {
+        let info = SessionInfo {
+            current_desktop: vec!["sway".to_string()],
+            desktop_session: Some("gnome".to_string()),
+            processes: vec!["gnome-shell".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(info.desktop(), Desktop::Sway);
+    }
+
+    #[test]
+    fn test_is_wayland() {
+        assert!(!SessionInfo::default().is_wayland());
+
+        let info = SessionInfo {
+            session_type: Some("wayland".to_string()),
+            ..Default::default()
+        };
+        assert!(info.is_wayland());
+
+        let info = SessionInfo {
+            session_type: Some("x11".to_string()),
+            wayland_display: true,
+            ..Default::default()
+        };
+        assert!(info.is_wayland());
+    }
+}
@@ -0,0 +1,151 @@
+use log::error;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// Watches a file for edits, such as the settings file edited outside the app.
+///
+/// The directory of the file is watched rather than the file itself, as editors and
+/// [`crate::read_write_settings::Settings::save`] replace the file by renaming a new one over
+/// it. The watch stops when the watcher is dropped.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    /// Starts watching the file, calling `on_change` once changes have settled for `debounce`.
+    /// The directory of the file must exist.
+    pub fn start<F>(path: &Path, debounce: Duration, on_change: F) -> Result<Self, String>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let dir = path
+            .parent()
+            .ok_or_else(|| format!("Failed to watch '{}': no parent directory", path.display()))?;
+        if !dir.is_dir() {
+            return Err(format!(
+                "Failed to watch '{}': directory '{}' does not exist",
+                path.display(),
+                dir.display()
+            ));
+        }
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch '{}': {}", dir.display(), e))?;
+
+        let path = path.to_path_buf();
+        std::thread::spawn(move || {
+            // Stops once the watcher, and with it the sender, is dropped
+            while let Ok(event) = receiver.recv() {
+                if !concerns(&event, &path) {
+                    continue;
+                }
+                loop {
+                    match receiver.recv_timeout(debounce) {
+                        Ok(_) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                on_change();
+            }
+        });
+        Ok(FileWatcher { _watcher: watcher })
+    }
+}
+
+/// Returns whether a watcher event may have changed the content of the file
+fn concerns(event: &notify::Result<notify::Event>, path: &Path) -> bool {
+    match event {
+        // Only the directory of the file is watched, and not recursively
+        Ok(event) => {
+            !matches!(event.kind, EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == path.file_name())
+        }
+        Err(e) => {
+            error!("File watcher error: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    fn start_counting(path: &Path) -> (FileWatcher, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let watcher = FileWatcher::start(path, DEBOUNCE, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+        (watcher, count)
+    }
+
+    #[test]
+    fn test_burst_of_writes_reloads_once() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let (_watcher, count) = start_counting(&path);
+
+        for i in 0..5 {
+            fs::write(&path, format!("{{\"rssi_delta_max\": {}}}", i)).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::thread::sleep(DEBOUNCE * 4);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_replacing_file_by_rename_reloads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{}").unwrap();
+        let (_watcher, count) = start_counting(&path);
+
+        let temporary = dir.path().join("settings.json.tmp");
+        fs::write(&temporary, "{\"dry_run\": true}").unwrap();
+        fs::rename(&temporary, &path).unwrap();
+        std::thread::sleep(DEBOUNCE * 4);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_other_files_ignored() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let (_watcher, count) = start_counting(&path);
+
+        fs::write(dir.path().join("lock_history.jsonl"), "{}").unwrap();
+        std::thread::sleep(DEBOUNCE * 4);
+
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_missing_directory_is_not_created() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let result = FileWatcher::start(&missing.join("work.ics"), DEBOUNCE, || {});
+
+        assert!(result.err().unwrap().contains("does not exist"));
+        assert!(!missing.exists());
+    }
+}
@@ -0,0 +1,340 @@
+use crate::lock_pipeline::LockContext;
+use crate::read_write_settings::find_executable;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::time::{timeout, timeout_at, Duration, Instant};
+
+/// How long a hook may run when it has no timeout of its own
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest timeout a hook may set
+pub const MAX_HOOK_TIMEOUT_SECS: u64 = 60;
+
+/// A user script run when the user walks away or comes back, without a shell
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hook {
+    /// Program followed by its arguments
+    pub argv: Vec<String>,
+    /// Extra environment variables set for the hook
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// How long the hook may run before being killed
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+impl Hook {
+    /// Checks that the hook is runnable, returning the resolved executable path
+    pub fn validate(&self) -> Result<PathBuf, String> {
+        let program = match self.argv.first() {
+            Some(program) if !program.trim().is_empty() => program,
+            _ => return Err("Hook command is empty".to_string()),
+        };
+        if let Some(timeout_secs) = self.timeout_secs {
+            if !(1..=MAX_HOOK_TIMEOUT_SECS).contains(&timeout_secs) {
+                return Err(format!(
+                    "Hook timeout must be between 1 and {} seconds",
+                    MAX_HOOK_TIMEOUT_SECS
+                ));
+            }
+        }
+        find_executable(program).ok_or_else(|| format!("Hook executable '{}' not found", program))
+    }
+}
+
+/// When a hook runs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// Right before the screen is locked
+    PreLock,
+    /// When the device comes back after an automatic lock
+    Return,
+}
+
+/// How a hook run ended
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HookResult {
+    pub argv: Vec<String>,
+    /// Exit code, missing when the hook did not start, timed out or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Name of a unit enum variant as serialized
+fn serialized_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Environment variables describing the event to the hooks
+pub fn hook_env(event: HookEvent, context: &LockContext) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    env.insert("LOCK_4_ME_EVENT".to_string(), serialized_name(&event));
+    env.insert(
+        "LOCK_4_ME_REASON".to_string(),
+        serialized_name(&context.reason),
+    );
+    if let Some(device_id) = &context.device_id {
+        env.insert("LOCK_4_ME_DEVICE_ID".to_string(), device_id.clone());
+    }
+    let readings = [
+        ("LOCK_4_ME_RSSI", context.rssi),
+        ("LOCK_4_ME_FILTERED_RSSI", context.filtered_rssi),
+        ("LOCK_4_ME_BASELINE_RSSI", context.baseline_rssi),
+        ("LOCK_4_ME_DIFF_RSSI", context.diff_rssi),
+        ("LOCK_4_ME_RSSI_DELTA_MAX", context.rssi_delta_max),
+    ];
+    for (key, value) in readings {
+        if let Some(value) = value {
+            env.insert(key.to_string(), value.to_string());
+        }
+    }
+    env
+}
+
+/// Runs a hook, killing it if it outlives its timeout
+pub async fn run_hook(hook: &Hook, event: HookEvent, context: &LockContext) -> HookResult {
+    let failed = |error: String| HookResult {
+        argv: hook.argv.clone(),
+        exit_code: None,
+        success: false,
+        error: Some(error),
+    };
+    let program = match hook.validate() {
+        Ok(program) => program,
+        Err(e) => return failed(e),
+    };
+    let max_wait = hook
+        .timeout_secs
+        .map(|timeout_secs| Duration::from_secs(timeout_secs.min(MAX_HOOK_TIMEOUT_SECS)))
+        .unwrap_or(HOOK_TIMEOUT);
+
+    let mut child = match tokio::process::Command::new(&program)
+        .args(&hook.argv[1..])
+        .envs(hook_env(event, context))
+        .envs(&hook.env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            return failed(format!(
+                "Failed to start hook '{}': {}",
+                program.display(),
+                e
+            ))
+        }
+    };
+
+    match timeout(max_wait, child.wait()).await {
+        Ok(Ok(status)) => HookResult {
+            argv: hook.argv.clone(),
+            exit_code: status.code(),
+            success: status.success(),
+            error: (!status.success())
+                .then(|| format!("Hook '{}' failed: {}", program.display(), status)),
+        },
+        Ok(Err(e)) => failed(format!(
+            "Failed to wait for hook '{}': {}",
+            program.display(),
+            e
+        )),
+        Err(_) => {
+            let _ = child.kill().await;
+            failed(format!(
+                "Hook '{}' timed out after {:?}",
+                program.display(),
+                max_wait
+            ))
+        }
+    }
+}
+
+/// Runs hooks one after the other, logging the failed ones
+pub async fn run_hooks(hooks: &[Hook], event: HookEvent, context: &LockContext) -> Vec<HookResult> {
+    let mut results = Vec::new();
+    for hook in hooks {
+        let result = run_hook(hook, event, context).await;
+        log_result(event, &result);
+        results.push(result);
+    }
+    results
+}
+
+/// Runs hooks one after the other like [`run_hooks`], all of them within `budget`: the hook
+/// running when it is used up is killed and the remaining ones are not started
+pub async fn run_hooks_within(
+    hooks: &[Hook],
+    event: HookEvent,
+    context: &LockContext,
+    budget: Duration,
+) -> Vec<HookResult> {
+    let deadline = Instant::now() + budget;
+    let mut results = Vec::new();
+    for hook in hooks {
+        let finished = if Instant::now() < deadline {
+            timeout_at(deadline, run_hook(hook, event, context))
+                .await
+                .ok()
+        } else {
+            None
+        };
+        let result = finished.unwrap_or_else(|| HookResult {
+            argv: hook.argv.clone(),
+            exit_code: None,
+            success: false,
+            error: Some(format!(
+                "Hook {:?} did not finish within the {:?} budget of the {:?} hooks",
+                hook.argv, budget, event
+            )),
+        });
+        log_result(event, &result);
+        results.push(result);
+    }
+    results
+}
+
+fn log_result(event: HookEvent, result: &HookResult) {
+    match &result.error {
+        Some(e) => warn!("{}", e),
+        None => info!("Ran {:?} hook {:?}", event, result.argv),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pipeline::LockReason;
+
+    fn hook(argv: &[&str]) -> Hook {
+        Hook {
+            argv: argv.iter().map(|arg| arg.to_string()).collect(),
+            env: BTreeMap::new(),
+            timeout_secs: None,
+        }
+    }
+
+    fn context() -> LockContext {
+        LockContext {
+            device_id: Some("test-device-123".to_string()),
+            rssi: Some(-80),
+            diff_rssi: Some(-30),
+            ..LockContext::new(LockReason::OverDelta)
+        }
+    }
+
+    #[test]
+    fn test_hook_env_describes_event() {
+        let env = hook_env(HookEvent::PreLock, &context());
+        assert_eq!(env["LOCK_4_ME_EVENT"], "pre_lock");
+        assert_eq!(env["LOCK_4_ME_REASON"], "over_delta");
+        assert_eq!(env["LOCK_4_ME_DEVICE_ID"], "test-device-123");
+        assert_eq!(env["LOCK_4_ME_RSSI"], "-80");
+        assert_eq!(env["LOCK_4_ME_DIFF_RSSI"], "-30");
+        assert!(!env.contains_key("LOCK_4_ME_BASELINE_RSSI"));
+    }
+
+    #[test]
+    fn test_hook_validate() {
+        assert!(hook(&[]).validate().unwrap_err().contains("empty"));
+        assert!(hook(&["lock-4-me-missing-hook"])
+            .validate()
+            .unwrap_err()
+            .contains("not found"));
+        let mut zero_timeout = hook(&["true"]);
+        zero_timeout.timeout_secs = Some(0);
+        assert!(zero_timeout.validate().is_err());
+        let mut long_timeout = hook(&["true"]);
+        long_timeout.timeout_secs = Some(MAX_HOOK_TIMEOUT_SECS + 1);
+        assert!(long_timeout
+            .validate()
+            .unwrap_err()
+            .contains("between 1 and 60"));
+        long_timeout.timeout_secs = Some(MAX_HOOK_TIMEOUT_SECS);
+        assert!(long_timeout.validate().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_passes_event_environment() {
+        let mut hook = hook(&[
+            "sh",
+            "-c",
+            r#"test "$LOCK_4_ME_EVENT" = return && test "$LOCK_4_ME_RSSI" = -80 && test "$EXTRA" = 1"#,
+        ]);
+        hook.env.insert("EXTRA".to_string(), "1".to_string());
+
+        let result = run_hook(&hook, HookEvent::Return, &context()).await;
+
+        assert!(result.success, "Hook should succeed: {:?}", result);
+        assert_eq!(result.exit_code, Some(0));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_captures_exit_code() {
+        let result = run_hook(
+            &hook(&["sh", "-c", "exit 3"]),
+            HookEvent::PreLock,
+            &context(),
+        )
+        .await;
+
+        assert!(!result.success);
+        assert_eq!(result.exit_code, Some(3));
+        assert!(result.error.unwrap().contains("failed"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_kills_on_timeout() {
+        let mut hook = hook(&["sleep", "10"]);
+        hook.timeout_secs = Some(1);
+
+        let result = run_hook(&hook, HookEvent::PreLock, &context()).await;
+
+        assert!(!result.success);
+        assert!(result.exit_code.is_none());
+        assert!(result.error.unwrap().contains("timed out"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hooks_within_budget() {
+        let hooks = [hook(&["true"]), hook(&["sleep", "10"]), hook(&["true"])];
+        let started = Instant::now();
+
+        let results = run_hooks_within(
+            &hooks,
+            HookEvent::PreLock,
+            &context(),
+            Duration::from_secs(1),
+        )
+        .await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let success: Vec<bool> = results.iter().map(|result| result.success).collect();
+        assert_eq!(success, vec![true, false, false]);
+        assert!(results[1].error.as_ref().unwrap().contains("budget"));
+        assert!(results[2].exit_code.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_hooks_keeps_going_after_failure() {
+        let hooks = [
+            hook(&["lock-4-me-missing-hook"]),
+            hook(&["lock-4-me-other"]),
+        ];
+        let results = run_hooks(&hooks, HookEvent::PreLock, &context()).await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| !result.success));
+    }
+}
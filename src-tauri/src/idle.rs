@@ -0,0 +1,135 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reads how long the session has gone without user input.
+///
+/// The screen saver `GetSessionIdleTime` method is preferred as it counts from the last input.
+/// logind only reports the session as idle once the desktop considers it idle, so with logind
+/// alone the session reads as active until the desktop idle delay has passed.
+///
+/// Buses that are not given are connected to when the idle time is read.
+#[derive(Clone, Default)]
+pub struct IdleMonitor {
+    #[cfg(target_os = "linux")]
+    system_bus: Option<zbus::Connection>,
+    #[cfg(target_os = "linux")]
+    session_bus: Option<zbus::Connection>,
+}
+
+impl IdleMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a monitor reading idle time from the given buses
+    #[cfg(target_os = "linux")]
+    pub fn with_connections(
+        system_bus: Option<zbus::Connection>,
+        session_bus: Option<zbus::Connection>,
+    ) -> Self {
+        IdleMonitor {
+            system_bus,
+            session_bus,
+        }
+    }
+
+    /// Returns how many seconds the session has been idle
+    #[cfg(target_os = "linux")]
+    pub async fn idle_secs(&self) -> Result<u64, String> {
+        let mut errors = Vec::new();
+        let session_bus = match &self.session_bus {
+            Some(session_bus) => Ok(session_bus.clone()),
+            None => zbus::Connection::session().await,
+        };
+        match session_bus {
+            Ok(session_bus) => match screensaver_idle_secs(&session_bus).await {
+                Ok(idle_secs) => return Ok(idle_secs),
+                Err(e) => errors.push(e),
+            },
+            Err(e) => errors.push(format!("Failed to connect to session bus: {}", e)),
+        }
+        let system_bus = match &self.system_bus {
+            Some(system_bus) => Ok(system_bus.clone()),
+            None => zbus::Connection::system().await,
+        };
+        match system_bus {
+            Ok(system_bus) => match logind_idle_secs(&system_bus).await {
+                Ok(idle_secs) => return Ok(idle_secs),
+                Err(e) => errors.push(e),
+            },
+            Err(e) => errors.push(format!("Failed to connect to system bus: {}", e)),
+        }
+        Err(format!("Failed to read idle time: {}", errors.join("; ")))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub async fn idle_secs(&self) -> Result<u64, String> {
+        Err("Reading idle time is not supported on this platform".to_string())
+    }
+}
+
+/// Reads the idle time from the first screen saver service implementing `GetSessionIdleTime`
+#[cfg(target_os = "linux")]
+pub async fn screensaver_idle_secs(session_bus: &zbus::Connection) -> Result<u64, String> {
+    let mut errors = Vec::new();
+    for (service, path, interface) in crate::dbus_lock::SCREENSAVER_SERVICES {
+        let result = async {
+            let proxy = zbus::Proxy::new(session_bus, service, path, interface).await?;
+            let idle_secs: u32 = proxy.call("GetSessionIdleTime", &()).await?;
+            zbus::Result::Ok(idle_secs)
+        }
+        .await;
+        match result {
+            Ok(idle_secs) => return Ok(u64::from(idle_secs)),
+            Err(e) => errors.push(format!("{} at {}: {}", service, path, e)),
+        }
+    }
+    Err(format!(
+        "Failed to read screen saver idle time: {}",
+        errors.join("; ")
+    ))
+}
+
+/// Reads the idle time from the `IdleHint` and `IdleSinceHint` of the graphical logind session
+#[cfg(target_os = "linux")]
+pub async fn logind_idle_secs(system_bus: &zbus::Connection) -> Result<u64, String> {
+    use crate::logind;
+
+    let path = logind::graphical_session_path(system_bus).await?;
+    let session = logind::session_proxy(system_bus, path).await?;
+    let idle = session
+        .idle_hint()
+        .await
+        .map_err(|e| format!("Failed to read logind idle hint: {}", e))?;
+    if !idle {
+        return Ok(0);
+    }
+    let since_usec = session
+        .idle_since_hint()
+        .await
+        .map_err(|e| format!("Failed to read logind idle hint: {}", e))?;
+    Ok(idle_secs_since(since_usec, SystemTime::now()))
+}
+
+/// Seconds elapsed between a wall-clock timestamp in microseconds and `now`
+pub fn idle_secs_since(since_usec: u64, now: SystemTime) -> u64 {
+    let since = UNIX_EPOCH + Duration::from_micros(since_usec);
+    now.duration_since(since).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_secs_since() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        assert_eq!(idle_secs_since(940_000_000, now), 60);
+        assert_eq!(idle_secs_since(1_000_000_000, now), 0);
+    }
+
+    #[test]
+    fn test_idle_secs_since_future_timestamp() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        assert_eq!(idle_secs_since(2_000_000_000, now), 0);
+    }
+}
@@ -1,15 +1,43 @@
-use listen_bluetooth::BluetoothListenerHandle;
-use log::error;
+use listen_bluetooth::{BluetoothListenerHandle, ListenerStatusState};
+use lock_audit::LockAuditLog;
+use lock_pipeline::LockPipeline;
+use lock_state::LockStateMonitor;
+use read_write_settings::{Settings, SettingsChange, SettingsStore};
+use sleep::SleepMonitor;
+use log::{error, info, warn};
 use std::sync::Mutex;
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{Menu, MenuItem, PredefinedMenuItem},
     tray::{MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    Builder, Manager, WindowEvent,
+    Builder, Listener, Manager, WindowEvent, Wry,
 };
-use tauri_plugin_log;
+pub mod auto_unlock;
+pub mod away_actions;
+pub mod away_hygiene;
+pub mod calendar;
+#[cfg(target_os = "linux")]
+pub mod dbus_lock;
+pub mod desktop_env;
+pub mod file_watcher;
+pub mod hooks;
+pub mod idle;
 pub mod listen_bluetooth;
+pub mod lock_audit;
+pub mod lock_methods;
+pub mod lock_pipeline;
 pub mod lock_screen;
+pub mod lock_state;
+#[cfg(target_os = "linux")]
+pub mod logind;
+#[cfg(target_os = "linux")]
+pub mod notifications;
+pub mod profiles;
 pub mod read_write_settings;
+pub mod schedule;
+pub mod settings_bundle;
+pub mod settings_migration;
+pub mod settings_watcher;
+pub mod sleep;
 
 /// Handles showing or hiding the main window
 fn toggle_window_visibility(window: &tauri::WebviewWindow) -> Result<(), String> {
@@ -47,8 +75,33 @@ fn toggle_window_visibility(window: &tauri::WebviewWindow) -> Result<(), String>
     }
 }
 
+/// Id of the system tray icon
+const TRAY_ID: &str = "main";
+
+/// Builds the tray menu, with a submenu to switch profile when there are profiles
+fn tray_menu(app: &tauri::AppHandle, settings: &Settings) -> Result<Menu<Wry>, tauri::Error> {
+    let stop_i = MenuItem::with_id(app, "stop", "Stop", true, None::<&str>)?;
+    match profiles::profile_menu(app, settings)? {
+        Some(profile_menu) => {
+            let separator = PredefinedMenuItem::separator(app)?;
+            Menu::with_items(app, &[&profile_menu, &separator, &stop_i])
+        }
+        None => Menu::with_items(app, &[&stop_i]),
+    }
+}
+
+/// Rebuilds the tray menu when the profiles change
+fn refresh_tray_menu(app: &tauri::AppHandle, settings: &Settings) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    if let Err(e) = tray_menu(app, settings).and_then(|menu| tray.set_menu(Some(menu))) {
+        error!("Failed to update tray menu: {}", e);
+    }
+}
+
 /// Configures the system tray icon
-fn setup_tray(app: &tauri::AppHandle) -> Result<(), tauri::Error> {
+fn setup_tray(app: &tauri::AppHandle, settings: &Settings) -> Result<(), tauri::Error> {
     // Get the default icon
     let icon = app
         .default_window_icon()
@@ -61,11 +114,10 @@ fn setup_tray(app: &tauri::AppHandle) -> Result<(), tauri::Error> {
         .clone();
 
     // Create menu items
-    let stop_i = MenuItem::with_id(app, "stop", "Stop", true, None::<&str>)?;
-    let menu = Menu::with_items(app, &[&stop_i])?;
+    let menu = tray_menu(app, settings)?;
 
     // Configure the system tray icon
-    TrayIconBuilder::new()
+    TrayIconBuilder::with_id(TRAY_ID)
         .icon(icon)
         .on_tray_icon_event(|tray, event| {
             if let TrayIconEvent::Click { button_state, .. } = event {
@@ -79,11 +131,12 @@ fn setup_tray(app: &tauri::AppHandle) -> Result<(), tauri::Error> {
                 }
             }
         })
-        .on_menu_event(|app, event| match event.id.as_ref() {
-            "stop" => {
+        .on_menu_event(|app, event| {
+            if event.id.as_ref() == "stop" {
                 app.exit(0);
+            } else {
+                profiles::handle_menu_event(app, event.id.as_ref());
             }
-            _ => {}
         })
         .menu(&menu)
         .show_menu_on_left_click(false)
@@ -95,6 +148,21 @@ fn setup_tray(app: &tauri::AppHandle) -> Result<(), tauri::Error> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let context = tauri::generate_context!();
+
+    // Export or import a settings bundle without starting the app
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match settings_bundle::CliCommand::parse(&args) {
+        Some(Ok(command)) => std::process::exit(command.run(&context.config().identifier)),
+        Some(Err(e)) => {
+            eprintln!("{}\n{}", e, settings_bundle::CliCommand::USAGE);
+            std::process::exit(2);
+        }
+        None => {}
+    }
+
+    let forced_dry_run = std::env::args().any(|arg| arg == lock_pipeline::DRY_RUN_FLAG);
+
     Builder::default()
         .plugin(
             tauri_plugin_log::Builder::new()
@@ -111,7 +179,8 @@ pub fn run() {
         )
         .plugin(tauri_plugin_opener::init())
         .manage(BluetoothListenerHandle(Mutex::new(None)))
-        .setup(|app| {
+        .manage(ListenerStatusState::default())
+        .setup(move |app| {
             // Use accessory on macos to prevent the app from being shown in the dock
             #[cfg(target_os = "macos")]
             app.set_activation_policy(tauri::ActivationPolicy::Accessory);
@@ -132,18 +201,103 @@ pub fn run() {
                 }
             });
 
-            // Set up system tray icon with menu
-            setup_tray(&app.app_handle())?;
+            // Keep the settings in the app config directory, importing those saved by earlier
+            // versions in the app data directory
+            let settings_store = SettingsStore::in_dir(
+                &app.path().app_config_dir()?,
+                Some(&app.path().app_data_dir()?.join(read_write_settings::SETTINGS_FILE)),
+            );
+            info!("Settings stored in '{}'", settings_store.path().display());
+            let settings = settings_store.get();
+            let settings_path = settings_store.path().to_path_buf();
+            app.manage(settings_store);
+
+            // Set up system tray icon with menu, kept up to date with the profiles
+            setup_tray(app.app_handle(), &settings)?;
+            let app_handle = app.app_handle().clone();
+            app.listen_any(read_write_settings::SETTINGS_CHANGED_EVENT, move |event| {
+                match serde_json::from_str::<SettingsChange>(event.payload()) {
+                    Ok(change) if change.touches(&profiles::TRAY_KEYS) => {
+                        refresh_tray_menu(&app_handle, &change.settings)
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to read settings change: {}", e),
+                }
+            });
+
+            // Track the session lock state and forward its changes to the frontend
+            let lock_state = LockStateMonitor::new();
+            app.manage(lock_state.clone());
+            tauri::async_runtime::spawn(lock_state::forward_lock_state_events(
+                app.app_handle().clone(),
+                lock_state.subscribe(),
+            ));
+
+            // Take lock decisions through a single pipeline, simulating them in dry-run mode
+            let audit_log = LockAuditLog::in_dir(&app.path().app_data_dir()?);
+            app.manage(audit_log.clone());
+            let pipeline =
+                LockPipeline::new(lock_state.clone(), forced_dry_run).with_audit_log(audit_log);
+            pipeline.apply_settings(&settings);
+            if forced_dry_run {
+                info!("Dry-run mode forced by {}", lock_pipeline::DRY_RUN_FLAG);
+            }
+            app.manage(pipeline.clone());
+            tauri::async_runtime::spawn(lock_pipeline::forward_lock_pipeline_events(
+                app.app_handle().clone(),
+                pipeline.subscribe(),
+            ));
+            tauri::async_runtime::spawn(async move { lock_state.start().await });
+
+            // Apply edits made to the settings file while the app runs
+            match settings_watcher::watch_settings(app.app_handle().clone(), &settings_path) {
+                Ok(watcher) => {
+                    app.manage(watcher);
+                }
+                Err(e) => warn!("Settings file edits will apply after a restart: {}", e),
+            }
+
+            // Track suspend and resume so the listener can stop scanning across sleep
+            let sleep_monitor = SleepMonitor::new();
+            app.manage(sleep_monitor.clone());
+            tauri::async_runtime::spawn(async move { sleep_monitor.start().await });
+
+            // Show a desktop notification, with a cancel action, while a lock is pending
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(async move {
+                let result = match zbus::Connection::session().await {
+                    Ok(session_bus) => {
+                        notifications::watch_pending_locks(&session_bus, pipeline).await
+                    }
+                    Err(e) => Err(format!("Failed to connect to session bus: {}", e)),
+                };
+                if let Err(e) = result {
+                    warn!("Lock notifications unavailable: {}", e);
+                }
+            });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             listen_bluetooth::listen_bluetooth,
-            read_write_settings::read_settings,
-            read_write_settings::write_settings,
+            listen_bluetooth::get_listener_status,
+            read_write_settings::get_settings,
+            read_write_settings::update_settings,
+            profiles::create_profile,
+            profiles::rename_profile,
+            profiles::delete_profile,
+            profiles::switch_profile,
+            settings_bundle::export_settings,
+            settings_bundle::import_settings,
             lock_screen::lock_screen,
+            lock_screen::test_lock_command,
+            lock_methods::list_lock_methods,
+            lock_state::is_session_locked,
+            lock_pipeline::is_dry_run,
+            lock_pipeline::cancel_pending_lock,
+            lock_audit::query_lock_history,
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
 
@@ -229,10 +383,7 @@ mod tests {
         let test_events = ["stop", "unknown", "other"];
         
         for event_id in test_events {
-            let should_exit = match event_id {
-                "stop" => true,
-                _ => false,
-            };
+            let should_exit = matches!(event_id, "stop");
             
             if event_id == "stop" {
                 assert!(should_exit, "Stop event should trigger exit");
@@ -281,10 +432,10 @@ mod tests {
         
         // Accessory policy prevents app from showing in dock
         // This is the expected behavior for our system tray app
-        match policy {
-            ActivationPolicy::Accessory => assert!(true, "Correct policy for system tray app"),
-            _ => panic!("Should use Accessory policy on macOS"),
-        }
+        assert!(
+            matches!(policy, ActivationPolicy::Accessory),
+            "Should use Accessory policy on macOS"
+        );
     }
 
     #[test]
@@ -292,9 +443,22 @@ mod tests {
         // Test that we have the expected commands registered
         let expected_commands = [
             "listen_bluetooth",
-            "read_settings", 
-            "write_settings",
+            "get_settings",
+            "update_settings",
+            "create_profile",
+            "rename_profile",
+            "delete_profile",
+            "switch_profile",
+            "export_settings",
+            "import_settings",
             "lock_screen",
+            "test_lock_command",
+            "list_lock_methods",
+            "is_session_locked",
+            "is_dry_run",
+            "cancel_pending_lock",
+            "query_lock_history",
+            "get_listener_status",
         ];
         
         // In a real test, we'd verify these are actually registered
@@ -321,10 +485,10 @@ mod tests {
         
         // Test conversion to tauri::Error
         let tauri_error = tauri::Error::Io(io_error);
-        match tauri_error {
-            tauri::Error::Io(_) => assert!(true, "Correctly converted to Tauri IO error"),
-            _ => panic!("Should be an IO error"),
-        }
+        assert!(
+            matches!(tauri_error, tauri::Error::Io(_)),
+            "Should be an IO error"
+        );
     }
 
     #[test]
@@ -1,14 +1,22 @@
+use crate::lock_audit::now_ms;
+use crate::lock_pipeline::{LockContext, LockPipeline, LockReason};
+use crate::lock_state::{LockStateEvent, LockStateMonitor};
+use crate::read_write_settings::{Settings, SettingsStore};
+use crate::schedule::ScheduleState;
+use crate::sleep::{SleepEvent, SleepMonitor, RESUME_GRACE};
 use bt_discover::*;
 use futures::stream::StreamExt;
 use futures::Stream;
-use log::{error, info};
+use log::{error, info, warn};
+use serde::Serialize;
 use serde_json::json;
 use std::fmt;
 use std::pin::Pin;
 use std::sync::Mutex;
-use tauri::{AppHandle, Emitter, State};
-use tokio::task::JoinHandle;
-use tokio::time::{timeout, Duration};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration, Instant};
 use uuid::Uuid;
 
 // Custom error structure
@@ -38,32 +46,186 @@ impl From<uuid::Error> for BluetoothError {
 // This is a wrapper around a Mutex to allow for the Bluetooth listener to be stopped
 pub struct BluetoothListenerHandle(pub Mutex<Option<JoinHandle<()>>>);
 
+impl BluetoothListenerHandle {
+    /// Settings the listener is started with
+    pub const SETTINGS_KEYS: [&'static str; 5] = [
+        "target_uuid",
+        "rssi_delta_max",
+        "resume_grace_secs",
+        "unlock_grace_secs",
+        "recompute_baseline_on_unlock",
+    ];
+
+    /// Starts the listener with the given settings, stopping the running one first
+    pub fn start(&self, app_handle: AppHandle, settings: &Settings) -> Result<(), String> {
+        // Stop previous listener if any
+        if let Some(handle) = self.0.lock().unwrap().take() {
+            handle.abort();
+        }
+
+        let target_uuid = match settings.target_uuid.as_str() {
+            "" => None,
+            uuid_str => Some(Uuid::parse_str(uuid_str).map_err(|e| e.to_string())?),
+        };
+        // The RSSI threshold only applies to a target device
+        let rssi_delta_max = target_uuid.map(|_| settings.rssi_delta_max);
+        let resume_grace = settings
+            .resume_grace_secs
+            .map(Duration::from_secs)
+            .unwrap_or(RESUME_GRACE);
+        let unlock_grace = settings
+            .unlock_grace_secs
+            .map(Duration::from_secs)
+            .unwrap_or(UNLOCK_GRACE);
+        let recompute_baseline_on_unlock = settings.recompute_baseline_on_unlock;
+
+        *app_handle.state::<ListenerStatusState>().0.lock().unwrap() = ListenerStatus {
+            target_uuid: target_uuid.map(|uuid| uuid.to_string()),
+            armed: true,
+            ..Default::default()
+        };
+
+        // Spawn the new listener task
+        let handle = tauri::async_runtime::spawn(async move {
+            run_bluetooth_listener(
+                app_handle.clone(),
+                target_uuid,
+                rssi_delta_max,
+                resume_grace,
+                unlock_grace,
+                recompute_baseline_on_unlock,
+            )
+            .await
+        });
+
+        // Store the new handle
+        *self.0.lock().unwrap() = Some(handle);
+
+        Ok(())
+    }
+
+    /// Starts the listener again with the given settings, if it is running
+    pub fn restart(&self, app_handle: AppHandle, settings: &Settings) -> Result<(), String> {
+        if self.0.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        self.start(app_handle, settings)
+    }
+}
+
+/// Grace period after an unlock before the listener locks again, unless configured
+pub const UNLOCK_GRACE: Duration = Duration::from_secs(10);
+
+/// The last time the listener was re-armed after an unlock
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RearmRecord {
+    pub timestamp_ms: u64,
+    pub grace_secs: u64,
+    pub recompute_baseline: bool,
+    /// Baseline recomputed from the readings taken during the grace period
+    pub baseline_rssi: Option<i16>,
+}
+
+/// What the listener is currently doing, as reported by `get_listener_status`
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ListenerStatus {
+    pub target_uuid: Option<String>,
+    /// Whether the listener may lock, `false` during the grace period after an unlock
+    pub armed: bool,
+    pub baseline_rssi: Option<i16>,
+    pub last_rssi: Option<i16>,
+    pub last_rearm: Option<RearmRecord>,
+    /// Whether the schedule lets the listener lock, unset without a schedule
+    pub schedule: Option<ScheduleState>,
+}
+
+// Shared status of the Bluetooth listener
+#[derive(Default)]
+pub struct ListenerStatusState(pub Mutex<ListenerStatus>);
+
+/// Grace period after an unlock during which the listener does not lock, optionally collecting
+/// the readings a new baseline is computed from
+struct Rearm {
+    until: Instant,
+    samples: Option<Vec<i16>>,
+}
+
+impl Rearm {
+    fn new(now: Instant, grace: Duration, recompute_baseline: bool) -> Self {
+        Rearm {
+            until: now + grace,
+            samples: recompute_baseline.then(Vec::new),
+        }
+    }
+
+    fn is_active(&self, now: Instant) -> bool {
+        now < self.until
+    }
+
+    fn add_sample(&mut self, rssi: i16) {
+        if let Some(samples) = &mut self.samples {
+            samples.push(rssi);
+        }
+    }
+
+    /// Mean of the collected readings, if the baseline is recomputed and any were taken
+    fn baseline(&self) -> Option<i16> {
+        let samples = self
+            .samples
+            .as_ref()
+            .filter(|samples| !samples.is_empty())?;
+        let sum: i32 = samples.iter().map(|&rssi| i32::from(rssi)).sum();
+        Some((sum as f64 / samples.len() as f64).round() as i16)
+    }
+}
+
+type DeviceStream = Pin<Box<dyn Stream<Item = DiscoveredDevice> + Send>>;
+
+/// Initializes the adapter and starts scanning, returning the adapter with the device stream
 async fn get_bluetooth_stream(
     target_uuid: Option<Uuid>,
-) -> Result<Pin<Box<dyn Stream<Item = DiscoveredDevice> + Send>>, BluetoothError> {
-    discover_bluetooth_devices(target_uuid)
+) -> Result<(BluetoothAdapter, DeviceStream), BluetoothError> {
+    let adapter = init_bluetooth()
         .await
-        .map_err(|e| BluetoothError::Discovery(e.to_string()))
+        .map_err(|e| BluetoothError::Discovery(e.to_string()))?;
+    let stream = bluetooth_devices_stream(adapter.clone(), target_uuid);
+    Ok((adapter, stream))
 }
 
-/// Process a discovered device, handling RSSI and emitting events
+/// Waits until the machine resumes from sleep
+async fn wait_for_resume(sleep_events: &mut broadcast::Receiver<SleepEvent>) {
+    loop {
+        match sleep_events.recv().await {
+            Ok(SleepEvent::Resumed) | Err(broadcast::error::RecvError::Closed) => return,
+            Ok(SleepEvent::Sleeping) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+        }
+    }
+}
+
+/// Returns whether a timeout may lock the screen, which it may not during the resume grace
+/// period
+fn timeout_lock_allowed(resume_grace_until: Option<Instant>, now: Instant) -> bool {
+    resume_grace_until.is_none_or(|until| now >= until)
+}
+
+/// Process a discovered device, handling RSSI and emitting events.
+/// Returns the lock decision when the RSSI is over the allowed delta.
 fn process_device(
     app_handle: &AppHandle,
     device: DiscoveredDevice,
     initial_rssi: &mut Option<i16>,
+    last_rssi: &mut Option<i16>,
     rssi_delta_max: Option<i16>,
-) -> bool {
-    let rssi = match device.rssi {
-        Some(rssi) => rssi,
-        None => return false,
-    };
+) -> Option<LockContext> {
+    let rssi = device.rssi?;
+    *last_rssi = Some(rssi);
 
     if initial_rssi.is_none() {
         *initial_rssi = Some(rssi);
     }
 
     let diff_rssi = rssi - initial_rssi.unwrap();
-    if rssi_delta_max.map_or(true, |delta_max| delta_max + diff_rssi > 0) {
+    if rssi_delta_max.is_none_or(|delta_max| delta_max + diff_rssi > 0) {
         let event_type = device.event_type;
         let id = device.id;
         let local_name = device.local_name.unwrap_or_else(|| id.clone());
@@ -78,47 +240,191 @@ fn process_device(
                 "diff_rssi": diff_rssi
             }),
         );
-        true
+        None
     } else {
         info!("Over delta rssi: {}", diff_rssi);
         let _ = app_handle.emit(
             "bluetooth-over-delta-rssi",
             json!({ "diff_rssi": diff_rssi }),
         );
-        false
+        Some(LockContext {
+            reason: LockReason::OverDelta,
+            device_id: Some(device.id),
+            rssi: Some(rssi),
+            filtered_rssi: Some(rssi),
+            baseline_rssi: *initial_rssi,
+            diff_rssi: Some(diff_rssi),
+            rssi_delta_max,
+        })
     }
 }
 
+/// Hands a reading of the returning device over to the lock pipeline for automatic unlock,
+/// without blocking the listener
+fn observe_return(pipeline: &LockPipeline, rssi: Option<i16>) {
+    let (Some(rssi), true) = (rssi, pipeline.may_auto_unlock()) else {
+        return;
+    };
+    let pipeline = pipeline.clone();
+    tokio::spawn(async move {
+        if let Err(e) = pipeline.auto_unlock(rssi).await {
+            error!("Error unlocking screen: {}", e);
+        }
+    });
+}
+
+/// Hands a lock decision over to the lock pipeline without blocking the listener
+fn request_lock(pipeline: &LockPipeline, context: LockContext) {
+    let pipeline = pipeline.clone();
+    tokio::spawn(async move {
+        if let Err(e) = pipeline.request_lock(context).await {
+            error!("Error locking screen: {}", e);
+        }
+    });
+}
+
 async fn run_bluetooth_listener(
     app_handle: AppHandle,
     target_uuid: Option<Uuid>,
     rssi_delta_max: Option<i16>,
+    resume_grace: Duration,
+    unlock_grace: Duration,
+    recompute_baseline: bool,
 ) {
     const TIMEOUT_DURATION: Duration = Duration::from_secs(15);
     const REFRESH_BACKOFF: Duration = Duration::from_secs(1);
     const ERROR_BACKOFF: Duration = Duration::from_secs(3);
 
+    let pipeline = app_handle.state::<LockPipeline>().inner().clone();
+    let sleep_monitor = app_handle.state::<SleepMonitor>().inner().clone();
+    let mut sleep_events = sleep_monitor.subscribe();
+    let mut lock_events = app_handle.state::<LockStateMonitor>().inner().subscribe();
+    let status = |update: &dyn Fn(&mut ListenerStatus)| {
+        update(&mut app_handle.state::<ListenerStatusState>().0.lock().unwrap())
+    };
     let mut initial_rssi: Option<i16> = None;
+    let mut last_rssi: Option<i16> = None;
     let mut successives_timeout = 0;
+    let mut adapter_working = false;
+    let mut resume_grace_until: Option<Instant> = None;
+    let mut rearm: Option<Rearm> = None;
 
     // Main loop to restart the stream if it stops
     loop {
-        let mut device_stream = match get_bluetooth_stream(target_uuid).await {
-            Ok(stream) => stream,
+        let (adapter, mut device_stream) = match get_bluetooth_stream(target_uuid).await {
+            Ok(stream) => {
+                adapter_working = true;
+                stream
+            }
             Err(e) => {
                 error!("Error discovering bluetooth devices: {}", e);
+                // Lock once when discovery stops working while the device is monitored
+                if let (true, Some(target_uuid)) = (adapter_working, target_uuid) {
+                    let context = LockContext {
+                        device_id: Some(target_uuid.to_string()),
+                        rssi: last_rssi,
+                        filtered_rssi: last_rssi,
+                        baseline_rssi: initial_rssi,
+                        rssi_delta_max,
+                        ..LockContext::new(LockReason::AdapterLost)
+                    };
+                    request_lock(&pipeline, context);
+                }
+                adapter_working = false;
                 tokio::time::sleep(ERROR_BACKOFF).await;
                 continue;
             }
         };
 
-        // Inner loop to process the current stream
+        // Inner loop to process the current stream, left early when the machine goes to sleep
+        let mut sleeping = false;
         loop {
-            let next_event = timeout(TIMEOUT_DURATION, device_stream.next()).await;
+            let next_event = tokio::select! {
+                next_event = timeout(TIMEOUT_DURATION, device_stream.next()) => next_event,
+                event = sleep_events.recv() => {
+                    if let Ok(SleepEvent::Sleeping) = event {
+                        sleeping = true;
+                        break;
+                    }
+                    continue;
+                }
+                event = lock_events.recv() => {
+                    if let Ok(LockStateEvent::Unlocked) = event {
+                        // Give the user time to settle before locking again
+                        successives_timeout = 0;
+                        if recompute_baseline {
+                            initial_rssi = None;
+                        }
+                        rearm = Some(Rearm::new(Instant::now(), unlock_grace, recompute_baseline));
+                        info!("Session unlocked, re-arming in {:?}", unlock_grace);
+                        status(&|status| {
+                            status.armed = false;
+                            status.last_rearm = Some(RearmRecord {
+                                timestamp_ms: now_ms(),
+                                grace_secs: unlock_grace.as_secs(),
+                                recompute_baseline,
+                                baseline_rssi: None,
+                            });
+                        });
+                        let _ = app_handle.emit(
+                            "bluetooth-rearming",
+                            json!({
+                                "grace_secs": unlock_grace.as_secs(),
+                                "recompute_baseline": recompute_baseline
+                            }),
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            // Re-arm once the grace period after an unlock is over
+            if let Some(ended) = rearm.take_if(|rearm| !rearm.is_active(Instant::now())) {
+                if let Some(baseline) = ended.baseline() {
+                    initial_rssi = Some(baseline);
+                }
+                info!("Listener re-armed, baseline rssi: {:?}", initial_rssi);
+                status(&|status| {
+                    status.armed = true;
+                    status.baseline_rssi = initial_rssi;
+                    if let Some(last_rearm) = &mut status.last_rearm {
+                        last_rearm.baseline_rssi = ended.baseline();
+                    }
+                });
+                let _ = app_handle.emit(
+                    "bluetooth-rearmed",
+                    json!({ "baseline_rssi": initial_rssi }),
+                );
+            }
 
             match next_event {
                 Ok(Some(device)) => {
-                    process_device(&app_handle, device, &mut initial_rssi, rssi_delta_max);
+                    let decision = process_device(
+                        &app_handle,
+                        device,
+                        &mut initial_rssi,
+                        &mut last_rssi,
+                        rssi_delta_max,
+                    );
+                    if let (Some(rearm), Some(rssi)) = (&mut rearm, last_rssi) {
+                        rearm.add_sample(rssi);
+                    }
+                    status(&|status| {
+                        status.baseline_rssi = initial_rssi;
+                        status.last_rssi = last_rssi;
+                    });
+                    match decision {
+                        Some(_) if rearm.is_some() => {
+                            info!("Over delta during the unlock grace period, not locking");
+                        }
+                        Some(context) => request_lock(&pipeline, context),
+                        None => {
+                            if pipeline.device_returned(last_rssi) {
+                                info!("Device back in range, away actions cancelled");
+                            }
+                            observe_return(&pipeline, last_rssi);
+                        }
+                    }
                     successives_timeout = 0; // Reset timeout counter on successful events
                 }
                 _ => {
@@ -130,44 +436,83 @@ async fn run_bluetooth_listener(
                     } else {
                         successives_timeout = 0;
                         info!("Refresh time out");
-                        let _ = app_handle.emit("bluetooth-refresh-timeout", {});
+                        let _ = app_handle.emit("bluetooth-refresh-timeout", ());
+                        if !timeout_lock_allowed(resume_grace_until, Instant::now()) {
+                            info!("Timeout during the resume grace period, not locking");
+                        } else if rearm.is_some() {
+                            info!("Timeout during the unlock grace period, not locking");
+                        } else if let Some(target_uuid) = target_uuid {
+                            let context = LockContext {
+                                device_id: Some(target_uuid.to_string()),
+                                rssi: last_rssi,
+                                filtered_rssi: last_rssi,
+                                baseline_rssi: initial_rssi,
+                                rssi_delta_max,
+                                ..LockContext::new(LockReason::Timeout)
+                            };
+                            request_lock(&pipeline, context);
+                        }
                     }
                 }
             }
         }
 
+        if sleeping {
+            // Stop scanning before sleep, then start over from a fresh adapter on resume,
+            // discarding the readings taken before sleep
+            drop(device_stream);
+            if let Err(e) = stop_scan(&adapter).await {
+                warn!("Failed to stop scanning before sleep: {}", e);
+            }
+            drop(adapter);
+            sleep_monitor.ready_for_sleep();
+            info!("Scanning stopped for sleep");
+            let _ = app_handle.emit("bluetooth-suspended", ());
+
+            wait_for_resume(&mut sleep_events).await;
+            initial_rssi = None;
+            last_rssi = None;
+            successives_timeout = 0;
+            adapter_working = false;
+            status(&|status| {
+                status.baseline_rssi = None;
+                status.last_rssi = None;
+            });
+            resume_grace_until = Some(Instant::now() + resume_grace);
+            info!("Resumed from sleep, restarting scanning");
+            let _ = app_handle.emit(
+                "bluetooth-resumed",
+                json!({ "grace_secs": resume_grace.as_secs() }),
+            );
+            continue;
+        }
+
         // Wait before restarting the stream
         tokio::time::sleep(REFRESH_BACKOFF).await;
     }
 }
 
-#[tauri::command(rename_all = "snake_case")]
+/// Starts the Bluetooth listener with the stored settings, stopping the running one. The
+/// listener is restarted whenever one of its settings changes.
+#[tauri::command]
 pub async fn listen_bluetooth(
     app_handle: AppHandle,
     state: State<'_, BluetoothListenerHandle>,
-    target_uuid: Option<String>,
-    rssi_delta_max: Option<i16>,
+    store: State<'_, SettingsStore>,
 ) -> Result<(), String> {
-    // Stop previous listener if any
-    if let Some(handle) = state.0.lock().unwrap().take() {
-        handle.abort();
-    }
-
-    // Parse UUID if provided
-    let target_uuid = match target_uuid {
-        Some(uuid_str) => Some(Uuid::parse_str(&uuid_str).map_err(|e| e.to_string())?),
-        None => None,
-    };
-
-    // Spawn the new listener task
-    let handle = tokio::spawn(async move {
-        run_bluetooth_listener(app_handle.clone(), target_uuid, rssi_delta_max).await
-    });
-
-    // Store the new handle
-    *state.0.lock().unwrap() = Some(handle);
+    state.start(app_handle, &store.get())
+}
 
-    Ok(())
+/// Returns what the Bluetooth listener is currently doing
+#[tauri::command]
+pub fn get_listener_status(
+    status: State<'_, ListenerStatusState>,
+    pipeline: State<'_, LockPipeline>,
+) -> ListenerStatus {
+    ListenerStatus {
+        schedule: pipeline.schedule_state(),
+        ..status.0.lock().unwrap().clone()
+    }
 }
 
 #[cfg(test)]
@@ -204,10 +549,10 @@ mod tests {
         assert!(uuid_result.is_err(), "Invalid UUID should fail to parse");
         
         let bluetooth_error: BluetoothError = uuid_result.unwrap_err().into();
-        match bluetooth_error {
-            BluetoothError::UuidParse(_) => assert!(true),
-            _ => panic!("Expected UuidParse error"),
-        }
+        assert!(
+            matches!(bluetooth_error, BluetoothError::UuidParse(_)),
+            "Expected UuidParse error"
+        );
     }
 
     #[test]
@@ -237,16 +582,16 @@ mod tests {
         let diff_rssi = -5i16; // Device got closer (less negative)
         
         // Should be blocked: delta_max + diff_rssi = -10 + (-5) = -15, which is < 0
-        let allowed = rssi_delta_max.map_or(true, |delta_max| delta_max + diff_rssi > 0);
+        let allowed = rssi_delta_max.is_none_or(|delta_max| delta_max + diff_rssi > 0);
         assert!(!allowed, "Device should be blocked when too close");
 
         let diff_rssi = -15i16; // Device is farther away
-        let allowed = rssi_delta_max.map_or(true, |delta_max| delta_max + diff_rssi > 0);
+        let allowed = rssi_delta_max.is_none_or(|delta_max| delta_max + diff_rssi > 0);
         assert!(!allowed, "Device should still be blocked");
 
         // Test with no delta max (should always allow)
         let rssi_delta_max: Option<i16> = None;
-        let allowed = rssi_delta_max.map_or(true, |delta_max| delta_max + diff_rssi > 0);
+        let allowed = rssi_delta_max.is_none_or(|delta_max| delta_max + diff_rssi > 0);
         assert!(allowed, "Should always allow when no delta max is set");
     }
 
@@ -320,6 +665,46 @@ mod tests {
         assert_eq!(successives_timeout, 0);
     }
 
+    #[test]
+    fn test_rearm_grace_period() {
+        let now = Instant::now();
+        let rearm = Rearm::new(now, Duration::from_secs(10), false);
+        assert!(rearm.is_active(now));
+        assert!(!rearm.is_active(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_rearm_recomputes_baseline_from_samples() {
+        let mut rearm = Rearm::new(Instant::now(), UNLOCK_GRACE, true);
+        assert_eq!(rearm.baseline(), None);
+        for rssi in [-50, -53, -55] {
+            rearm.add_sample(rssi);
+        }
+        assert_eq!(rearm.baseline(), Some(-53));
+    }
+
+    #[test]
+    fn test_rearm_keeps_baseline_unless_recomputed() {
+        let mut rearm = Rearm::new(Instant::now(), UNLOCK_GRACE, false);
+        rearm.add_sample(-50);
+        assert_eq!(rearm.baseline(), None);
+    }
+
+    #[test]
+    fn test_timeout_lock_allowed_after_resume_grace() {
+        let now = Instant::now();
+        assert!(timeout_lock_allowed(None, now));
+        assert!(!timeout_lock_allowed(
+            Some(now + Duration::from_secs(30)),
+            now
+        ));
+        assert!(timeout_lock_allowed(Some(now), now));
+        assert!(timeout_lock_allowed(
+            Some(now),
+            now + Duration::from_secs(1)
+        ));
+    }
+
     #[test]
     fn test_process_device_name_fallback_logic() {
         // Test the name fallback logic: local_name.unwrap_or_else(|| id.clone())
@@ -0,0 +1,411 @@
+use crate::hooks::HookResult;
+use crate::lock_methods::LockMethod;
+use crate::lock_pipeline::{LockContext, PendingLockCancel};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the lock history file in the app data directory
+pub const LOCK_HISTORY_FILE: &str = "lock_history.jsonl";
+
+/// Size above which the lock history file is rotated
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Number of rotated lock history files kept next to the current one
+const MAX_ROTATED_FILES: usize = 3;
+
+/// What a lock history record is about
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A lock decision
+    #[default]
+    Lock,
+    /// The device coming back after an automatic lock
+    Return,
+    /// The session unlocked as the device stayed close after an automatic lock
+    Unlock,
+}
+
+/// Why a lock decision did not go as far as a lock attempt
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Automatic locks are off outside of the schedule
+    OutsideSchedule,
+    /// A calendar event suppressing automatic locks was under way
+    CalendarEvent { summary: String },
+    /// The user was active more recently than the minimum idle time
+    UserActive { idle_secs: u64, min_idle_secs: u64 },
+    /// The warning countdown was called off
+    Cancelled { cause: PendingLockCancel },
+}
+
+/// A lock decision as stored in the lock history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LockRecord {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    #[serde(default)]
+    pub event: AuditEvent,
+    #[serde(flatten)]
+    pub context: LockContext,
+    pub dry_run: bool,
+    /// The lock method that locked the screen
+    pub backend: Option<LockMethod>,
+    pub success: bool,
+    /// Why the lock failed
+    pub error: Option<String>,
+    /// Why the lock was not attempted
+    #[serde(default)]
+    pub skipped: Option<SkipReason>,
+    /// Hooks run for the event
+    #[serde(default)]
+    pub hooks: Vec<HookResult>,
+    /// Errors of the away hygiene actions run after the lock
+    #[serde(default)]
+    pub hygiene_errors: Vec<String>,
+}
+
+impl LockRecord {
+    /// Creates a record of a lock decision taken now
+    pub fn new(
+        context: LockContext,
+        dry_run: bool,
+        result: &Result<Option<LockMethod>, String>,
+    ) -> Self {
+        LockRecord {
+            timestamp_ms: now_ms(),
+            event: AuditEvent::Lock,
+            context,
+            dry_run,
+            backend: result.as_ref().ok().copied().flatten(),
+            success: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+            skipped: None,
+            hooks: Vec::new(),
+            hygiene_errors: Vec::new(),
+        }
+    }
+
+    /// Creates a record of a lock decision skipped now
+    pub fn skipped(context: LockContext, dry_run: bool, reason: SkipReason) -> Self {
+        LockRecord {
+            success: false,
+            skipped: Some(reason),
+            ..LockRecord::new(context, dry_run, &Ok(None))
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Append-only JSONL log of the lock decisions, rotated when it grows too large.
+///
+/// Cloning the log shares the same file lock.
+#[derive(Clone)]
+pub struct LockAuditLog {
+    path: PathBuf,
+    max_file_bytes: u64,
+    max_rotated_files: usize,
+    file_lock: Arc<Mutex<()>>,
+}
+
+impl LockAuditLog {
+    /// Creates a log stored in `lock_history.jsonl` in the given directory
+    pub fn in_dir(dir: &Path) -> Self {
+        Self::with_limits(
+            dir.join(LOCK_HISTORY_FILE),
+            MAX_FILE_BYTES,
+            MAX_ROTATED_FILES,
+        )
+    }
+
+    /// Creates a log with custom rotation limits
+    pub fn with_limits(path: PathBuf, max_file_bytes: u64, max_rotated_files: usize) -> Self {
+        LockAuditLog {
+            path,
+            max_file_bytes,
+            max_rotated_files,
+            file_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Path of the current log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of a log file, `0` being the current one and higher indexes older rotated ones
+    fn file_path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            return self.path.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        self.path
+            .with_file_name(format!("{}.{}.jsonl", stem, index))
+    }
+
+    /// Appends a record, rotating the log first if it is too large
+    pub fn append(&self, record: &LockRecord) -> Result<(), String> {
+        let _guard = self.file_lock.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create lock history directory: {}", e))?;
+        }
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= self.max_file_bytes) {
+            self.rotate()?;
+        }
+
+        let mut line = serde_json::to_string(record)
+            .map_err(|e| format!("Failed to serialize lock record: {}", e))?;
+        line.push('\n');
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open lock history: {}", e))?;
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write lock history: {}", e))
+    }
+
+    /// Shifts every log file to the next index, dropping the oldest one. Rotated files are only
+    /// readable by the user, even when written by a version creating them with the umask.
+    fn rotate(&self) -> Result<(), String> {
+        if self.max_rotated_files == 0 {
+            return fs::remove_file(&self.path)
+                .map_err(|e| format!("Failed to rotate lock history: {}", e));
+        }
+        let _ = fs::remove_file(self.file_path(self.max_rotated_files));
+        for index in (0..self.max_rotated_files).rev() {
+            let from = self.file_path(index);
+            if from.exists() {
+                let to = self.file_path(index + 1);
+                fs::rename(&from, &to)
+                    .and_then(|()| make_private(&to))
+                    .map_err(|e| format!("Failed to rotate lock history: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the records between `from` and `to` (inclusive, in milliseconds since the Unix
+    /// epoch), newest first, at most `limit` of them. Unreadable lines are skipped.
+    pub fn query(
+        &self,
+        from: Option<u64>,
+        to: Option<u64>,
+        limit: Option<usize>,
+    ) -> Result<Vec<LockRecord>, String> {
+        let _guard = self.file_lock.lock().unwrap();
+        let mut records = Vec::new();
+        for index in (0..=self.max_rotated_files).rev() {
+            let file = match File::open(self.file_path(index)) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to open lock history: {}", e)),
+            };
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| format!("Failed to read lock history: {}", e))?;
+                let Ok(record) = serde_json::from_str::<LockRecord>(&line) else {
+                    continue;
+                };
+                if from.is_some_and(|from| record.timestamp_ms < from)
+                    || to.is_some_and(|to| record.timestamp_ms > to)
+                {
+                    continue;
+                }
+                records.push(record);
+            }
+        }
+        records.reverse();
+        if let Some(limit) = limit {
+            records.truncate(limit);
+        }
+        Ok(records)
+    }
+}
+
+/// Returns the recorded lock decisions between `from` and `to` (milliseconds since the Unix
+/// epoch), newest first
+#[tauri::command]
+pub fn query_lock_history(
+    audit_log: tauri::State<'_, LockAuditLog>,
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>,
+) -> Result<Vec<LockRecord>, String> {
+    audit_log.query(from, to, limit)
+}
+
+/// Restricts a file to the user
+fn make_private(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pipeline::LockReason;
+    use tempfile::TempDir;
+
+    fn assert_private(path: &Path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{} should be private", path.display());
+        }
+    }
+
+    fn record(timestamp_ms: u64) -> LockRecord {
+        LockRecord {
+            timestamp_ms,
+            ..LockRecord::new(LockContext::new(LockReason::Manual), false, &Ok(None))
+        }
+    }
+
+    #[test]
+    fn test_record_from_result() {
+        let context = LockContext::new(LockReason::Timeout);
+        let locked = LockRecord::new(context.clone(), false, &Ok(Some(LockMethod::Logind)));
+        assert!(locked.success);
+        assert_eq!(locked.backend, Some(LockMethod::Logind));
+        assert!(locked.error.is_none());
+
+        let failed = LockRecord::new(context, false, &Err("Failed to lock screen".to_string()));
+        assert!(!failed.success);
+        assert!(failed.backend.is_none());
+        assert_eq!(failed.error.as_deref(), Some("Failed to lock screen"));
+    }
+
+    #[test]
+    fn test_skipped_record() {
+        let skipped = LockRecord::skipped(
+            LockContext::new(LockReason::OverDelta),
+            false,
+            SkipReason::UserActive {
+                idle_secs: 5,
+                min_idle_secs: 60,
+            },
+        );
+        assert!(!skipped.success);
+        assert!(skipped.error.is_none());
+
+        let json = serde_json::to_value(&skipped).unwrap();
+        assert_eq!(json["skipped"]["kind"], "user_active");
+        assert_eq!(json["skipped"]["idle_secs"], 5);
+        let cancelled = serde_json::json!({"kind": "cancelled", "cause": "device_returned"});
+        assert_eq!(
+            serde_json::from_value::<SkipReason>(cancelled).unwrap(),
+            SkipReason::Cancelled {
+                cause: PendingLockCancel::DeviceReturned
+            }
+        );
+    }
+
+    #[test]
+    fn test_record_serialization_is_flat() {
+        let json = serde_json::to_value(record(42)).unwrap();
+        assert_eq!(json["timestamp_ms"], 42);
+        assert_eq!(json["reason"], "manual");
+        assert_eq!(json["success"], true);
+        assert_eq!(json["event"], "lock");
+    }
+
+    #[test]
+    fn test_record_without_event_or_hooks_deserializes() {
+        let json = r#"{"timestamp_ms": 1, "reason": "timeout", "device_id": null, "rssi": null,
+            "filtered_rssi": null, "baseline_rssi": null, "diff_rssi": null,
+            "rssi_delta_max": null, "dry_run": false, "backend": "logind", "success": true,
+            "error": null}"#;
+        let record: LockRecord = serde_json::from_str(json).unwrap();
+        assert_eq!(record.event, AuditEvent::Lock);
+        assert!(record.skipped.is_none());
+        assert!(record.hooks.is_empty());
+        assert!(record.hygiene_errors.is_empty());
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let dir = TempDir::new().unwrap();
+        let log = LockAuditLog::in_dir(&dir.path().join("nested"));
+        for timestamp_ms in [10, 20, 30] {
+            log.append(&record(timestamp_ms)).unwrap();
+        }
+
+        let all = log.query(None, None, None).unwrap();
+        let timestamps: Vec<u64> = all.iter().map(|r| r.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![30, 20, 10]);
+
+        let ranged = log.query(Some(15), Some(30), Some(1)).unwrap();
+        assert_eq!(ranged, vec![record(30)]);
+        assert_private(log.path());
+    }
+
+    #[test]
+    fn test_query_without_history() {
+        let dir = TempDir::new().unwrap();
+        let log = LockAuditLog::in_dir(dir.path());
+        assert!(log.query(None, None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_skips_invalid_lines() {
+        let dir = TempDir::new().unwrap();
+        let log = LockAuditLog::in_dir(dir.path());
+        log.append(&record(10)).unwrap();
+        let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"not json\n").unwrap();
+        log.append(&record(20)).unwrap();
+
+        assert_eq!(log.query(None, None, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rotation_keeps_limited_history() {
+        let dir = TempDir::new().unwrap();
+        // Every append after the first rotates the log
+        let log = LockAuditLog::with_limits(dir.path().join(LOCK_HISTORY_FILE), 1, 2);
+        for timestamp_ms in 1..=5 {
+            log.append(&record(timestamp_ms)).unwrap();
+        }
+
+        assert!(dir.path().join("lock_history.1.jsonl").exists());
+        assert!(dir.path().join("lock_history.2.jsonl").exists());
+        assert!(!dir.path().join("lock_history.3.jsonl").exists());
+        assert_private(log.path());
+        assert_private(&dir.path().join("lock_history.1.jsonl"));
+        assert_private(&dir.path().join("lock_history.2.jsonl"));
+        let timestamps: Vec<u64> = log
+            .query(None, None, None)
+            .unwrap()
+            .iter()
+            .map(|r| r.timestamp_ms)
+            .collect();
+        assert_eq!(timestamps, vec![5, 4, 3]);
+    }
+}
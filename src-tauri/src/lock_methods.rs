@@ -0,0 +1,404 @@
+use crate::desktop_env::{Desktop, SessionInfo};
+use crate::read_write_settings::{find_executable, CustomLockCommand, SettingsStore};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+/// A way of locking the screen
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockMethod {
+    /// The user-defined lock command from the settings
+    Custom,
+    /// `org.freedesktop.login1.Session.Lock` on the system bus
+    Logind,
+    /// `Lock` on the session bus screen saver (freedesktop or GNOME)
+    ScreenSaver,
+    Loginctl,
+    Swaylock,
+    Hyprlock,
+    I3lock,
+    GnomeScreensaverCommand,
+    XdgScreensaver,
+    Qdbus,
+    CinnamonScreensaverCommand,
+    MateScreensaverCommand,
+    Xflock4,
+    /// Cmd+Ctrl+Q keyboard shortcut simulation on macOS
+    Osascript,
+    /// `LockWorkStation` from user32.dll on Windows
+    LockWorkStation,
+}
+
+/// Generic Linux lock methods, tried after the ones preferred for the desktop
+const LINUX_FALLBACK_ORDER: [LockMethod; 12] = [
+    LockMethod::Logind,
+    LockMethod::ScreenSaver,
+    LockMethod::Loginctl,
+    LockMethod::GnomeScreensaverCommand,
+    LockMethod::XdgScreensaver,
+    LockMethod::Qdbus,
+    LockMethod::CinnamonScreensaverCommand,
+    LockMethod::MateScreensaverCommand,
+    LockMethod::Xflock4,
+    LockMethod::Swaylock,
+    LockMethod::Hyprlock,
+    LockMethod::I3lock,
+];
+
+impl LockMethod {
+    /// Program and arguments for the methods that run a command
+    pub fn command(&self) -> Option<(&'static str, &'static [&'static str])> {
+        let command: (&'static str, &'static [&'static str]) = match self {
+            Self::Custom | Self::Logind | Self::ScreenSaver => return None,
+            Self::Loginctl => ("loginctl", &["lock-session"]),
+            Self::Swaylock => ("swaylock", &["-f"]),
+            Self::Hyprlock => ("hyprlock", &[]),
+            Self::I3lock => ("i3lock", &[]),
+            Self::GnomeScreensaverCommand => ("gnome-screensaver-command", &["--lock"]),
+            Self::XdgScreensaver => ("xdg-screensaver", &["lock"]),
+            Self::Qdbus => (
+                "qdbus",
+                &["org.freedesktop.ScreenSaver", "/ScreenSaver", "Lock"],
+            ),
+            Self::CinnamonScreensaverCommand => ("cinnamon-screensaver-command", &["--lock"]),
+            Self::MateScreensaverCommand => ("mate-screensaver-command", &["--lock"]),
+            Self::Xflock4 => ("xflock4", &[]),
+            Self::Osascript => (
+                "osascript",
+                &[
+                    "-e",
+                    "tell application \"System Events\" to keystroke \"q\" using {control down, command down}",
+                ],
+            ),
+            Self::LockWorkStation => ("rundll32.exe", &["user32.dll,LockWorkStation"]),
+        };
+        Some(command)
+    }
+
+    /// Whether the method needs an X11 session
+    pub fn is_x11_only(&self) -> bool {
+        matches!(
+            self,
+            Self::I3lock
+                | Self::GnomeScreensaverCommand
+                | Self::XdgScreensaver
+                | Self::CinnamonScreensaverCommand
+                | Self::MateScreensaverCommand
+                | Self::Xflock4
+        )
+    }
+}
+
+/// Builds the list of lock methods to try for the session, most suitable first.
+///
+/// On Linux the methods preferred for the detected desktop come first, followed by the
+/// generic ones. X11-only tools are left out of the generic ones on Wayland sessions.
+pub fn rank_lock_methods(session: &SessionInfo) -> Vec<LockMethod> {
+    if cfg!(target_os = "macos") {
+        return vec![LockMethod::Osascript];
+    }
+    if cfg!(target_os = "windows") {
+        return vec![LockMethod::LockWorkStation];
+    }
+
+    let wayland = session.is_wayland();
+    let preferred: &[LockMethod] = match session.desktop() {
+        Desktop::Gnome => &[
+            LockMethod::ScreenSaver,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+            LockMethod::GnomeScreensaverCommand,
+        ],
+        Desktop::Kde => &[
+            LockMethod::ScreenSaver,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+            LockMethod::Qdbus,
+        ],
+        Desktop::Cinnamon => &[
+            LockMethod::CinnamonScreensaverCommand,
+            LockMethod::ScreenSaver,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+        ],
+        Desktop::Mate => &[
+            LockMethod::MateScreensaverCommand,
+            LockMethod::ScreenSaver,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+        ],
+        Desktop::Xfce => &[
+            LockMethod::Xflock4,
+            LockMethod::ScreenSaver,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+        ],
+        Desktop::Sway => &[
+            LockMethod::Swaylock,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+        ],
+        Desktop::Hyprland => &[
+            LockMethod::Hyprlock,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+            LockMethod::Swaylock,
+        ],
+        Desktop::I3 => &[
+            LockMethod::I3lock,
+            LockMethod::Logind,
+            LockMethod::Loginctl,
+            LockMethod::XdgScreensaver,
+        ],
+        Desktop::Other => &[
+            LockMethod::Logind,
+            LockMethod::ScreenSaver,
+            LockMethod::Loginctl,
+        ],
+    };
+
+    let mut ranked = preferred.to_vec();
+    for method in LINUX_FALLBACK_ORDER {
+        if !ranked.contains(&method) && !(wayland && method.is_x11_only()) {
+            ranked.push(method);
+        }
+    }
+    ranked
+}
+
+/// Moves the pinned lock method, if any, to the front of the list
+pub fn apply_pinned(mut methods: Vec<LockMethod>, pinned: Option<LockMethod>) -> Vec<LockMethod> {
+    if let Some(pinned) = pinned {
+        methods.retain(|method| *method != pinned);
+        methods.insert(0, pinned);
+    }
+    methods
+}
+
+/// A lock method together with whether it can be used on this machine
+#[derive(Serialize, Debug, Clone)]
+pub struct LockMethodInfo {
+    pub method: LockMethod,
+    pub available: bool,
+    pub pinned: bool,
+}
+
+/// The detected session and the lock methods in the order they are tried
+#[derive(Serialize, Debug, Clone)]
+pub struct LockMethodsReport {
+    pub desktop: Desktop,
+    pub wayland: bool,
+    pub methods: Vec<LockMethodInfo>,
+}
+
+/// Returns whether a lock method can be used on this machine
+pub async fn is_available(
+    method: LockMethod,
+    custom_lock_command: Option<&CustomLockCommand>,
+) -> bool {
+    match method {
+        LockMethod::Custom => custom_lock_command.is_some_and(|command| command.validate().is_ok()),
+        #[cfg(target_os = "linux")]
+        LockMethod::Logind => match zbus::Connection::system().await {
+            Ok(system_bus) => crate::dbus_lock::logind_available(&system_bus).await,
+            Err(_) => false,
+        },
+        #[cfg(target_os = "linux")]
+        LockMethod::ScreenSaver => match zbus::Connection::session().await {
+            Ok(session_bus) => crate::dbus_lock::screensaver_available(&session_bus).await,
+            Err(_) => false,
+        },
+        _ => method
+            .command()
+            .is_some_and(|(program, _)| find_executable(program).is_some()),
+    }
+}
+
+/// Lists the lock methods for the current session in the order they are tried, with their
+/// availability. The custom lock command comes first when one is configured.
+#[tauri::command]
+pub async fn list_lock_methods(
+    store: State<'_, SettingsStore>,
+) -> Result<LockMethodsReport, String> {
+    let settings = store.get();
+    let custom_lock_command = settings.custom_lock_command;
+    let pinned_lock_method = settings.pinned_lock_method;
+    let session = SessionInfo::detect();
+    let mut methods = rank_lock_methods(&session);
+    if custom_lock_command.is_some() {
+        methods.insert(0, LockMethod::Custom);
+    }
+    let methods = apply_pinned(methods, pinned_lock_method);
+
+    let mut infos = Vec::with_capacity(methods.len());
+    for method in methods {
+        infos.push(LockMethodInfo {
+            method,
+            available: is_available(method, custom_lock_command.as_ref()).await,
+            pinned: pinned_lock_method == Some(method),
+        });
+    }
+    Ok(LockMethodsReport {
+        desktop: session.desktop(),
+        wayland: session.is_wayland(),
+        methods: infos,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(current_desktop: &str, wayland: bool) -> SessionInfo {
+        SessionInfo {
+            current_desktop: vec![current_desktop.to_string()],
+            session_type: Some(if wayland { "wayland" } else { "x11" }.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rank_prefers_desktop_lockers() {
+        assert_eq!(
+            rank_lock_methods(&session("GNOME", true))[0],
+            LockMethod::ScreenSaver
+        );
+        assert_eq!(
+            rank_lock_methods(&session("KDE", true))[0],
+            LockMethod::ScreenSaver
+        );
+        assert_eq!(
+            rank_lock_methods(&session("X-Cinnamon", false))[0],
+            LockMethod::CinnamonScreensaverCommand
+        );
+        assert_eq!(
+            rank_lock_methods(&session("MATE", false))[0],
+            LockMethod::MateScreensaverCommand
+        );
+        assert_eq!(
+            rank_lock_methods(&session("XFCE", false))[0],
+            LockMethod::Xflock4
+        );
+        assert_eq!(
+            rank_lock_methods(&session("sway", true))[0],
+            LockMethod::Swaylock
+        );
+        assert_eq!(
+            rank_lock_methods(&session("Hyprland", true))[0],
+            LockMethod::Hyprlock
+        );
+        assert_eq!(
+            rank_lock_methods(&session("i3", false))[0],
+            LockMethod::I3lock
+        );
+        assert_eq!(
+            rank_lock_methods(&session("Unknown", false))[0],
+            LockMethod::Logind
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rank_wayland_compositors_use_logind_after_locker() {
+        let ranked = rank_lock_methods(&session("sway", true));
+        assert_eq!(
+            &ranked[..3],
+            &[
+                LockMethod::Swaylock,
+                LockMethod::Logind,
+                LockMethod::Loginctl
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rank_skips_x11_fallbacks_on_wayland() {
+        let ranked = rank_lock_methods(&session("sway", true));
+        assert!(!ranked.iter().any(|method| method.is_x11_only()));
+
+        let ranked = rank_lock_methods(&session("Unknown", false));
+        assert!(ranked.contains(&LockMethod::Xflock4));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rank_has_no_duplicates() {
+        for desktop in ["GNOME", "KDE", "sway", "Hyprland", "i3", "Unknown"] {
+            let ranked = rank_lock_methods(&session(desktop, false));
+            for (index, method) in ranked.iter().enumerate() {
+                assert!(
+                    !ranked[index + 1..].contains(method),
+                    "{:?} listed twice for {}",
+                    method,
+                    desktop
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_pinned_moves_method_first() {
+        let methods = vec![
+            LockMethod::Logind,
+            LockMethod::ScreenSaver,
+            LockMethod::Loginctl,
+        ];
+        let pinned = apply_pinned(methods.clone(), Some(LockMethod::Loginctl));
+        assert_eq!(
+            pinned,
+            vec![
+                LockMethod::Loginctl,
+                LockMethod::Logind,
+                LockMethod::ScreenSaver
+            ]
+        );
+
+        assert_eq!(apply_pinned(methods.clone(), None), methods);
+    }
+
+    #[test]
+    fn test_apply_pinned_adds_missing_method() {
+        let pinned = apply_pinned(vec![LockMethod::Logind], Some(LockMethod::Swaylock));
+        assert_eq!(pinned, vec![LockMethod::Swaylock, LockMethod::Logind]);
+    }
+
+    #[test]
+    fn test_command_methods() {
+        assert_eq!(
+            LockMethod::Loginctl.command(),
+            Some(("loginctl", &["lock-session"][..]))
+        );
+        assert_eq!(
+            LockMethod::Swaylock.command(),
+            Some(("swaylock", &["-f"][..]))
+        );
+        assert!(LockMethod::Logind.command().is_none());
+        assert!(LockMethod::ScreenSaver.command().is_none());
+        assert!(LockMethod::Custom.command().is_none());
+    }
+
+    #[test]
+    fn test_lock_method_serialization() {
+        assert_eq!(
+            serde_json::to_string(&LockMethod::GnomeScreensaverCommand).unwrap(),
+            "\"gnome_screensaver_command\""
+        );
+        let method: LockMethod = serde_json::from_str("\"swaylock\"").unwrap();
+        assert_eq!(method, LockMethod::Swaylock);
+    }
+
+    #[tokio::test]
+    async fn test_custom_method_availability() {
+        assert!(!is_available(LockMethod::Custom, None).await);
+
+        let missing = CustomLockCommand {
+            argv: vec!["lock-4-me-missing-locker".to_string()],
+            env: Default::default(),
+            timeout_secs: None,
+            exclusive: false,
+        };
+        assert!(!is_available(LockMethod::Custom, Some(&missing)).await);
+    }
+}
@@ -0,0 +1,1349 @@
+use crate::auto_unlock::{AutoUnlock, Dwell, UnlockCandidate};
+use crate::away_actions::{perform_away_action, AwayAction, AwayActionEvent, AwayActionPlan};
+use crate::away_hygiene::{run_away_hygiene, AwayHygiene};
+use crate::calendar::{CalendarAction, CalendarMonitor};
+use crate::hooks::{run_hooks, run_hooks_within, Hook, HookEvent};
+use crate::idle::IdleMonitor;
+use crate::lock_audit::{AuditEvent, LockAuditLog, LockRecord, SkipReason};
+use crate::lock_methods::LockMethod;
+use crate::lock_screen::run_lock_backends;
+use crate::lock_state::{LockStateEvent, LockStateMonitor};
+use crate::read_write_settings::{CustomLockCommand, Settings};
+use crate::schedule::{Schedule, ScheduleState};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{Duration, Instant};
+
+/// How long to wait for the session to report being locked after a lock request
+const LOCK_CONFIRM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long the pre-lock hooks may delay a lock altogether
+const PRE_LOCK_HOOKS_BUDGET: Duration = Duration::from_secs(5);
+
+/// Command-line flag forcing dry-run mode regardless of the settings
+pub const DRY_RUN_FLAG: &str = "--dry-run";
+
+/// Why a lock was requested
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReason {
+    /// The RSSI dropped further below the baseline than the allowed delta
+    OverDelta,
+    /// The device was not seen for too long
+    Timeout,
+    /// The Bluetooth adapter stopped working while the device was monitored
+    AdapterLost,
+    /// The user asked for the screen to be locked
+    Manual,
+}
+
+/// A lock decision together with the signal readings it was based on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LockContext {
+    pub reason: LockReason,
+    pub device_id: Option<String>,
+    /// Last RSSI reading of the device
+    pub rssi: Option<i16>,
+    /// RSSI the decision was based on (currently the last reading, as readings are not smoothed)
+    pub filtered_rssi: Option<i16>,
+    pub baseline_rssi: Option<i16>,
+    pub diff_rssi: Option<i16>,
+    pub rssi_delta_max: Option<i16>,
+}
+
+impl LockContext {
+    /// Creates a context with no signal readings
+    pub fn new(reason: LockReason) -> Self {
+        LockContext {
+            reason,
+            device_id: None,
+            rssi: None,
+            filtered_rssi: None,
+            baseline_rssi: None,
+            diff_rssi: None,
+            rssi_delta_max: None,
+        }
+    }
+}
+
+/// What became of a lock request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockOutcome {
+    Locked,
+    AlreadyLocked,
+    InProgress,
+    Simulated,
+    Cancelled,
+    /// Every step of the away action plan ran and the device came back
+    PlanCompleted,
+    /// The signal was weak but the user was active, so a warning was given instead
+    UserActive,
+    /// Automatic locking was not active according to the schedule
+    OutsideSchedule,
+    /// Automatic locking was suppressed during a calendar event
+    DuringCalendarEvent,
+}
+
+/// Why a pending lock was called off
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingLockCancel {
+    /// The user cancelled from the app or the notification
+    User,
+    /// The device was seen in range again
+    DeviceReturned,
+}
+
+/// A lock waiting for its warning countdown to end
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PendingLock {
+    #[serde(flatten)]
+    pub context: LockContext,
+    pub countdown_secs: u64,
+}
+
+/// A pending lock that was called off
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CancelledLock {
+    #[serde(flatten)]
+    pub context: LockContext,
+    pub cause: PendingLockCancel,
+}
+
+/// A weak-signal lock skipped because the session was not idle long enough
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UserActiveWarning {
+    #[serde(flatten)]
+    pub context: LockContext,
+    pub idle_secs: u64,
+    pub min_idle_secs: u64,
+}
+
+/// An automatic lock skipped during a calendar event
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CalendarSkip {
+    #[serde(flatten)]
+    pub context: LockContext,
+    /// Summary of the calendar event
+    pub event: String,
+}
+
+/// An event of the lock pipeline forwarded to the frontend
+#[derive(Clone, Debug, PartialEq)]
+pub enum LockPipelineEvent {
+    /// A lock was decided but not performed because of dry-run mode
+    Simulated(LockContext),
+    /// A lock was decided and will happen at the end of the countdown unless cancelled
+    Pending(PendingLock),
+    /// A pending lock was cancelled
+    Cancelled(CancelledLock),
+    /// A step of the away action plan was taken
+    AwayAction(AwayActionEvent),
+    /// The device came back before every step of the away action plan ran
+    AwayPlanCancelled(LockContext),
+    /// The signal was weak while the user was active, sent once until the device returns
+    UserActive(UserActiveWarning),
+    /// The session was unlocked as the device stayed close after an automatic lock
+    AutoUnlocked(LockContext),
+    /// An automatic lock was skipped outside of the schedule, sent once until the device returns
+    OutsideSchedule(LockContext),
+    /// An automatic lock was skipped during a calendar event, sent once until the device returns
+    DuringCalendarEvent(CalendarSkip),
+}
+
+impl LockPipelineEvent {
+    /// Name of the Tauri event emitted for this event
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            Self::Simulated(_) => "lock-simulated",
+            Self::Pending(_) => "lock-pending",
+            Self::Cancelled(_) => "lock-cancelled",
+            Self::AwayAction(_) => "away-action",
+            Self::AwayPlanCancelled(_) => "away-plan-cancelled",
+            Self::UserActive(_) => "lock-skipped-user-active",
+            Self::AutoUnlocked(_) => "auto-unlocked",
+            Self::OutsideSchedule(_) => "lock-skipped-schedule",
+            Self::DuringCalendarEvent(_) => "lock-skipped-calendar",
+        }
+    }
+
+    fn emit(&self, app_handle: &AppHandle) {
+        let _ = match self {
+            Self::Simulated(context) => app_handle.emit(self.event_name(), context),
+            Self::Pending(pending) => app_handle.emit(self.event_name(), pending),
+            Self::Cancelled(cancelled) => app_handle.emit(self.event_name(), cancelled),
+            Self::AwayAction(action) => app_handle.emit(self.event_name(), action),
+            Self::AwayPlanCancelled(context) => app_handle.emit(self.event_name(), context),
+            Self::UserActive(warning) => app_handle.emit(self.event_name(), warning),
+            Self::AutoUnlocked(context) => app_handle.emit(self.event_name(), context),
+            Self::OutsideSchedule(context) => app_handle.emit(self.event_name(), context),
+            Self::DuringCalendarEvent(skip) => app_handle.emit(self.event_name(), skip),
+        };
+    }
+}
+
+/// The settings used when locking
+#[derive(Clone, Debug, Default)]
+struct LockConfig {
+    custom_lock_command: Option<CustomLockCommand>,
+    pinned_lock_method: Option<LockMethod>,
+    lock_all_sessions: bool,
+    dry_run: bool,
+    lock_warning_secs: u64,
+    away_action_plan: Option<AwayActionPlan>,
+    pre_lock_hooks: Vec<Hook>,
+    on_return_hooks: Vec<Hook>,
+    away_hygiene: AwayHygiene,
+    min_idle_secs: Option<u64>,
+    auto_unlock: Option<AutoUnlock>,
+    schedule: Option<Schedule>,
+}
+
+/// Takes every lock decision through the same steps: skipping it when the session is already
+/// locked or a lock is underway, counting down a warning period for automatic locks, simulating
+/// it in dry-run mode, and otherwise running the lock backends and waiting for the session to
+/// report being locked. Simulated and attempted locks are recorded in the lock history when an
+/// audit log is set.
+///
+/// When an away action plan is set, automatic lock decisions run the plan instead, locking at
+/// the lock step, until the device returns or the session is unlocked.
+///
+/// When a schedule is set, automatic lock decisions are skipped while it is not active. During
+/// calendar events matching the calendar settings, they are skipped or preceded by a longer
+/// warning.
+///
+/// When a minimum idle time is set, weak-signal decisions are turned into a warning while the
+/// session has seen user input more recently than that.
+///
+/// When automatic unlock is set, an automatic lock confirmed by the lock state monitor can be
+/// undone through logind if the device stays close soon enough, as long as the session was not
+/// unlocked and locked again in between.
+///
+/// Cloning the pipeline shares the same state.
+#[derive(Clone)]
+pub struct LockPipeline {
+    monitor: LockStateMonitor,
+    config: Arc<Mutex<LockConfig>>,
+    forced_dry_run: bool,
+    in_progress: Arc<AtomicBool>,
+    pending: Arc<Mutex<Option<oneshot::Sender<PendingLockCancel>>>>,
+    away: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    locked_away: Arc<Mutex<Option<LockContext>>>,
+    active_warned: Arc<AtomicBool>,
+    schedule_warned: Arc<AtomicBool>,
+    calendar_warned: Arc<AtomicBool>,
+    calendar: CalendarMonitor,
+    unlock_candidate: Arc<Mutex<Option<UnlockCandidate>>>,
+    events: broadcast::Sender<LockPipelineEvent>,
+    audit_log: Option<LockAuditLog>,
+    idle: IdleMonitor,
+    #[cfg(target_os = "linux")]
+    system_bus: Option<zbus::Connection>,
+}
+
+impl LockPipeline {
+    /// Creates a pipeline, always in dry-run mode when `forced_dry_run` is set
+    pub fn new(monitor: LockStateMonitor, forced_dry_run: bool) -> Self {
+        let (events, _) = broadcast::channel(16);
+        LockPipeline {
+            monitor,
+            config: Arc::new(Mutex::new(LockConfig::default())),
+            forced_dry_run,
+            in_progress: Arc::new(AtomicBool::new(false)),
+            pending: Arc::new(Mutex::new(None)),
+            away: Arc::new(Mutex::new(None)),
+            locked_away: Arc::new(Mutex::new(None)),
+            active_warned: Arc::new(AtomicBool::new(false)),
+            schedule_warned: Arc::new(AtomicBool::new(false)),
+            calendar_warned: Arc::new(AtomicBool::new(false)),
+            calendar: CalendarMonitor::new(),
+            unlock_candidate: Arc::new(Mutex::new(None)),
+            events,
+            audit_log: None,
+            idle: IdleMonitor::new(),
+            #[cfg(target_os = "linux")]
+            system_bus: None,
+        }
+    }
+
+    /// Records the lock decisions in the given audit log
+    pub fn with_audit_log(mut self, audit_log: LockAuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Reads the session idle time from the given monitor
+    pub fn with_idle_monitor(mut self, idle: IdleMonitor) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    /// Unlocks the session through the given system bus instead of connecting to it
+    #[cfg(target_os = "linux")]
+    pub fn with_system_bus(mut self, system_bus: zbus::Connection) -> Self {
+        self.system_bus = Some(system_bus);
+        self
+    }
+
+    /// Settings taken into account by [`LockPipeline::apply_settings`]
+    pub const SETTINGS_KEYS: [&'static str; 13] = [
+        "custom_lock_command",
+        "pinned_lock_method",
+        "lock_all_sessions",
+        "dry_run",
+        "lock_warning_secs",
+        "away_action_plan",
+        "pre_lock_hooks",
+        "on_return_hooks",
+        "away_hygiene",
+        "min_idle_secs",
+        "auto_unlock",
+        "schedule",
+        "calendar",
+    ];
+
+    /// Takes the lock-related values of the settings into account for the next lock requests
+    pub fn apply_settings(&self, settings: &Settings) {
+        let mut config = self.config.lock().unwrap();
+        config.custom_lock_command = settings.custom_lock_command.clone();
+        config.pinned_lock_method = settings.pinned_lock_method;
+        config.lock_all_sessions = settings.lock_all_sessions;
+        config.dry_run = settings.dry_run;
+        config.lock_warning_secs = settings.lock_warning_secs;
+        config.away_action_plan = settings.away_action_plan.clone();
+        config.pre_lock_hooks = settings.pre_lock_hooks.clone();
+        config.on_return_hooks = settings.on_return_hooks.clone();
+        config.away_hygiene = settings.away_hygiene.clone();
+        config.min_idle_secs = settings.min_idle_secs;
+        config.auto_unlock = settings.auto_unlock.clone();
+        config.schedule = settings.schedule.clone();
+        drop(config);
+        self.calendar.configure(settings.calendar.as_ref());
+    }
+
+    /// Returns whether locks are only simulated
+    pub fn is_dry_run(&self) -> bool {
+        self.forced_dry_run || self.config.lock().unwrap().dry_run
+    }
+
+    /// Returns whether automatic locking is active according to the schedule, and until when,
+    /// unset without a schedule
+    pub fn schedule_state(&self) -> Option<ScheduleState> {
+        let schedule = self.config.lock().unwrap().schedule.clone()?;
+        match schedule.state_at(chrono::Utc::now()) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Returns whether the schedule stops an automatic lock at the moment. Locks go ahead when
+    /// the schedule cannot be evaluated.
+    fn outside_schedule(&self, context: &LockContext) -> bool {
+        if context.reason == LockReason::Manual {
+            return false;
+        }
+        let Some(schedule) = self.config.lock().unwrap().schedule.clone() else {
+            return false;
+        };
+        match schedule.is_active_at(chrono::Utc::now()) {
+            Ok(active) => !active,
+            Err(e) => {
+                warn!("{}, locking regardless of the schedule", e);
+                false
+            }
+        }
+    }
+
+    /// Subscribes to the pipeline events
+    pub fn subscribe(&self) -> broadcast::Receiver<LockPipelineEvent> {
+        self.events.subscribe()
+    }
+
+    /// Handles a lock decision
+    pub async fn request_lock(&self, context: LockContext) -> Result<LockOutcome, String> {
+        if self.outside_schedule(&context) {
+            info!("Outside of the schedule, lock skipped: {:?}", context);
+            if !self.schedule_warned.swap(true, Ordering::SeqCst) {
+                self.record(LockRecord::skipped(
+                    context.clone(),
+                    self.is_dry_run(),
+                    SkipReason::OutsideSchedule,
+                ));
+                let _ = self
+                    .events
+                    .send(LockPipelineEvent::OutsideSchedule(context));
+            }
+            return Ok(LockOutcome::OutsideSchedule);
+        }
+        if context.reason != LockReason::Manual {
+            if let Some(event) = self.calendar.current_event(chrono::Utc::now()) {
+                if event.action == CalendarAction::Suppress {
+                    info!(
+                        "During calendar event '{}', lock skipped: {:?}",
+                        event.summary, context
+                    );
+                    if !self.calendar_warned.swap(true, Ordering::SeqCst) {
+                        self.record(LockRecord::skipped(
+                            context.clone(),
+                            self.is_dry_run(),
+                            SkipReason::CalendarEvent {
+                                summary: event.summary.clone(),
+                            },
+                        ));
+                        let _ = self.events.send(LockPipelineEvent::DuringCalendarEvent(
+                            CalendarSkip {
+                                context,
+                                event: event.summary,
+                            },
+                        ));
+                    }
+                    return Ok(LockOutcome::DuringCalendarEvent);
+                }
+            }
+        }
+        if let Some(warning) = self.check_user_active(&context).await {
+            info!(
+                "User active {}s ago, lock skipped: {:?}",
+                warning.idle_secs, warning.context
+            );
+            if !self.active_warned.swap(true, Ordering::SeqCst) {
+                self.record(LockRecord::skipped(
+                    context,
+                    self.is_dry_run(),
+                    SkipReason::UserActive {
+                        idle_secs: warning.idle_secs,
+                        min_idle_secs: warning.min_idle_secs,
+                    },
+                ));
+                let _ = self.events.send(LockPipelineEvent::UserActive(warning));
+            }
+            return Ok(LockOutcome::UserActive);
+        }
+        let plan = self.config.lock().unwrap().away_action_plan.clone();
+        match plan {
+            Some(plan) if context.reason != LockReason::Manual && !plan.steps.is_empty() => {
+                self.run_away_plan(plan, context).await
+            }
+            _ => self.lock_once(context, false).await,
+        }
+    }
+
+    /// Returns a warning when the decision comes from a weak signal and the session has not been
+    /// idle for the minimum idle time. Locks go ahead when the idle time cannot be read.
+    async fn check_user_active(&self, context: &LockContext) -> Option<UserActiveWarning> {
+        let min_idle_secs = match self.config.lock().unwrap().min_idle_secs {
+            Some(min_idle_secs) if min_idle_secs > 0 => min_idle_secs,
+            _ => return None,
+        };
+        if !matches!(context.reason, LockReason::OverDelta | LockReason::Timeout) {
+            return None;
+        }
+        match self.idle.idle_secs().await {
+            Ok(idle_secs) if idle_secs < min_idle_secs => Some(UserActiveWarning {
+                context: context.clone(),
+                idle_secs,
+                min_idle_secs,
+            }),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("{}, locking regardless of user activity", e);
+                None
+            }
+        }
+    }
+
+    /// Locks unless the session is already locked or a lock is underway. After the away action
+    /// plan blanked the displays, an active screen saver does not count as a locked session.
+    async fn lock_once(
+        &self,
+        context: LockContext,
+        after_blank: bool,
+    ) -> Result<LockOutcome, String> {
+        info!("Lock requested: {:?}", context);
+        if self.is_locked(after_blank) {
+            info!("Session already locked, skipping lock");
+            return Ok(LockOutcome::AlreadyLocked);
+        }
+        if self.in_progress.swap(true, Ordering::SeqCst) {
+            info!("Lock already in progress, skipping lock");
+            return Ok(LockOutcome::InProgress);
+        }
+        let result = self.lock(context, after_blank).await;
+        self.in_progress.store(false, Ordering::SeqCst);
+        result
+    }
+
+    fn is_locked(&self, after_blank: bool) -> bool {
+        self.monitor.is_locked() && !(after_blank && self.monitor.is_screensaver_active())
+    }
+
+    /// Calls off the pending lock, returning `false` if there is none
+    pub fn cancel_pending_lock(&self, cause: PendingLockCancel) -> bool {
+        match self.pending.lock().unwrap().take() {
+            Some(cancel) => cancel.send(cause).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Calls off the pending lock and the away action plan when the device is back in range,
+    /// returning `false` if neither was running. The on-return hooks run if the screen was
+    /// locked automatically while the device was away.
+    pub fn device_returned(&self, rssi: Option<i16>) -> bool {
+        self.active_warned.store(false, Ordering::SeqCst);
+        self.schedule_warned.store(false, Ordering::SeqCst);
+        self.calendar_warned.store(false, Ordering::SeqCst);
+        if let Some(context) = self.locked_away.lock().unwrap().take() {
+            self.spawn_return_hooks(context, rssi);
+        }
+        let pending_cancelled = self.cancel_pending_lock(PendingLockCancel::DeviceReturned);
+        let away_ended = match self.away.lock().unwrap().take() {
+            Some(end) => end.send(()).is_ok(),
+            None => false,
+        };
+        pending_cancelled || away_ended
+    }
+
+    /// Returns whether the away action plan is running
+    pub fn is_away(&self) -> bool {
+        self.away.lock().unwrap().is_some()
+    }
+
+    /// Takes the steps of the away action plan as their delays elapse, then waits for the device
+    /// to return. The plan also ends when the session is unlocked, as the user is back.
+    async fn run_away_plan(
+        &self,
+        plan: AwayActionPlan,
+        context: LockContext,
+    ) -> Result<LockOutcome, String> {
+        let (end, mut ended) = oneshot::channel();
+        {
+            let mut away = self.away.lock().unwrap();
+            if away.is_some() {
+                return Ok(LockOutcome::InProgress);
+            }
+            *away = Some(end);
+        }
+        info!("Device away, running away action plan: {:?}", context);
+        let mut lock_states = self.monitor.subscribe();
+
+        let started = Instant::now();
+        let mut blanked = false;
+        for step in plan.ordered_steps() {
+            let at = started + Duration::from_secs(step.delay_secs);
+            tokio::select! {
+                _ = tokio::time::sleep_until(at) => {}
+                _ = &mut ended => {
+                    info!("Device returned, away action plan cancelled");
+                    let _ = self.events.send(LockPipelineEvent::AwayPlanCancelled(context));
+                    return Ok(LockOutcome::Cancelled);
+                }
+                _ = session_unlocked(&mut lock_states) => {
+                    info!("Session unlocked, away action plan cancelled");
+                    self.away.lock().unwrap().take();
+                    let _ = self.events.send(LockPipelineEvent::AwayPlanCancelled(context));
+                    return Ok(LockOutcome::Cancelled);
+                }
+            }
+
+            let dry_run = self.is_dry_run();
+            let result = match step.action {
+                AwayAction::Lock => self.lock_once(context.clone(), blanked).await.map(|_| ()),
+                _ if dry_run => Ok(()),
+                action => perform_away_action(action).await,
+            };
+            blanked |= step.action == AwayAction::Blank && result.is_ok();
+            if let Err(e) = &result {
+                warn!("Away action {:?} failed: {}", step.action, e);
+            }
+            let _ = self
+                .events
+                .send(LockPipelineEvent::AwayAction(AwayActionEvent {
+                    action: step.action,
+                    delay_secs: step.delay_secs,
+                    dry_run,
+                    success: result.is_ok(),
+                    error: result.err(),
+                }));
+        }
+
+        tokio::select! {
+            _ = ended => info!("Device returned after the away action plan"),
+            _ = session_unlocked(&mut lock_states) => {
+                info!("Session unlocked after the away action plan");
+                self.away.lock().unwrap().take();
+            }
+        }
+        Ok(LockOutcome::PlanCompleted)
+    }
+
+    /// Returns whether a lock is waiting for its warning countdown to end
+    pub fn has_pending_lock(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
+
+    /// Waits for the warning countdown, returning the cause if the lock is called off
+    async fn count_down(
+        &self,
+        context: &LockContext,
+        countdown_secs: u64,
+    ) -> Option<PendingLockCancel> {
+        let (cancel, cancelled) = oneshot::channel();
+        *self.pending.lock().unwrap() = Some(cancel);
+        info!("Locking in {} seconds unless cancelled", countdown_secs);
+        let _ = self.events.send(LockPipelineEvent::Pending(PendingLock {
+            context: context.clone(),
+            countdown_secs,
+        }));
+
+        let cause = tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(countdown_secs)) => None,
+            cause = cancelled => cause.ok(),
+        };
+        self.pending.lock().unwrap().take();
+        cause
+    }
+
+    /// Seconds of warning before an automatic lock, longer during a calendar event relaxing
+    /// locks
+    fn lock_warning_secs(&self) -> u64 {
+        let lock_warning_secs = self.config.lock().unwrap().lock_warning_secs;
+        match self.calendar.current_event(chrono::Utc::now()) {
+            Some(event) => match event.action {
+                CalendarAction::Relax { warning_secs } => {
+                    info!(
+                        "During calendar event '{}', lock warning of at least {}s",
+                        event.summary, warning_secs
+                    );
+                    lock_warning_secs.max(warning_secs)
+                }
+                CalendarAction::Suppress => lock_warning_secs,
+            },
+            None => lock_warning_secs,
+        }
+    }
+
+    async fn lock(&self, context: LockContext, after_blank: bool) -> Result<LockOutcome, String> {
+        let lock_warning_secs = if context.reason == LockReason::Manual {
+            0
+        } else {
+            self.lock_warning_secs()
+        };
+        if context.reason != LockReason::Manual && lock_warning_secs > 0 {
+            if let Some(cause) = self.count_down(&context, lock_warning_secs).await {
+                info!("Pending lock cancelled: {:?}", cause);
+                self.record(LockRecord::skipped(
+                    context.clone(),
+                    self.is_dry_run(),
+                    SkipReason::Cancelled { cause },
+                ));
+                let _ = self
+                    .events
+                    .send(LockPipelineEvent::Cancelled(CancelledLock {
+                        context,
+                        cause,
+                    }));
+                return Ok(LockOutcome::Cancelled);
+            }
+            if self.is_locked(after_blank) {
+                info!("Session locked during the countdown, skipping lock");
+                return Ok(LockOutcome::AlreadyLocked);
+            }
+        }
+
+        if self.is_dry_run() {
+            info!("Dry-run mode, lock simulated");
+            self.record(LockRecord::new(context.clone(), true, &Ok(None)));
+            let _ = self.events.send(LockPipelineEvent::Simulated(context));
+            return Ok(LockOutcome::Simulated);
+        }
+
+        let config = self.config.lock().unwrap().clone();
+        let hooks = run_hooks_within(
+            &config.pre_lock_hooks,
+            HookEvent::PreLock,
+            &context,
+            PRE_LOCK_HOOKS_BUDGET,
+        )
+        .await;
+        let result = run_lock_backends(
+            config.custom_lock_command.as_ref(),
+            config.pinned_lock_method,
+            config.lock_all_sessions,
+        )
+        .await;
+        // Clean up secrets even if the screen could not be locked
+        let hygiene_errors = run_away_hygiene(&config.away_hygiene).await;
+        self.record(LockRecord {
+            hooks,
+            hygiene_errors,
+            ..LockRecord::new(context.clone(), false, &result.clone().map(Some))
+        });
+        result?;
+        if context.reason != LockReason::Manual {
+            *self.locked_away.lock().unwrap() = Some(context.clone());
+        }
+        if !self.monitor.is_watching() {
+            return Ok(LockOutcome::Locked);
+        }
+        if !self.monitor.wait_for(true, LOCK_CONFIRM_TIMEOUT).await {
+            warn!("Lock requested but the session did not report being locked");
+        } else if context.reason != LockReason::Manual && config.auto_unlock.is_some() {
+            *self.unlock_candidate.lock().unwrap() = Some(UnlockCandidate::new(
+                context,
+                self.monitor.lock_generation(),
+                Instant::now(),
+            ));
+        }
+        Ok(LockOutcome::Locked)
+    }
+
+    /// Returns whether the session may be unlocked automatically as the device returns
+    pub fn may_auto_unlock(&self) -> bool {
+        self.unlock_candidate.lock().unwrap().is_some()
+    }
+
+    /// Takes a reading of the returning device into account, unlocking the session once the
+    /// device stayed close for the dwell period. Returns whether the session was unlocked.
+    pub async fn auto_unlock(&self, rssi: i16) -> Result<bool, String> {
+        let settings = self.config.lock().unwrap().auto_unlock.clone();
+        let context = {
+            let mut candidate = self.unlock_candidate.lock().unwrap();
+            let (Some(settings), Some(current)) = (settings, candidate.as_mut()) else {
+                candidate.take();
+                return Ok(false);
+            };
+            // Only undo the lock performed by the pipeline, while it is still in place
+            if !self.monitor.is_locked()
+                || self.monitor.lock_generation() != current.lock_generation
+            {
+                candidate.take();
+                return Ok(false);
+            }
+            match current.observe(&settings, rssi, Instant::now()) {
+                Dwell::Waiting => return Ok(false),
+                Dwell::Expired => {
+                    info!("Device returned too late for automatic unlock");
+                    candidate.take();
+                    return Ok(false);
+                }
+                Dwell::Ready => candidate.take().map(|candidate| candidate.context),
+            }
+        };
+        let Some(mut context) = context else {
+            return Ok(false);
+        };
+        context.rssi = Some(rssi);
+        context.filtered_rssi = Some(rssi);
+
+        info!("Device stayed close, unlocking session");
+        let result = self.unlock_session().await;
+        self.record(LockRecord {
+            event: AuditEvent::Unlock,
+            ..LockRecord::new(context.clone(), false, &result.clone().map(|_| None))
+        });
+        result?;
+        let _ = self.events.send(LockPipelineEvent::AutoUnlocked(context));
+        Ok(true)
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn unlock_session(&self) -> Result<(), String> {
+        let system_bus = match &self.system_bus {
+            Some(system_bus) => system_bus.clone(),
+            None => zbus::Connection::system()
+                .await
+                .map_err(|e| format!("Failed to connect to system bus: {}", e))?,
+        };
+        crate::dbus_lock::unlock_logind_session(&system_bus).await
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn unlock_session(&self) -> Result<(), String> {
+        Err("Automatic unlock is not supported on this platform".to_string())
+    }
+
+    /// Runs the on-return hooks for the automatic lock the device returned from
+    fn spawn_return_hooks(&self, mut context: LockContext, rssi: Option<i16>) {
+        let hooks = self.config.lock().unwrap().on_return_hooks.clone();
+        if hooks.is_empty() {
+            return;
+        }
+        context.rssi = rssi;
+        context.filtered_rssi = rssi;
+        let pipeline = self.clone();
+        tokio::spawn(async move {
+            let results = run_hooks(&hooks, HookEvent::Return, &context).await;
+            let success = results.iter().all(|result| result.success);
+            pipeline.record(LockRecord {
+                event: AuditEvent::Return,
+                success,
+                hooks: results,
+                ..LockRecord::new(context, false, &Ok(None))
+            });
+        });
+    }
+
+    fn record(&self, record: LockRecord) {
+        if let Some(audit_log) = &self.audit_log {
+            if let Err(e) = audit_log.append(&record) {
+                error!("Error recording lock decision: {}", e);
+            }
+        }
+    }
+}
+
+/// Waits for the session to be unlocked
+async fn session_unlocked(lock_states: &mut broadcast::Receiver<LockStateEvent>) {
+    loop {
+        match lock_states.recv().await {
+            Ok(LockStateEvent::Unlocked) => return,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+/// Emits a Tauri event for every received pipeline event
+pub async fn forward_lock_pipeline_events(
+    app_handle: AppHandle,
+    mut events: broadcast::Receiver<LockPipelineEvent>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => event.emit(&app_handle),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Cancels the pending lock, returning `false` if there is none
+#[tauri::command]
+pub fn cancel_pending_lock(pipeline: tauri::State<'_, LockPipeline>) -> bool {
+    pipeline.cancel_pending_lock(PendingLockCancel::User)
+}
+
+/// Returns whether locks are only simulated
+#[tauri::command]
+pub fn is_dry_run(pipeline: tauri::State<'_, LockPipeline>) -> bool {
+    pipeline.is_dry_run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::CalendarSettings;
+    use crate::schedule::{ScheduleException, ScheduleWindow};
+
+    fn over_delta_context() -> LockContext {
+        LockContext {
+            reason: LockReason::OverDelta,
+            device_id: Some("test-device-123".to_string()),
+            rssi: Some(-80),
+            filtered_rssi: Some(-80),
+            baseline_rssi: Some(-50),
+            diff_rssi: Some(-30),
+            rssi_delta_max: Some(15),
+        }
+    }
+
+    fn dry_run_settings() -> Settings {
+        Settings {
+            dry_run: true,
+            // Would fail the lock if a backend was invoked
+            custom_lock_command: Some(CustomLockCommand {
+                argv: vec!["lock-4-me-missing-locker".to_string()],
+                env: Default::default(),
+                timeout_secs: None,
+                exclusive: true,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dry_run_from_settings_or_flag() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        assert!(!pipeline.is_dry_run());
+        pipeline.apply_settings(&dry_run_settings());
+        assert!(pipeline.is_dry_run());
+        pipeline.apply_settings(&Settings::default());
+        assert!(!pipeline.is_dry_run());
+
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), true);
+        pipeline.apply_settings(&Settings::default());
+        assert!(pipeline.is_dry_run(), "The flag overrides the settings");
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_simulates_lock() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&dry_run_settings());
+        let mut events = pipeline.subscribe();
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            LockPipelineEvent::Simulated(over_delta_context())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_outside_schedule_skips_automatic_locks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let audit_log = LockAuditLog::in_dir(dir.path());
+        let pipeline =
+            LockPipeline::new(LockStateMonitor::new(), false).with_audit_log(audit_log.clone());
+        // Active every day but the days around today
+        let today = chrono::Utc::now().date_naive();
+        let schedule = Schedule {
+            time_zone: Some("UTC".to_string()),
+            windows: vec![ScheduleWindow {
+                days: vec![
+                    chrono::Weekday::Mon,
+                    chrono::Weekday::Tue,
+                    chrono::Weekday::Wed,
+                    chrono::Weekday::Thu,
+                    chrono::Weekday::Fri,
+                    chrono::Weekday::Sat,
+                    chrono::Weekday::Sun,
+                ],
+                start: chrono::NaiveTime::MIN,
+                end: chrono::NaiveTime::MIN,
+            }],
+            exceptions: [today.pred_opt(), Some(today), today.succ_opt()]
+                .into_iter()
+                .flatten()
+                .map(|date| ScheduleException {
+                    date,
+                    active: false,
+                })
+                .collect(),
+        };
+        assert!(schedule.validate().is_empty());
+        pipeline.apply_settings(&Settings {
+            schedule: Some(schedule),
+            ..dry_run_settings()
+        });
+        let mut events = pipeline.subscribe();
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+        assert_eq!(outcome, Ok(LockOutcome::OutsideSchedule));
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+        assert_eq!(outcome, Ok(LockOutcome::OutsideSchedule));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            LockPipelineEvent::OutsideSchedule(over_delta_context())
+        );
+        assert!(
+            events.try_recv().is_err(),
+            "Skipped locks are reported once"
+        );
+        assert_eq!(
+            pipeline.schedule_state().map(|state| state.active),
+            Some(false)
+        );
+        let records = audit_log.query(None, None, None).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].skipped, Some(SkipReason::OutsideSchedule));
+        assert!(records[0].error.is_none());
+
+        let outcome = pipeline
+            .request_lock(LockContext::new(LockReason::Manual))
+            .await;
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+    }
+
+    #[tokio::test]
+    async fn test_calendar_event_suppresses_or_relaxes_automatic_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("work.ics");
+        let now = chrono::Utc::now();
+        let format = "%Y%m%dT%H%M%SZ";
+        std::fs::write(
+            &path,
+            format!(
+                "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:now\r\nSUMMARY:Product demo\r\n\
+                 DTSTART:{}\r\nDTEND:{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+                (now - chrono::Duration::hours(1)).format(format),
+                (now + chrono::Duration::hours(1)).format(format)
+            ),
+        )
+        .unwrap();
+        let calendar = CalendarSettings {
+            files: vec![path],
+            keywords: vec!["demo".to_string()],
+            action: CalendarAction::Suppress,
+        };
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&Settings {
+            calendar: Some(calendar.clone()),
+            ..dry_run_settings()
+        });
+        let mut events = pipeline.subscribe();
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+        assert_eq!(outcome, Ok(LockOutcome::DuringCalendarEvent));
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+        assert_eq!(outcome, Ok(LockOutcome::DuringCalendarEvent));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            LockPipelineEvent::DuringCalendarEvent(CalendarSkip {
+                context: over_delta_context(),
+                event: "Product demo".to_string(),
+            })
+        );
+        assert!(
+            events.try_recv().is_err(),
+            "Skipped locks are reported once"
+        );
+        let outcome = pipeline
+            .request_lock(LockContext::new(LockReason::Manual))
+            .await;
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+
+        pipeline.apply_settings(&Settings {
+            calendar: Some(CalendarSettings {
+                action: CalendarAction::Relax { warning_secs: 120 },
+                ..calendar
+            }),
+            lock_warning_secs: 10,
+            ..dry_run_settings()
+        });
+        assert_eq!(pipeline.lock_warning_secs(), 120);
+    }
+
+    #[tokio::test]
+    async fn test_already_locked_skips_lock() {
+        let monitor = LockStateMonitor::new();
+        monitor.set_locked(true);
+        let pipeline = LockPipeline::new(monitor, true);
+        let mut events = pipeline.subscribe();
+
+        let outcome = pipeline
+            .request_lock(LockContext::new(LockReason::Manual))
+            .await;
+
+        assert_eq!(outcome, Ok(LockOutcome::AlreadyLocked));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lock_runs_backends_outside_dry_run() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        let mut settings = dry_run_settings();
+        settings.dry_run = false;
+        pipeline.apply_settings(&settings);
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+
+        assert!(outcome.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_event_name() {
+        let event = LockPipelineEvent::Simulated(LockContext::new(LockReason::Timeout));
+        assert_eq!(event.event_name(), "lock-simulated");
+    }
+
+    #[test]
+    fn test_context_serialization() {
+        let json = serde_json::to_value(over_delta_context()).unwrap();
+        assert_eq!(json["reason"], "over_delta");
+        assert_eq!(json["device_id"], "test-device-123");
+        assert_eq!(json["rssi"], -80);
+        assert_eq!(json["baseline_rssi"], -50);
+        assert_eq!(json["diff_rssi"], -30);
+        assert_eq!(json["rssi_delta_max"], 15);
+    }
+
+    #[tokio::test]
+    async fn test_lock_decisions_are_recorded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let audit_log = LockAuditLog::in_dir(dir.path());
+        let pipeline =
+            LockPipeline::new(LockStateMonitor::new(), false).with_audit_log(audit_log.clone());
+        let mut settings = dry_run_settings();
+        pipeline.apply_settings(&settings);
+        pipeline.request_lock(over_delta_context()).await.unwrap();
+        settings.dry_run = false;
+        pipeline.apply_settings(&settings);
+        let _ = pipeline.request_lock(over_delta_context()).await;
+
+        let records = audit_log.query(None, None, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].dry_run);
+        assert!(!records[0].success);
+        assert!(records[0].error.as_ref().unwrap().contains("not found"));
+        assert!(records[1].dry_run);
+        assert!(records[1].success);
+        assert_eq!(records[1].context, over_delta_context());
+    }
+
+    fn warning_settings() -> Settings {
+        Settings {
+            lock_warning_secs: 1,
+            ..dry_run_settings()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_countdown_ends_in_lock() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&warning_settings());
+        let mut events = pipeline.subscribe();
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            LockPipelineEvent::Pending(PendingLock {
+                context: over_delta_context(),
+                countdown_secs: 1,
+            })
+        );
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            LockPipelineEvent::Simulated(_)
+        ));
+        assert!(!pipeline.has_pending_lock());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_lock() {
+        for cause in [PendingLockCancel::User, PendingLockCancel::DeviceReturned] {
+            let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+            let mut settings = warning_settings();
+            settings.lock_warning_secs = 60;
+            pipeline.apply_settings(&settings);
+            let mut events = pipeline.subscribe();
+
+            let request = tokio::spawn({
+                let pipeline = pipeline.clone();
+                async move { pipeline.request_lock(over_delta_context()).await }
+            });
+            assert!(matches!(
+                events.recv().await.unwrap(),
+                LockPipelineEvent::Pending(_)
+            ));
+            assert!(pipeline.cancel_pending_lock(cause));
+
+            assert_eq!(request.await.unwrap(), Ok(LockOutcome::Cancelled));
+            assert_eq!(
+                events.recv().await.unwrap(),
+                LockPipelineEvent::Cancelled(CancelledLock {
+                    context: over_delta_context(),
+                    cause,
+                })
+            );
+            assert!(!pipeline.cancel_pending_lock(cause));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manual_lock_skips_countdown() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        let mut settings = warning_settings();
+        settings.lock_warning_secs = 60;
+        pipeline.apply_settings(&settings);
+
+        let outcome = pipeline
+            .request_lock(LockContext::new(LockReason::Manual))
+            .await;
+
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+    }
+
+    fn plan(steps: &[(AwayAction, u64)]) -> Settings {
+        Settings {
+            away_action_plan: Some(AwayActionPlan {
+                steps: steps
+                    .iter()
+                    .map(|&(action, delay_secs)| crate::away_actions::AwayStep {
+                        action,
+                        delay_secs,
+                    })
+                    .collect(),
+            }),
+            ..dry_run_settings()
+        }
+    }
+
+    async fn next_event(events: &mut broadcast::Receiver<LockPipelineEvent>) -> LockPipelineEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("Timed out waiting for pipeline event")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_away_plan_runs_steps_in_order() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&plan(&[(AwayAction::Lock, 1), (AwayAction::Notify, 0)]));
+        let mut events = pipeline.subscribe();
+
+        let request = tokio::spawn({
+            let pipeline = pipeline.clone();
+            async move { pipeline.request_lock(over_delta_context()).await }
+        });
+
+        match next_event(&mut events).await {
+            LockPipelineEvent::AwayAction(event) => {
+                assert_eq!(event.action, AwayAction::Notify);
+                assert!(event.dry_run);
+                assert!(event.success);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        assert!(matches!(
+            next_event(&mut events).await,
+            LockPipelineEvent::Simulated(_)
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            LockPipelineEvent::AwayAction(AwayActionEvent {
+                action: AwayAction::Lock,
+                ..
+            })
+        ));
+
+        // Further decisions while away do not restart the plan
+        assert_eq!(
+            pipeline.request_lock(over_delta_context()).await,
+            Ok(LockOutcome::InProgress)
+        );
+        assert!(pipeline.is_away());
+        assert!(pipeline.device_returned(None));
+        assert_eq!(request.await.unwrap(), Ok(LockOutcome::PlanCompleted));
+        assert!(!pipeline.is_away());
+    }
+
+    #[tokio::test]
+    async fn test_device_return_cancels_away_plan() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&plan(&[(AwayAction::Notify, 0), (AwayAction::Lock, 60)]));
+        let mut events = pipeline.subscribe();
+
+        let request = tokio::spawn({
+            let pipeline = pipeline.clone();
+            async move { pipeline.request_lock(over_delta_context()).await }
+        });
+        assert!(matches!(
+            next_event(&mut events).await,
+            LockPipelineEvent::AwayAction(_)
+        ));
+
+        assert!(pipeline.device_returned(None));
+        assert_eq!(request.await.unwrap(), Ok(LockOutcome::Cancelled));
+        assert_eq!(
+            next_event(&mut events).await,
+            LockPipelineEvent::AwayPlanCancelled(over_delta_context())
+        );
+        assert!(!pipeline.device_returned(None));
+    }
+
+    #[tokio::test]
+    async fn test_unlock_ends_away_plan() {
+        let monitor = LockStateMonitor::new();
+        let pipeline = LockPipeline::new(monitor.clone(), false);
+        pipeline.apply_settings(&plan(&[(AwayAction::Lock, 0)]));
+        let mut events = pipeline.subscribe();
+
+        let request = tokio::spawn({
+            let pipeline = pipeline.clone();
+            async move { pipeline.request_lock(over_delta_context()).await }
+        });
+        assert!(matches!(
+            next_event(&mut events).await,
+            LockPipelineEvent::Simulated(_)
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            LockPipelineEvent::AwayAction(_)
+        ));
+        assert!(pipeline.is_away());
+
+        monitor.set_locked(true);
+        monitor.set_locked(false);
+        assert_eq!(request.await.unwrap(), Ok(LockOutcome::PlanCompleted));
+        assert!(!pipeline.is_away());
+    }
+
+    #[tokio::test]
+    async fn test_manual_lock_ignores_away_plan() {
+        let pipeline = LockPipeline::new(LockStateMonitor::new(), false);
+        pipeline.apply_settings(&plan(&[(AwayAction::Lock, 60)]));
+
+        let outcome = pipeline
+            .request_lock(LockContext::new(LockReason::Manual))
+            .await;
+
+        assert_eq!(outcome, Ok(LockOutcome::Simulated));
+        assert!(!pipeline.is_away());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_are_recorded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let audit_log = LockAuditLog::in_dir(dir.path());
+        let pipeline =
+            LockPipeline::new(LockStateMonitor::new(), false).with_audit_log(audit_log.clone());
+        let hook = |script: &str| Hook {
+            argv: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            env: Default::default(),
+            timeout_secs: None,
+        };
+        pipeline.apply_settings(&Settings {
+            custom_lock_command: Some(CustomLockCommand {
+                argv: vec!["true".to_string()],
+                env: Default::default(),
+                timeout_secs: None,
+                exclusive: true,
+            }),
+            pre_lock_hooks: vec![hook("exit 3")],
+            on_return_hooks: vec![hook(r#"test "$LOCK_4_ME_RSSI" = -40"#)],
+            ..Default::default()
+        });
+
+        let outcome = pipeline.request_lock(over_delta_context()).await;
+        assert_eq!(outcome, Ok(LockOutcome::Locked));
+        pipeline.device_returned(Some(-40));
+
+        let mut records = Vec::new();
+        for _ in 0..50 {
+            records = audit_log.query(None, None, None).unwrap();
+            if records.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(records.len(), 2, "Return hooks should be recorded");
+
+        let returned = &records[0];
+        assert_eq!(returned.event, AuditEvent::Return);
+        assert!(
+            returned.success,
+            "Return hook should succeed: {:?}",
+            returned
+        );
+        assert_eq!(returned.context.rssi, Some(-40));
+
+        let locked = &records[1];
+        assert_eq!(locked.event, AuditEvent::Lock);
+        assert!(locked.success, "A failing hook does not prevent the lock");
+        assert_eq!(locked.backend, Some(LockMethod::Custom));
+        assert_eq!(locked.hooks[0].exit_code, Some(3));
+    }
+}
@@ -0,0 +1,335 @@
+use log::info;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+#[cfg(target_os = "linux")]
+use futures::stream::StreamExt;
+#[cfg(target_os = "linux")]
+use log::warn;
+
+/// A change of the session lock state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockStateEvent {
+    Locked,
+    Unlocked,
+}
+
+impl LockStateEvent {
+    /// Name of the Tauri event emitted for this change
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            Self::Locked => "session-locked",
+            Self::Unlocked => "session-unlocked",
+        }
+    }
+}
+
+/// Tracks whether the desktop session is currently locked.
+///
+/// On Linux the state follows logind's `LockedHint` for the graphical session. The
+/// `ActiveChanged` signal of the session bus screen saver, which also reports a blanked screen,
+/// is only used when logind is unavailable. Cloning the monitor shares the same state.
+#[derive(Clone)]
+pub struct LockStateMonitor {
+    locked: Arc<AtomicBool>,
+    watching: Arc<AtomicBool>,
+    logind_watched: Arc<AtomicBool>,
+    screensaver_active: Arc<AtomicBool>,
+    lock_generation: Arc<AtomicU64>,
+    events: broadcast::Sender<LockStateEvent>,
+}
+
+impl Default for LockStateMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockStateMonitor {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        LockStateMonitor {
+            locked: Arc::new(AtomicBool::new(false)),
+            watching: Arc::new(AtomicBool::new(false)),
+            logind_watched: Arc::new(AtomicBool::new(false)),
+            screensaver_active: Arc::new(AtomicBool::new(false)),
+            lock_generation: Arc::new(AtomicU64::new(0)),
+            events,
+        }
+    }
+
+    /// Returns whether the session is currently known to be locked
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether at least one lock state source is being watched
+    pub fn is_watching(&self) -> bool {
+        self.watching.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the screen saver is active, which may only mean the screen is blanked
+    pub fn is_screensaver_active(&self) -> bool {
+        self.screensaver_active.load(Ordering::SeqCst)
+    }
+
+    /// Returns how many times the session got locked, telling apart successive locks
+    pub fn lock_generation(&self) -> u64 {
+        self.lock_generation.load(Ordering::SeqCst)
+    }
+
+    /// Subscribes to lock and unlock events
+    pub fn subscribe(&self) -> broadcast::Receiver<LockStateEvent> {
+        self.events.subscribe()
+    }
+
+    /// Records the current lock state, broadcasting an event only when it changes
+    pub fn set_locked(&self, locked: bool) {
+        if self.locked.swap(locked, Ordering::SeqCst) == locked {
+            return;
+        }
+        let event = if locked {
+            self.lock_generation.fetch_add(1, Ordering::SeqCst);
+            LockStateEvent::Locked
+        } else {
+            LockStateEvent::Unlocked
+        };
+        info!("Session lock state changed: {:?}", event);
+        let _ = self.events.send(event);
+    }
+
+    /// Records whether the screen saver is active, taking it as the lock state unless logind
+    /// reports the lock state
+    fn set_screensaver_active(&self, active: bool) {
+        self.screensaver_active.store(active, Ordering::SeqCst);
+        if !self.logind_watched.load(Ordering::SeqCst) {
+            self.set_locked(active);
+        }
+    }
+
+    /// Waits until the session reaches the given lock state, returning `false` on timeout
+    pub async fn wait_for(&self, locked: bool, max_wait: Duration) -> bool {
+        let mut events = self.subscribe();
+        if self.is_locked() == locked {
+            return true;
+        }
+        let expected = if locked {
+            LockStateEvent::Locked
+        } else {
+            LockStateEvent::Unlocked
+        };
+        timeout(max_wait, async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event == expected => return true,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return false,
+                }
+            }
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    /// Starts watching the lock state of the graphical logind session on the system bus
+    #[cfg(target_os = "linux")]
+    pub async fn watch_logind(&self, system_bus: &zbus::Connection) -> Result<(), String> {
+        let path = crate::logind::graphical_session_path(system_bus).await?;
+        let session = crate::logind::session_proxy(system_bus, path).await?;
+        let locked = session
+            .locked_hint()
+            .await
+            .map_err(|e| format!("Failed to read LockedHint: {}", e))?;
+        self.set_locked(locked);
+        self.watching.store(true, Ordering::SeqCst);
+        self.logind_watched.store(true, Ordering::SeqCst);
+
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut changes = session.receive_locked_hint_changed().await;
+            while let Some(change) = changes.next().await {
+                if let Ok(locked) = change.get().await {
+                    monitor.set_locked(locked);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Starts watching the `ActiveChanged` signal of the session bus screen savers, which only
+    /// sets the lock state when logind is not watched
+    #[cfg(target_os = "linux")]
+    pub async fn watch_screensaver(&self, session_bus: &zbus::Connection) -> Result<(), String> {
+        let mut watched_services: Vec<&str> = Vec::new();
+        for (service, path, interface) in crate::dbus_lock::SCREENSAVER_SERVICES {
+            if watched_services.contains(&service) {
+                continue;
+            }
+            let Ok(proxy) = zbus::Proxy::new(session_bus, service, path, interface).await else {
+                continue;
+            };
+            let active: bool = match proxy.call("GetActive", &()).await {
+                Ok(active) => active,
+                Err(_) => continue,
+            };
+            self.set_screensaver_active(active);
+            let mut signals = proxy.receive_signal("ActiveChanged").await.map_err(|e| {
+                format!("Failed to subscribe to {}.ActiveChanged: {}", interface, e)
+            })?;
+            watched_services.push(service);
+            self.watching.store(true, Ordering::SeqCst);
+
+            let monitor = self.clone();
+            tokio::spawn(async move {
+                // Keep the proxy alive for as long as the signal stream is read
+                let _proxy = proxy;
+                while let Some(message) = signals.next().await {
+                    if let Ok(active) = message.body().deserialize::<bool>() {
+                        monitor.set_screensaver_active(active);
+                    }
+                }
+            });
+        }
+        if watched_services.is_empty() {
+            return Err("No screen saver service found on the session bus".to_string());
+        }
+        Ok(())
+    }
+
+    /// Starts watching every available lock state source, logging the ones that are unavailable
+    pub async fn start(&self) {
+        #[cfg(target_os = "linux")]
+        {
+            match zbus::Connection::system().await {
+                Ok(system_bus) => {
+                    if let Err(e) = self.watch_logind(&system_bus).await {
+                        warn!("Lock state from logind unavailable: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to connect to system bus: {}", e),
+            }
+            match zbus::Connection::session().await {
+                Ok(session_bus) => {
+                    if let Err(e) = self.watch_screensaver(&session_bus).await {
+                        warn!("Lock state from screen saver unavailable: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to connect to session bus: {}", e),
+            }
+        }
+    }
+}
+
+/// Emits `session-locked` / `session-unlocked` Tauri events for every received lock state change
+pub async fn forward_lock_state_events(
+    app_handle: AppHandle,
+    mut events: broadcast::Receiver<LockStateEvent>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app_handle.emit(event.event_name(), ());
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Returns whether the session is currently locked
+#[tauri::command]
+pub fn is_session_locked(monitor: tauri::State<'_, LockStateMonitor>) -> bool {
+    monitor.is_locked()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor_starts_unlocked() {
+        let monitor = LockStateMonitor::new();
+        assert!(!monitor.is_locked());
+        assert!(!monitor.is_watching());
+    }
+
+    #[test]
+    fn test_lock_generation_counts_locks() {
+        let monitor = LockStateMonitor::new();
+        assert_eq!(monitor.lock_generation(), 0);
+        monitor.set_locked(true);
+        monitor.set_locked(true);
+        monitor.set_locked(false);
+        assert_eq!(monitor.lock_generation(), 1);
+        monitor.set_locked(true);
+        assert_eq!(monitor.lock_generation(), 2);
+    }
+
+    #[test]
+    fn test_set_locked_emits_on_change_only() {
+        let monitor = LockStateMonitor::new();
+        let mut events = monitor.subscribe();
+
+        monitor.set_locked(true);
+        monitor.set_locked(true);
+        monitor.set_locked(false);
+
+        assert_eq!(events.try_recv().unwrap(), LockStateEvent::Locked);
+        assert_eq!(events.try_recv().unwrap(), LockStateEvent::Unlocked);
+        assert!(events.try_recv().is_err(), "Repeated state should not emit");
+    }
+
+    #[test]
+    fn test_clones_share_state() {
+        let monitor = LockStateMonitor::new();
+        let clone = monitor.clone();
+        clone.set_locked(true);
+        assert!(monitor.is_locked());
+    }
+
+    #[test]
+    fn test_event_names() {
+        assert_eq!(LockStateEvent::Locked.event_name(), "session-locked");
+        assert_eq!(LockStateEvent::Unlocked.event_name(), "session-unlocked");
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        assert_eq!(
+            serde_json::to_string(&LockStateEvent::Locked).unwrap(),
+            "\"locked\""
+        );
+        assert_eq!(
+            serde_json::to_string(&LockStateEvent::Unlocked).unwrap(),
+            "\"unlocked\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_returns_immediately_when_in_state() {
+        let monitor = LockStateMonitor::new();
+        assert!(monitor.wait_for(false, Duration::from_millis(10)).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_observes_change() {
+        let monitor = LockStateMonitor::new();
+        let clone = monitor.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            clone.set_locked(true);
+        });
+        assert!(monitor.wait_for(true, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_times_out() {
+        let monitor = LockStateMonitor::new();
+        assert!(!monitor.wait_for(true, Duration::from_millis(10)).await);
+    }
+}
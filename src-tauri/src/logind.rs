@@ -0,0 +1,262 @@
+use zbus::proxy;
+use zbus::zvariant::{OwnedFd, OwnedObjectPath};
+
+/// Proxy for the systemd-logind manager object on the system bus.
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+pub trait LoginManager {
+    /// Returns the object path of the session the given process belongs to
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    /// Lists the sessions as (id, uid, user name, seat, object path)
+    #[allow(clippy::type_complexity)]
+    fn list_sessions(&self) -> zbus::Result<Vec<(String, u32, String, String, OwnedObjectPath)>>;
+
+    /// Suspends the machine
+    fn suspend(&self, interactive: bool) -> zbus::Result<()>;
+
+    /// Takes an inhibitor lock, held until the returned file descriptor is closed
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    /// Sent with `true` right before the machine sleeps and with `false` after it resumes
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Proxy for a single systemd-logind session object.
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait LoginSession {
+    /// Asks the session's screen locker to lock the session
+    fn lock(&self) -> zbus::Result<()>;
+
+    /// Asks the session's screen locker to unlock the session
+    fn unlock(&self) -> zbus::Result<()>;
+
+    /// Whether the session's screen locker reports the session as locked
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+
+    /// Whether the desktop reports the session as idle
+    #[zbus(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    /// When `IdleHint` last changed, in microseconds since the epoch
+    #[zbus(property)]
+    fn idle_since_hint(&self) -> zbus::Result<u64>;
+
+    /// Session identifier, as taken by `loginctl`
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    /// Session type, such as `x11`, `wayland` or `tty`
+    #[zbus(property, name = "Type")]
+    fn session_type(&self) -> zbus::Result<String>;
+
+    /// Session class, `user` for user sessions
+    #[zbus(property)]
+    fn class(&self) -> zbus::Result<String>;
+
+    /// Whether the session is the active one of its seat
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+
+    /// Whether the session is remote
+    #[zbus(property)]
+    fn remote(&self) -> zbus::Result<bool>;
+}
+
+/// Session types with a display the screen locker can lock
+const GRAPHICAL_SESSION_TYPES: [&str; 3] = ["x11", "wayland", "mir"];
+
+/// A session of the current user, as listed by logind
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserSession {
+    pub id: String,
+    pub path: OwnedObjectPath,
+    pub session_type: String,
+    pub class: String,
+    pub active: bool,
+    pub remote: bool,
+}
+
+impl UserSession {
+    /// Whether this is a graphical session of the user, rather than a greeter or a console
+    pub fn is_graphical(&self) -> bool {
+        self.class == "user" && GRAPHICAL_SESSION_TYPES.contains(&self.session_type.as_str())
+    }
+}
+
+/// Picks the session to lock among the user's sessions: a graphical one, preferring active and
+/// then local sessions
+pub fn pick_graphical_session(sessions: &[UserSession]) -> Option<&UserSession> {
+    sessions
+        .iter()
+        .filter(|session| session.is_graphical())
+        .min_by_key(|session| (!session.active, session.remote))
+}
+
+/// Returns the uid the current process runs as
+fn current_uid() -> Result<u32, String> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata("/proc/self")
+        .map(|metadata| metadata.uid())
+        .map_err(|e| format!("Failed to read current uid: {}", e))
+}
+
+/// Resolves the logind session object path of the current process.
+pub async fn current_session_path(
+    system_bus: &zbus::Connection,
+) -> Result<OwnedObjectPath, String> {
+    let manager = LoginManagerProxy::new(system_bus)
+        .await
+        .map_err(|e| format!("Failed to reach logind: {}", e))?;
+    manager
+        .get_session_by_pid(std::process::id())
+        .await
+        .map_err(|e| format!("Failed to resolve logind session: {}", e))
+}
+
+/// Lists the sessions of the current user through `ListSessions`.
+pub async fn user_sessions(system_bus: &zbus::Connection) -> Result<Vec<UserSession>, String> {
+    let uid = current_uid()?;
+    let manager = LoginManagerProxy::new(system_bus)
+        .await
+        .map_err(|e| format!("Failed to reach logind: {}", e))?;
+    let listed = manager
+        .list_sessions()
+        .await
+        .map_err(|e| format!("Failed to list logind sessions: {}", e))?;
+
+    let mut sessions = Vec::new();
+    for (id, session_uid, _, _, path) in listed {
+        if session_uid != uid {
+            continue;
+        }
+        let session = session_proxy(system_bus, path.clone()).await?;
+        sessions.push(UserSession {
+            id,
+            path,
+            session_type: session.session_type().await.unwrap_or_default(),
+            class: session.class().await.unwrap_or_default(),
+            active: session.active().await.unwrap_or(false),
+            remote: session.remote().await.unwrap_or(false),
+        });
+    }
+    Ok(sessions)
+}
+
+/// Resolves the graphical logind session of the current user.
+///
+/// The session of the current process is used unless it is known not to be graphical. When it
+/// is not graphical, or the process belongs to no session (as when started by some autostart
+/// mechanisms), the user's sessions are listed and one is picked with
+/// [`pick_graphical_session`].
+pub async fn graphical_session_path(
+    system_bus: &zbus::Connection,
+) -> Result<OwnedObjectPath, String> {
+    let mut errors = Vec::new();
+    match current_session_path(system_bus).await {
+        Ok(path) => {
+            let session = session_proxy(system_bus, path.clone()).await?;
+            match session.session_type().await {
+                Ok(session_type) if !GRAPHICAL_SESSION_TYPES.contains(&session_type.as_str()) => {
+                    errors.push(format!(
+                        "session of the process is of type '{}'",
+                        session_type
+                    ))
+                }
+                _ => return Ok(path),
+            }
+        }
+        Err(e) => errors.push(e),
+    }
+    match user_sessions(system_bus).await {
+        Ok(sessions) => match pick_graphical_session(&sessions) {
+            Some(session) => return Ok(session.path.clone()),
+            None => errors.push("no graphical session of the user".to_string()),
+        },
+        Err(e) => errors.push(e),
+    }
+    Err(format!(
+        "Failed to resolve logind session: {}",
+        errors.join("; ")
+    ))
+}
+
+/// Builds a proxy for the logind session at the given object path.
+pub async fn session_proxy<'a>(
+    system_bus: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> Result<LoginSessionProxy<'a>, String> {
+    LoginSessionProxy::builder(system_bus)
+        .path(path)
+        .map_err(|e| format!("Invalid logind session path: {}", e))?
+        .build()
+        .await
+        .map_err(|e| format!("Failed to reach logind session: {}", e))
+}
+
+/// Suspends the machine through logind, without asking for authentication interactively.
+pub async fn suspend(system_bus: &zbus::Connection) -> Result<(), String> {
+    let manager = LoginManagerProxy::new(system_bus)
+        .await
+        .map_err(|e| format!("Failed to reach logind: {}", e))?;
+    manager
+        .suspend(false)
+        .await
+        .map_err(|e| format!("Failed to suspend: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(
+        id: &str,
+        session_type: &str,
+        class: &str,
+        active: bool,
+        remote: bool,
+    ) -> UserSession {
+        UserSession {
+            id: id.to_string(),
+            path: OwnedObjectPath::try_from(format!("/org/freedesktop/login1/session/{}", id))
+                .unwrap(),
+            session_type: session_type.to_string(),
+            class: class.to_string(),
+            active,
+            remote,
+        }
+    }
+
+    #[test]
+    fn test_pick_graphical_session_prefers_active_local() {
+        let sessions = [
+            session("c1", "tty", "user", true, false),
+            session("greeter", "wayland", "greeter", true, false),
+            session("2", "x11", "user", false, false),
+            session("3", "x11", "user", true, true),
+            session("4", "wayland", "user", true, false),
+        ];
+        assert_eq!(pick_graphical_session(&sessions).unwrap().id, "4");
+        assert_eq!(pick_graphical_session(&sessions[..4]).unwrap().id, "3");
+        assert_eq!(pick_graphical_session(&sessions[..3]).unwrap().id, "2");
+    }
+
+    #[test]
+    fn test_pick_graphical_session_without_graphical_session() {
+        let sessions = [
+            session("c1", "tty", "user", true, false),
+            session("greeter", "x11", "greeter", true, false),
+        ];
+        assert!(pick_graphical_session(&sessions).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::lock_pipeline::{LockPipeline, LockPipelineEvent, PendingLockCancel};
+use futures::stream::StreamExt;
+use log::warn;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use zbus::proxy;
+use zbus::zvariant::Value;
+
+/// Key of the notification action cancelling the pending lock
+pub const CANCEL_ACTION: &str = "cancel";
+
+/// Proxy for the desktop notification service on the session bus.
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+pub trait Notifications {
+    /// Shows a notification, returning its id
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    /// Closes a notification
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    /// Sent when the user picks an action of a notification
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+}
+
+/// Shows a desktop notification telling the user they are considered away
+pub async fn notify_away(session_bus: &zbus::Connection) -> Result<(), String> {
+    let notifications = NotificationsProxy::new(session_bus)
+        .await
+        .map_err(|e| format!("Failed to reach notification service: {}", e))?;
+    notifications
+        .notify(
+            "Lock-4-me",
+            0,
+            "",
+            "Device out of range",
+            "Your device is out of range.",
+            &[],
+            HashMap::new(),
+            -1,
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to show notification: {}", e))
+}
+
+/// Shows a desktop notification with a cancel action while a lock is pending.
+///
+/// Picking the action cancels the pending lock, and the notification is closed when the lock is
+/// cancelled from elsewhere. The notification expires on its own at the end of the countdown.
+/// A plain notification is shown when a lock is skipped because the user is active.
+pub async fn watch_pending_locks(
+    session_bus: &zbus::Connection,
+    pipeline: LockPipeline,
+) -> Result<(), String> {
+    let notifications = NotificationsProxy::new(session_bus)
+        .await
+        .map_err(|e| format!("Failed to reach notification service: {}", e))?;
+    let mut actions = notifications
+        .receive_action_invoked()
+        .await
+        .map_err(|e| format!("Failed to subscribe to notification actions: {}", e))?;
+    let mut events = pipeline.subscribe();
+
+    tokio::spawn(async move {
+        let mut shown: Option<u32> = None;
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(LockPipelineEvent::Pending(pending)) => {
+                        let body = format!(
+                            "Your screen will lock in {} seconds.",
+                            pending.countdown_secs
+                        );
+                        let expire_timeout =
+                            i32::try_from(pending.countdown_secs * 1000).unwrap_or(i32::MAX);
+                        match notifications
+                            .notify(
+                                "Lock-4-me",
+                                shown.unwrap_or(0),
+                                "",
+                                "Locking soon",
+                                &body,
+                                &[CANCEL_ACTION, "Cancel"],
+                                HashMap::new(),
+                                expire_timeout,
+                            )
+                            .await
+                        {
+                            Ok(id) => shown = Some(id),
+                            Err(e) => warn!("Failed to show lock notification: {}", e),
+                        }
+                    }
+                    Ok(LockPipelineEvent::UserActive(_)) => {
+                        if let Err(e) = notifications
+                            .notify(
+                                "Lock-4-me",
+                                0,
+                                "",
+                                "Device out of range",
+                                "Your screen will lock once you stop using the computer.",
+                                &[],
+                                HashMap::new(),
+                                -1,
+                            )
+                            .await
+                        {
+                            warn!("Failed to show activity notification: {}", e);
+                        }
+                    }
+                    Ok(LockPipelineEvent::Cancelled(_)) => {
+                        if let Some(id) = shown.take() {
+                            let _ = notifications.close_notification(id).await;
+                        }
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                Some(action) = actions.next() => {
+                    let Ok(args) = action.args() else {
+                        continue;
+                    };
+                    if Some(args.id) == shown && args.action_key == CANCEL_ACTION {
+                        shown = None;
+                        pipeline.cancel_pending_lock(PendingLockCancel::User);
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use crate::auto_unlock::AutoUnlock;
+use crate::away_actions::AwayActionPlan;
+use crate::hooks::Hook;
+use crate::read_write_settings::{
+    notify_settings_changed, FieldError, FieldErrorCode, Settings, SettingsError, SettingsStore,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+use tauri::menu::{CheckMenuItem, IsMenuItem, Submenu};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+/// Longest accepted profile name, in characters
+pub const MAX_PROFILE_NAME_LEN: usize = 64;
+
+/// Settings held by each profile, which take the values of the active profile
+pub const PROFILE_KEYS: [&str; 8] = [
+    "target_uuid",
+    "rssi_delta_max",
+    "min_idle_secs",
+    "lock_warning_secs",
+    "away_action_plan",
+    "pre_lock_hooks",
+    "on_return_hooks",
+    "auto_unlock",
+];
+
+/// Settings shown in the tray menu
+pub const TRAY_KEYS: [&str; 2] = ["profiles", "active_profile"];
+
+/// Prefix of the ids of the tray menu items switching profile
+pub const PROFILE_MENU_ID_PREFIX: &str = "profile:";
+
+/// Settings that depend on where the user is, such as at home or at the office, saved under a
+/// name so that they can be switched at once
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub target_uuid: String,
+    pub rssi_delta_max: i16,
+    #[serde(default)]
+    pub min_idle_secs: Option<u64>,
+    #[serde(default)]
+    pub lock_warning_secs: u64,
+    #[serde(default)]
+    pub away_action_plan: Option<AwayActionPlan>,
+    #[serde(default)]
+    pub pre_lock_hooks: Vec<Hook>,
+    #[serde(default)]
+    pub on_return_hooks: Vec<Hook>,
+    #[serde(default)]
+    pub auto_unlock: Option<AutoUnlock>,
+}
+
+impl Profile {
+    /// Takes the profile values from the settings
+    pub fn of(settings: &Settings) -> Self {
+        Profile {
+            target_uuid: settings.target_uuid.clone(),
+            rssi_delta_max: settings.rssi_delta_max,
+            min_idle_secs: settings.min_idle_secs,
+            lock_warning_secs: settings.lock_warning_secs,
+            away_action_plan: settings.away_action_plan.clone(),
+            pre_lock_hooks: settings.pre_lock_hooks.clone(),
+            on_return_hooks: settings.on_return_hooks.clone(),
+            auto_unlock: settings.auto_unlock.clone(),
+        }
+    }
+
+    /// Puts the profile values in the settings
+    pub fn apply_to(&self, settings: &mut Settings) {
+        settings.target_uuid = self.target_uuid.clone();
+        settings.rssi_delta_max = self.rssi_delta_max;
+        settings.min_idle_secs = self.min_idle_secs;
+        settings.lock_warning_secs = self.lock_warning_secs;
+        settings.away_action_plan = self.away_action_plan.clone();
+        settings.pre_lock_hooks = self.pre_lock_hooks.clone();
+        settings.on_return_hooks = self.on_return_hooks.clone();
+        settings.auto_unlock = self.auto_unlock.clone();
+    }
+}
+
+/// Keeps the active profile and the settings in effect in sync as settings change from
+/// `current` to `settings`.
+///
+/// Switching profile, or editing the active profile itself, applies the profile to the
+/// settings. Otherwise the settings are saved into the active profile. Returns whether either
+/// was changed.
+pub fn sync_active_profile(current: &Settings, settings: &mut Settings) -> bool {
+    let Some(name) = settings.active_profile.clone() else {
+        return false;
+    };
+    let Some(profile) = settings.profiles.get(&name).cloned() else {
+        return false;
+    };
+    let in_effect = Profile::of(settings);
+    if in_effect == profile {
+        return false;
+    }
+    if current.active_profile != settings.active_profile
+        || current.profiles.get(&name) != Some(&profile)
+    {
+        profile.apply_to(settings);
+    } else {
+        settings.profiles.insert(name, in_effect);
+    }
+    true
+}
+
+/// Checks a profile name, returning why it is not accepted
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Profile name is empty".to_string());
+    }
+    if name.trim() != name {
+        return Err(format!(
+            "Profile name '{}' starts or ends with spaces",
+            name
+        ));
+    }
+    if name.chars().count() > MAX_PROFILE_NAME_LEN {
+        return Err(format!(
+            "Profile name is longer than {} characters",
+            MAX_PROFILE_NAME_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the profiles and the active profile, returning an error for each invalid field.
+///
+/// The values of each profile are checked as the settings would be once it is active, with
+/// fields such as `profiles.office.rssi_delta_max`.
+pub fn validate_profiles(settings: &Settings) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Some(name) = &settings.active_profile {
+        if !settings.profiles.contains_key(name) {
+            errors.push(FieldError::new(
+                "active_profile",
+                FieldErrorCode::InvalidValue,
+                format!("Profile '{}' does not exist", name),
+            ));
+        }
+    }
+    for (name, profile) in &settings.profiles {
+        let field = format!("profiles.{}", name);
+        if let Err(e) = validate_profile_name(name) {
+            errors.push(FieldError::new(field, FieldErrorCode::InvalidValue, e));
+            continue;
+        }
+        if settings.active_profile.as_ref() == Some(name) {
+            // Checked as the settings in effect
+            continue;
+        }
+        let mut candidate = settings.clone();
+        candidate.profiles.clear();
+        candidate.active_profile = None;
+        profile.apply_to(&mut candidate);
+        errors.extend(
+            candidate
+                .validate()
+                .into_iter()
+                .filter(|error| {
+                    PROFILE_KEYS.iter().any(|key| {
+                        error.field == *key
+                            || error.field.starts_with(&format!("{}.", key))
+                            || error.field.starts_with(&format!("{}[", key))
+                    })
+                })
+                .map(|error| FieldError {
+                    field: format!("{}.{}", field, error.field),
+                    ..error
+                }),
+        );
+    }
+    errors
+}
+
+fn profile_error(name: &str, message: String) -> SettingsError {
+    SettingsError::Invalid(vec![FieldError::new(
+        format!("profiles.{}", name),
+        FieldErrorCode::InvalidValue,
+        message,
+    )])
+}
+
+fn missing_profile(name: &str) -> SettingsError {
+    profile_error(name, format!("Profile '{}' does not exist", name))
+}
+
+impl Settings {
+    /// Saves the settings in effect as a new profile, without switching to it
+    pub fn create_profile(&mut self, name: &str) -> Result<(), SettingsError> {
+        validate_profile_name(name).map_err(|e| profile_error(name, e))?;
+        if self.profiles.contains_key(name) {
+            return Err(profile_error(
+                name,
+                format!("Profile '{}' already exists", name),
+            ));
+        }
+        self.profiles.insert(name.to_string(), Profile::of(self));
+        Ok(())
+    }
+
+    /// Renames a profile, which stays active if it was
+    pub fn rename_profile(&mut self, name: &str, new_name: &str) -> Result<(), SettingsError> {
+        validate_profile_name(new_name).map_err(|e| profile_error(new_name, e))?;
+        if name != new_name && self.profiles.contains_key(new_name) {
+            return Err(profile_error(
+                new_name,
+                format!("Profile '{}' already exists", new_name),
+            ));
+        }
+        let profile = self
+            .profiles
+            .remove(name)
+            .ok_or_else(|| missing_profile(name))?;
+        self.profiles.insert(new_name.to_string(), profile);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = Some(new_name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Deletes a profile. Deleting the active profile keeps its values in effect, with no
+    /// profile active.
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), SettingsError> {
+        self.profiles
+            .remove(name)
+            .ok_or_else(|| missing_profile(name))?;
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+        Ok(())
+    }
+
+    /// Makes a profile active, its values taking effect once the settings are saved
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), SettingsError> {
+        if !self.profiles.contains_key(name) {
+            return Err(missing_profile(name));
+        }
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+}
+
+/// Changes the profiles with `change`, then saves and applies the settings
+fn update_profiles<F>(
+    app_handle: &AppHandle,
+    store: &SettingsStore,
+    change: F,
+) -> Result<Settings, SettingsError>
+where
+    F: FnOnce(&mut Settings) -> Result<(), SettingsError>,
+{
+    let change = store.modify(change)?;
+    notify_settings_changed(app_handle, &change);
+    Ok(change.settings)
+}
+
+/// Saves the settings in effect as a new profile
+#[tauri::command]
+pub fn create_profile(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    name: String,
+) -> Result<Settings, SettingsError> {
+    update_profiles(&app_handle, &store, |settings| {
+        settings.create_profile(&name)
+    })
+}
+
+#[tauri::command]
+pub fn rename_profile(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    name: String,
+    new_name: String,
+) -> Result<Settings, SettingsError> {
+    update_profiles(&app_handle, &store, |settings| {
+        settings.rename_profile(&name, &new_name)
+    })
+}
+
+#[tauri::command]
+pub fn delete_profile(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    name: String,
+) -> Result<Settings, SettingsError> {
+    update_profiles(&app_handle, &store, |settings| {
+        settings.delete_profile(&name)
+    })
+}
+
+/// Makes a profile active, the listener and lock pipeline taking its values right away
+#[tauri::command]
+pub fn switch_profile(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    name: String,
+) -> Result<Settings, SettingsError> {
+    update_profiles(&app_handle, &store, |settings| {
+        settings.switch_profile(&name)
+    })
+}
+
+/// Builds the tray submenu switching profile, unless there is no profile
+pub fn profile_menu<R: Runtime, M: Manager<R>>(
+    manager: &M,
+    settings: &Settings,
+) -> tauri::Result<Option<Submenu<R>>> {
+    if settings.profiles.is_empty() {
+        return Ok(None);
+    }
+    let items = settings
+        .profiles
+        .keys()
+        .map(|name| {
+            CheckMenuItem::with_id(
+                manager,
+                format!("{}{}", PROFILE_MENU_ID_PREFIX, name),
+                name,
+                true,
+                settings.active_profile.as_ref() == Some(name),
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let items: Vec<&dyn IsMenuItem<R>> = items.iter().map(|item| item as _).collect();
+    Submenu::with_items(manager, "Profile", true, &items).map(Some)
+}
+
+/// Switches to the profile of a tray menu item, returning false for other items
+pub fn handle_menu_event(app_handle: &AppHandle, id: &str) -> bool {
+    let Some(name) = id.strip_prefix(PROFILE_MENU_ID_PREFIX) else {
+        return false;
+    };
+    let store = app_handle.state::<SettingsStore>();
+    if let Err(e) = update_profiles(app_handle, &store, |settings| settings.switch_profile(name)) {
+        error!("Failed to switch to profile '{}': {}", name, e);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_write_settings::Theme;
+    use tempfile::tempdir;
+
+    const HOME_UUID: &str = "12345678-1234-1234-1234-123456789012";
+    const OFFICE_UUID: &str = "87654321-4321-4321-4321-210987654321";
+
+    fn home_settings() -> Settings {
+        Settings {
+            target_uuid: HOME_UUID.to_string(),
+            rssi_delta_max: 20,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_create_profile_takes_settings_in_effect() {
+        let mut settings = home_settings();
+        settings.create_profile("home").unwrap();
+
+        assert_eq!(settings.profiles["home"], Profile::of(&home_settings()));
+        assert_eq!(settings.active_profile, None);
+        assert!(settings.create_profile("home").is_err());
+        assert!(settings.create_profile(" ").is_err());
+    }
+
+    #[test]
+    fn test_rename_and_delete_active_profile() {
+        let mut settings = home_settings();
+        settings.create_profile("home").unwrap();
+        settings.switch_profile("home").unwrap();
+
+        settings.rename_profile("home", "house").unwrap();
+        assert_eq!(settings.active_profile.as_deref(), Some("house"));
+        assert!(!settings.profiles.contains_key("home"));
+
+        settings.delete_profile("house").unwrap();
+        assert_eq!(settings.active_profile, None);
+        assert_eq!(settings.target_uuid, HOME_UUID);
+        assert!(settings.delete_profile("house").is_err());
+        assert!(settings.switch_profile("house").is_err());
+    }
+
+    #[test]
+    fn test_switching_applies_profile() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store.update(home_settings()).unwrap();
+        store
+            .modify(|settings| settings.create_profile("home"))
+            .unwrap();
+        store
+            .modify(|settings| {
+                settings.create_profile("office")?;
+                let office = settings.profiles.get_mut("office").unwrap();
+                office.target_uuid = OFFICE_UUID.to_string();
+                office.rssi_delta_max = 40;
+                Ok(())
+            })
+            .unwrap();
+
+        let change = store
+            .modify(|settings| settings.switch_profile("office"))
+            .unwrap();
+
+        assert_eq!(
+            change.changed,
+            ["active_profile", "rssi_delta_max", "target_uuid"]
+        );
+        assert_eq!(store.get().target_uuid, OFFICE_UUID);
+        assert_eq!(store.get().rssi_delta_max, 40);
+    }
+
+    #[test]
+    fn test_edits_saved_into_active_profile() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store.update(home_settings()).unwrap();
+        store
+            .modify(|settings| {
+                settings.create_profile("home")?;
+                settings.switch_profile("home")
+            })
+            .unwrap();
+
+        store
+            .patch(&serde_json::json!({"rssi_delta_max": 30, "theme": "light"}))
+            .unwrap();
+        assert_eq!(store.get().profiles["home"].rssi_delta_max, 30);
+
+        // Editing the active profile itself applies it
+        store
+            .patch(&serde_json::json!({"profiles": {"home": {"rssi_delta_max": 35}}}))
+            .unwrap();
+        let settings = store.get();
+        assert_eq!(settings.rssi_delta_max, 35);
+        assert_eq!(settings.theme, Theme::Light);
+    }
+
+    #[test]
+    fn test_validate_profiles() {
+        let mut settings = home_settings();
+        settings.create_profile("office").unwrap();
+        settings.profiles.get_mut("office").unwrap().rssi_delta_max = 0;
+        settings.active_profile = Some("travel".to_string());
+
+        let fields: Vec<String> = settings
+            .validate()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+
+        assert_eq!(fields, ["active_profile", "profiles.office.rssi_delta_max"]);
+    }
+}
@@ -1,48 +1,863 @@
+use crate::auto_unlock::AutoUnlock;
+use crate::away_actions::AwayActionPlan;
+use crate::away_hygiene::AwayHygiene;
+use crate::calendar::CalendarSettings;
+use crate::hooks::Hook;
+use crate::listen_bluetooth::BluetoothListenerHandle;
+use crate::lock_methods::LockMethod;
+use crate::lock_pipeline::LockPipeline;
+use crate::profiles::{sync_active_profile, validate_profiles, Profile};
+use crate::schedule::Schedule;
+use crate::settings_migration::{migrate, CURRENT_SCHEMA_VERSION};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::fs::{create_dir_all, File};
+use std::collections::BTreeMap;
+use std::fs::{self, create_dir_all, File, OpenOptions};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Name of the settings file in the app config directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// A user-defined lock command, run without a shell
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomLockCommand {
+    /// Program followed by its arguments
+    pub argv: Vec<String>,
+    /// Extra environment variables set for the command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// How long to wait for the command to exit before considering the lock in place
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// When set, the built-in lock methods are not tried if the command fails
+    #[serde(default)]
+    pub exclusive: bool,
+}
+
+impl CustomLockCommand {
+    /// Checks that the command is runnable, returning the resolved executable path
+    pub fn validate(&self) -> Result<PathBuf, String> {
+        let program = match self.argv.first() {
+            Some(program) if !program.trim().is_empty() => program,
+            _ => return Err("Custom lock command is empty".to_string()),
+        };
+        if self.timeout_secs == Some(0) {
+            return Err("Custom lock command timeout must be greater than zero".to_string());
+        }
+        find_executable(program)
+            .ok_or_else(|| format!("Custom lock command executable '{}' not found", program))
+    }
+}
+
+/// Resolves a program name to an executable file, searching `PATH` for bare names
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    let program_path = Path::new(program);
+    if program_path.components().count() > 1 {
+        return is_executable(program_path).then(|| program_path.to_path_buf());
+    }
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Range accepted for the RSSI drop that locks the screen, as offered by the settings view
+pub const RSSI_DELTA_RANGE: std::ops::RangeInclusive<i16> = 1..=60;
+
+/// Range accepted for the RSSI a device must reach to unlock the session
+pub const MIN_RSSI_RANGE: std::ops::RangeInclusive<i16> = -127..=0;
+
+/// Color theme of the interface
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Light,
+    #[default]
+    Dark,
+}
+
+/// Language of the interface
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    En,
+    Fr,
+}
+
+/// Why a settings field was rejected
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldErrorCode {
+    /// Not a valid Bluetooth device UUID
+    InvalidUuid,
+    /// A number outside of its accepted range
+    OutOfRange,
+    /// A command or hook that cannot be run
+    InvalidCommand,
+    /// A value of the wrong type or shape
+    InvalidValue,
+}
+
+/// A rejected settings field, reported next to the field by the interface
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    /// Name of the field, with the index for list items such as `pre_lock_hooks[0]`
+    pub field: String,
+    pub code: FieldErrorCode,
+    pub message: String,
+}
+
+impl FieldError {
+    pub(crate) fn new(
+        field: impl Into<String>,
+        code: FieldErrorCode,
+        message: impl Into<String>,
+    ) -> Self {
+        FieldError {
+            field: field.into(),
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Why settings could not be updated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsError {
+    /// Some fields are invalid, nothing was changed
+    Invalid(Vec<FieldError>),
+    /// The settings are valid but could not be saved
+    Failed(String),
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(errors) => {
+                let messages: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.field, error.message))
+                    .collect();
+                write!(f, "Invalid settings: {}", messages.join("; "))
+            }
+            Self::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+fn current_schema_version() -> u32 {
+    CURRENT_SCHEMA_VERSION
+}
+
+/// The user's settings.
+///
+/// Fields added after the first release must have a `#[serde(default)]` so that older files
+/// still load; anything else changing the meaning of the file goes through a migration in
+/// [`crate::settings_migration`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
+    /// Version of the schema the settings follow
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
     pub target_uuid: String,
     pub rssi_delta_max: i16,
-    pub theme: String,
-    pub language: String,
+    pub theme: Theme,
+    pub language: Language,
+    #[serde(default)]
+    pub custom_lock_command: Option<CustomLockCommand>,
+    /// Lock method tried before the ones detected for the desktop
+    #[serde(default)]
+    pub pinned_lock_method: Option<LockMethod>,
+    /// Lock every graphical session of the user rather than only the one the app runs in
+    #[serde(default)]
+    pub lock_all_sessions: bool,
+    /// Only simulate locks, without invoking any lock backend
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Seconds of warning before an automatic lock, during which it can be cancelled
+    #[serde(default)]
+    pub lock_warning_secs: u64,
+    /// Actions taken as absence grows, instead of locking right away
+    #[serde(default)]
+    pub away_action_plan: Option<AwayActionPlan>,
+    /// Run right before the screen is locked
+    #[serde(default)]
+    pub pre_lock_hooks: Vec<Hook>,
+    /// Run when the device comes back after an automatic lock
+    #[serde(default)]
+    pub on_return_hooks: Vec<Hook>,
+    /// Secrets cleaned up whenever the screen is locked
+    #[serde(default)]
+    pub away_hygiene: AwayHygiene,
+    /// Minimum idle time before a weak signal locks the screen, unset to lock regardless of
+    /// user activity
+    #[serde(default)]
+    pub min_idle_secs: Option<u64>,
+    /// Seconds after resuming from sleep during which a timeout does not lock, unset for the
+    /// default grace period
+    #[serde(default)]
+    pub resume_grace_secs: Option<u64>,
+    /// Seconds after an unlock during which the listener does not lock, unset for the default
+    /// grace period
+    #[serde(default)]
+    pub unlock_grace_secs: Option<u64>,
+    /// Recompute the RSSI baseline from the readings taken during the unlock grace period
+    #[serde(default)]
+    pub recompute_baseline_on_unlock: bool,
+    /// Unlocks the session when the device stays close soon after an automatic lock, unset to
+    /// never unlock
+    #[serde(default)]
+    pub auto_unlock: Option<AutoUnlock>,
+    /// Named sets of the settings that depend on where the user is, see
+    /// [`crate::profiles::PROFILE_KEYS`]
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profile whose values are in effect, kept up to date as they change. Unset when no
+    /// profile is used.
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    /// When automatic locking is active, unset for always
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Calendar events during which automatic locking is suppressed or relaxed, unset to ignore
+    /// calendars
+    #[serde(default)]
+    pub calendar: Option<CalendarSettings>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            target_uuid: String::new(),
+            rssi_delta_max: 15,
+            theme: Theme::default(),
+            language: Language::default(),
+            custom_lock_command: None,
+            pinned_lock_method: None,
+            lock_all_sessions: false,
+            dry_run: false,
+            lock_warning_secs: 0,
+            away_action_plan: None,
+            pre_lock_hooks: Vec::new(),
+            on_return_hooks: Vec::new(),
+            away_hygiene: AwayHygiene::default(),
+            min_idle_secs: None,
+            resume_grace_secs: None,
+            unlock_grace_secs: None,
+            recompute_baseline_on_unlock: false,
+            auto_unlock: None,
+            profiles: BTreeMap::new(),
+            active_profile: None,
+            schedule: None,
+            calendar: None,
+        }
+    }
 }
 
 impl Settings {
-    pub fn save(&self, file_path: &str) -> Result<(), String> {
+    /// Saves the settings without ever leaving a partial file behind.
+    ///
+    /// The settings are written to a temporary file in the same directory, flushed to disk and
+    /// then renamed over the file, whose previous version is kept as a backup (see
+    /// [`backup_path`]). The file is only readable by the user on Unix.
+    pub fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<(), String> {
+        let file_path = file_path.as_ref();
         // Ensure parent directory exists
-        if let Some(parent) = Path::new(file_path).parent() {
+        if let Some(parent) = file_path.parent() {
             create_dir_all(parent)
                 .map_err(|e| format!("Error creating directory '{}': {}", parent.display(), e))?;
         }
-        let mut settings_file = File::create(file_path)
-            .map_err(|e| format!("Error creating settings file '{}': {}", file_path, e))?;
         let settings_json = serde_json::to_string(self)
             .map_err(|e| format!("Error serializing settings: {}", e))?;
-        settings_file
-            .write_all(settings_json.as_bytes())
-            .map_err(|e| format!("Error writing settings to file '{}': {}", file_path, e))
+
+        let temp_path = with_suffix(file_path, ".tmp");
+        if let Err(e) = write_synced(&temp_path, settings_json.as_bytes()) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!(
+                "Error writing settings to file '{}': {}",
+                temp_path.display(),
+                e
+            ));
+        }
+        // A corrupt file would replace a good backup, so only valid JSON is backed up
+        if is_json_file(file_path) {
+            let backup = backup_path(file_path);
+            if let Err(e) = copy_private(file_path, &backup) {
+                warn!(
+                    "Failed to back up settings to '{}': {}",
+                    backup.display(),
+                    e
+                );
+            }
+        }
+        fs::rename(&temp_path, file_path).map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!(
+                "Error replacing settings file '{}': {}",
+                file_path.display(),
+                e
+            )
+        })?;
+        sync_parent_dir(file_path);
+        Ok(())
     }
 
-    pub fn load(file_path: &str) -> Result<Self, String> {
-        let settings_file = File::open(file_path)
-            .map_err(|e| format!("Error opening settings file '{}': {}", file_path, e))?;
-        serde_json::from_reader(settings_file)
-            .map_err(|e| format!("Error parsing JSON in settings file '{}': {}", file_path, e))
+    /// Loads settings, upgrading them to the current schema in memory
+    pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Self, String> {
+        Self::load_versioned(file_path).map(|(settings, _)| settings)
+    }
+
+    /// Loads settings, upgrading them to the current schema in memory, and returns the schema
+    /// version of the file as well.
+    ///
+    /// When the file is corrupt, the backup kept by [`Settings::save`] is loaded instead.
+    pub fn load_versioned<P: AsRef<Path>>(file_path: P) -> Result<(Self, u32), String> {
+        Self::load_with_fallback(file_path.as_ref())
+            .map(|(settings, version, _)| (settings, version))
+    }
+
+    /// Loads the file or, when it exists but cannot be read, its backup, returning the path the
+    /// settings were loaded from
+    fn load_with_fallback(file_path: &Path) -> Result<(Self, u32, PathBuf), String> {
+        let error = match Self::load_file(file_path) {
+            Ok((settings, version)) => return Ok((settings, version, file_path.to_path_buf())),
+            Err(e) => e,
+        };
+        let backup = backup_path(file_path);
+        if !file_path.exists() || !backup.exists() {
+            return Err(error);
+        }
+        let (settings, version) = Self::load_file(&backup)
+            .map_err(|backup_error| format!("{}; {}", error, backup_error))?;
+        warn!(
+            "{}, loaded the backup '{}' instead",
+            error,
+            backup.display()
+        );
+        Ok((settings, version, backup))
+    }
+
+    fn load_file(file_path: &Path) -> Result<(Self, u32), String> {
+        let settings_file = File::open(file_path).map_err(|e| {
+            format!(
+                "Error opening settings file '{}': {}",
+                file_path.display(),
+                e
+            )
+        })?;
+        let mut value: serde_json::Value = serde_json::from_reader(settings_file).map_err(|e| {
+            format!(
+                "Error parsing JSON in settings file '{}': {}",
+                file_path.display(),
+                e
+            )
+        })?;
+        let version = migrate(&mut value)
+            .map_err(|e| format!("Error in settings file '{}': {}", file_path.display(), e))?;
+        let settings = serde_json::from_value(value).map_err(|e| {
+            format!(
+                "Error parsing JSON in settings file '{}': {}",
+                file_path.display(),
+                e
+            )
+        })?;
+        Ok((settings, version))
+    }
+
+    /// Checks the settings, returning an error for each invalid field.
+    ///
+    /// An empty `target_uuid` means no device is selected. Commands and hooks must be runnable.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !self.target_uuid.is_empty() {
+            if let Err(e) = Uuid::parse_str(&self.target_uuid) {
+                errors.push(FieldError::new(
+                    "target_uuid",
+                    FieldErrorCode::InvalidUuid,
+                    format!("'{}' is not a valid device UUID: {}", self.target_uuid, e),
+                ));
+            }
+        }
+        if !RSSI_DELTA_RANGE.contains(&self.rssi_delta_max) {
+            errors.push(FieldError::new(
+                "rssi_delta_max",
+                FieldErrorCode::OutOfRange,
+                format!(
+                    "RSSI threshold must be between {} and {}",
+                    RSSI_DELTA_RANGE.start(),
+                    RSSI_DELTA_RANGE.end()
+                ),
+            ));
+        }
+        if let Some(command) = &self.custom_lock_command {
+            if let Err(e) = command.validate() {
+                errors.push(FieldError::new(
+                    "custom_lock_command",
+                    FieldErrorCode::InvalidCommand,
+                    e,
+                ));
+            }
+        }
+        for (field, hooks) in [
+            ("pre_lock_hooks", &self.pre_lock_hooks),
+            ("on_return_hooks", &self.on_return_hooks),
+        ] {
+            for (index, hook) in hooks.iter().enumerate() {
+                if let Err(e) = hook.validate() {
+                    errors.push(FieldError::new(
+                        format!("{}[{}]", field, index),
+                        FieldErrorCode::InvalidCommand,
+                        e,
+                    ));
+                }
+            }
+        }
+        if let Some(auto_unlock) = &self.auto_unlock {
+            if !MIN_RSSI_RANGE.contains(&auto_unlock.min_rssi) {
+                errors.push(FieldError::new(
+                    "auto_unlock.min_rssi",
+                    FieldErrorCode::OutOfRange,
+                    format!(
+                        "Unlock RSSI must be between {} and {}",
+                        MIN_RSSI_RANGE.start(),
+                        MIN_RSSI_RANGE.end()
+                    ),
+                ));
+            }
+            if auto_unlock.within_secs == 0 {
+                errors.push(FieldError::new(
+                    "auto_unlock.within_secs",
+                    FieldErrorCode::OutOfRange,
+                    "Unlock window must be greater than zero",
+                ));
+            }
+        }
+        if let Some(plan) = &self.away_action_plan {
+            errors.extend(plan.validate());
+        }
+        if let Some(schedule) = &self.schedule {
+            errors.extend(schedule.validate());
+        }
+        if let Some(calendar) = &self.calendar {
+            errors.extend(calendar.validate());
+        }
+        errors.extend(validate_profiles(self));
+        errors
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7396): objects are merged recursively, `null` removes a
+/// member and any other value replaces the target
+pub fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let Some(target) = target.as_object_mut() else {
+        return;
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
     }
 }
 
-#[tauri::command(rename_all = "snake_case")]
-pub fn write_settings(settings: Settings, file_path: String) -> Result<(), String> {
-    settings.save(&file_path)
+/// Names of the top-level settings whose values differ
+pub fn changed_keys(old: &Settings, new: &Settings) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    let mut keys: Vec<String> = old.keys().chain(new.keys()).cloned().collect();
+    keys.sort();
+    keys.dedup();
+    keys.retain(|key| old.get(key) != new.get(key));
+    keys
 }
 
-#[tauri::command(rename_all = "snake_case")]
-pub fn read_settings(file_path: String) -> Result<Settings, String> {
-    Settings::load(&file_path)
+/// Settings after an update, with the keys that changed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SettingsChange {
+    pub changed: Vec<String>,
+    pub settings: Settings,
+}
+
+impl SettingsChange {
+    /// Returns whether any of the given keys changed
+    pub fn touches(&self, keys: &[&str]) -> bool {
+        self.changed.iter().any(|key| keys.contains(&key.as_str()))
+    }
+}
+
+/// Event emitted with a [`SettingsChange`] whenever settings change
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+/// The settings of the app, kept in memory as the single source of truth and saved to a file
+/// whose location is decided by the backend.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Opens the settings stored in `settings.json` in the given directory.
+    ///
+    /// When that file does not exist yet, settings saved by earlier versions at `legacy_path`
+    /// are imported.
+    pub fn in_dir(dir: &Path, legacy_path: Option<&Path>) -> Self {
+        let path = dir.join(SETTINGS_FILE);
+        if let Some(legacy_path) = legacy_path.filter(|legacy| !path.exists() && legacy.exists()) {
+            match Settings::load(legacy_path).and_then(|settings| settings.save(&path)) {
+                Ok(()) => info!(
+                    "Imported settings from '{}' into '{}'",
+                    legacy_path.display(),
+                    path.display()
+                ),
+                Err(e) => warn!("Failed to import settings: {}", e),
+            }
+        }
+        Self::open(path)
+    }
+
+    /// Opens the settings stored in the given file, using the defaults when it is missing or
+    /// unreadable. An unreadable file is left untouched until the settings are next updated.
+    ///
+    /// A file written with an older schema is upgraded, keeping a copy of the original next to
+    /// it (see [`migration_backup_path`]).
+    pub fn open(path: PathBuf) -> Self {
+        let settings = if path.exists() {
+            match Settings::load_with_fallback(&path) {
+                Ok((settings, version, source)) => {
+                    if version < CURRENT_SCHEMA_VERSION {
+                        if let Err(e) = upgrade_file(&path, &source, version, &settings) {
+                            warn!("{}, keeping the upgraded settings in memory only", e);
+                        }
+                    }
+                    settings
+                }
+                Err(e) => {
+                    warn!("{}, using default settings", e);
+                    Settings::default()
+                }
+            }
+        } else {
+            Settings::default()
+        };
+        SettingsStore {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    /// Path of the settings file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current settings
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Replaces the settings, saving them first so that memory and disk stay in sync.
+    ///
+    /// Nothing is saved when no value changes.
+    pub fn update(&self, settings: Settings) -> Result<SettingsChange, SettingsError> {
+        let mut current = self.settings.lock().unwrap();
+        self.commit(&mut current, settings, true)
+    }
+
+    /// Changes the current settings with `change`, then saves them like
+    /// [`SettingsStore::update`]
+    pub fn modify<F>(&self, change: F) -> Result<SettingsChange, SettingsError>
+    where
+        F: FnOnce(&mut Settings) -> Result<(), SettingsError>,
+    {
+        let mut current = self.settings.lock().unwrap();
+        let mut settings = current.clone();
+        change(&mut settings)?;
+        self.commit(&mut current, settings, true)
+    }
+
+    /// Reads back the settings file after it was edited outside the app, keeping the current
+    /// settings when it cannot be read or is invalid.
+    ///
+    /// The file is not rewritten, an edit from an older schema being upgraded in memory only,
+    /// unless the edit has to be synced with the active profile.
+    pub fn reload(&self) -> Result<SettingsChange, SettingsError> {
+        let mut current = self.settings.lock().unwrap();
+        let (settings, _) = Settings::load_file(&self.path).map_err(SettingsError::Failed)?;
+        self.commit(&mut current, settings, false)
+    }
+
+    /// Applies a JSON merge patch to the settings, then saves them like
+    /// [`SettingsStore::update`]
+    pub fn patch(&self, patch: &serde_json::Value) -> Result<SettingsChange, SettingsError> {
+        let mut current = self.settings.lock().unwrap();
+        let settings = patched(&current, patch)?;
+        self.commit(&mut current, settings, true)
+    }
+
+    /// Returns what [`SettingsStore::update`] would change, without changing anything
+    pub fn preview(&self, settings: Settings) -> Result<SettingsChange, SettingsError> {
+        let current = self.settings.lock().unwrap();
+        Self::prepare(&current, settings).map(|(change, _)| change)
+    }
+
+    /// Validates new settings and puts them in place of the current ones, saving them first
+    /// when asked to
+    fn commit(
+        &self,
+        current: &mut Settings,
+        settings: Settings,
+        save: bool,
+    ) -> Result<SettingsChange, SettingsError> {
+        let (change, synced) = Self::prepare(current, settings)?;
+        if !change.changed.is_empty() {
+            // Settings read back from the file are saved when the sync changed them
+            if save || synced {
+                change.settings.save(&self.path).map_err(SettingsError::Failed)?;
+            }
+            *current = change.settings.clone();
+        }
+        Ok(change)
+    }
+
+    /// Validates new settings and lists the keys changed from `current`, also returning
+    /// whether the settings had to be synced with the active profile, see
+    /// [`sync_active_profile`]
+    fn prepare(
+        current: &Settings,
+        mut settings: Settings,
+    ) -> Result<(SettingsChange, bool), SettingsError> {
+        let synced = sync_active_profile(current, &mut settings);
+        let errors = settings.validate();
+        if !errors.is_empty() {
+            return Err(SettingsError::Invalid(errors));
+        }
+        settings.schema_version = CURRENT_SCHEMA_VERSION;
+        let changed = changed_keys(current, &settings);
+        Ok((SettingsChange { changed, settings }, synced))
+    }
+}
+
+/// Applies a JSON merge patch to settings, reporting each patched member that does not fit
+pub fn patched(settings: &Settings, patch: &serde_json::Value) -> Result<Settings, SettingsError> {
+    let Some(members) = patch.as_object() else {
+        return Err(SettingsError::Invalid(vec![FieldError::new(
+            "",
+            FieldErrorCode::InvalidValue,
+            "Settings patch must be a JSON object",
+        )]));
+    };
+    let to_value = |settings: &Settings| {
+        serde_json::to_value(settings)
+            .map_err(|e| SettingsError::Failed(format!("Error serializing settings: {}", e)))
+    };
+    let mut merged = to_value(settings)?;
+    merge_patch(&mut merged, patch);
+    let e = match serde_json::from_value(merged.clone()) {
+        Ok(settings) => return Ok(settings),
+        Err(e) => e,
+    };
+    // Find the patched members that do not fit, each on top of valid settings
+    let defaults = to_value(&Settings::default())?;
+    let errors = members
+        .keys()
+        .filter_map(|key| {
+            let mut candidate = defaults.clone();
+            match merged.get(key) {
+                Some(value) => candidate[key] = value.clone(),
+                None => {
+                    candidate.as_object_mut()?.remove(key);
+                }
+            }
+            let error = serde_json::from_value::<Settings>(candidate).err()?;
+            Some(FieldError::new(
+                key.clone(),
+                FieldErrorCode::InvalidValue,
+                error.to_string(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        return Err(SettingsError::Invalid(vec![FieldError::new(
+            "",
+            FieldErrorCode::InvalidValue,
+            e.to_string(),
+        )]));
+    }
+    Err(SettingsError::Invalid(errors))
+}
+
+/// Appends a suffix to the file name of a path
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Path of the copy of the previous version kept when saving settings, such as
+/// `settings.json.bak`
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Path of the copy kept of a settings file before it is upgraded from the given schema
+/// version, such as `settings.json.v0.bak`
+pub fn migration_backup_path(path: &Path, version: u32) -> PathBuf {
+    with_suffix(path, &format!(".v{}.bak", version))
+}
+
+/// Returns whether the file holds valid JSON
+fn is_json_file(path: &Path) -> bool {
+    fs::read(path)
+        .map(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).is_ok())
+        .unwrap_or(false)
+}
+
+/// Opens a new file only readable and writable by the user, replacing any leftover one
+fn create_private(path: &Path) -> std::io::Result<File> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Writes a new private file and flushes it to disk
+pub(crate) fn write_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = create_private(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Copies a file to a new private file
+fn copy_private(from: &Path, to: &Path) -> std::io::Result<()> {
+    write_synced(to, &fs::read(from)?)
+}
+
+/// Flushes the directory entry of a renamed file to disk, where the platform allows it
+pub(crate) fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        if let Err(e) = File::open(parent).and_then(|dir| dir.sync_all()) {
+            warn!("Failed to flush directory '{}': {}", parent.display(), e);
+        }
+    }
+}
+
+/// Rewrites a settings file with the current schema, after copying the file the settings were
+/// loaded from aside
+fn upgrade_file(
+    path: &Path,
+    source: &Path,
+    version: u32,
+    settings: &Settings,
+) -> Result<(), String> {
+    let backup_path = migration_backup_path(path, version);
+    copy_private(source, &backup_path).map_err(|e| {
+        format!(
+            "Failed to back up settings to '{}': {}",
+            backup_path.display(),
+            e
+        )
+    })?;
+    settings.save(path)?;
+    info!(
+        "Upgraded settings from schema version {} to {}, previous file kept at '{}'",
+        version,
+        CURRENT_SCHEMA_VERSION,
+        backup_path.display()
+    );
+    Ok(())
+}
+
+/// Returns the current settings
+#[tauri::command]
+pub fn get_settings(store: State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+/// Lets the backend react to changed settings: the lock pipeline takes its settings into account
+/// when one of them changed, and [`SETTINGS_CHANGED_EVENT`] tells the frontend and the other
+/// subsystems which keys changed.
+pub fn notify_settings_changed(app_handle: &AppHandle, change: &SettingsChange) {
+    if change.changed.is_empty() {
+        return;
+    }
+    info!("Settings changed: {}", change.changed.join(", "));
+    if change.touches(&LockPipeline::SETTINGS_KEYS) {
+        app_handle
+            .state::<LockPipeline>()
+            .apply_settings(&change.settings);
+    }
+    if change.touches(&BluetoothListenerHandle::SETTINGS_KEYS) {
+        if let Err(e) = app_handle
+            .state::<BluetoothListenerHandle>()
+            .restart(app_handle.clone(), &change.settings)
+        {
+            error!("Failed to restart the Bluetooth listener: {}", e);
+        }
+    }
+    if let Err(e) = app_handle.emit(SETTINGS_CHANGED_EVENT, change) {
+        error!("Failed to emit {}: {}", SETTINGS_CHANGED_EVENT, e);
+    }
+}
+
+/// Applies a JSON merge patch to the settings and saves them once, returning the saved
+/// settings.
+///
+/// Invalid settings are rejected with an error for each invalid field.
+#[tauri::command]
+pub fn update_settings(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    patch: serde_json::Value,
+) -> Result<Settings, SettingsError> {
+    let change = store.patch(&patch)?;
+    notify_settings_changed(&app_handle, &change);
+    Ok(change.settings)
 }
 
 #[cfg(test)]
@@ -54,9 +869,10 @@ mod tests {
     fn create_test_settings() -> Settings {
         Settings {
             target_uuid: "12345678-1234-1234-1234-123456789012".to_string(),
-            rssi_delta_max: -50,
-            theme: "dark".to_string(),
-            language: "en".to_string(),
+            rssi_delta_max: 25,
+            theme: Theme::Dark,
+            language: Language::En,
+            ..Default::default()
         }
     }
 
@@ -65,7 +881,7 @@ mod tests {
         let settings = create_test_settings();
         let json = serde_json::to_string(&settings).unwrap();
         assert!(json.contains("12345678-1234-1234-1234-123456789012"));
-        assert!(json.contains("-50"));
+        assert!(json.contains("25"));
         assert!(json.contains("dark"));
         assert!(json.contains("en"));
     }
@@ -81,8 +897,8 @@ mod tests {
         let settings: Settings = serde_json::from_str(json).unwrap();
         assert_eq!(settings.target_uuid, "12345678-1234-1234-1234-123456789012");
         assert_eq!(settings.rssi_delta_max, -50);
-        assert_eq!(settings.theme, "dark");
-        assert_eq!(settings.language, "en");
+        assert_eq!(settings.theme, Theme::Dark);
+        assert_eq!(settings.language, Language::En);
     }
 
     #[test]
@@ -152,29 +968,30 @@ mod tests {
     }
 
     #[test]
-    fn test_write_settings_command() {
+    fn test_settings_store_update() {
         let dir = tempdir().unwrap();
-        let file_path = dir.path().join("command_test.json");
-        let file_path_str = file_path.to_string_lossy().to_string();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        assert_eq!(store.path(), dir.path().join(SETTINGS_FILE));
+        assert_eq!(store.get().target_uuid, "");
+
+        let result = store.update(create_test_settings());
 
-        let settings = create_test_settings();
-        let result = write_settings(settings, file_path_str.clone());
-        
         assert!(result.is_ok());
-        assert!(file_path.exists());
-        
-        // Verify content
-        let loaded = read_settings(file_path_str);
-        assert!(loaded.is_ok());
-        let loaded_settings = loaded.unwrap();
-        assert_eq!(loaded_settings.target_uuid, "12345678-1234-1234-1234-123456789012");
+        assert_eq!(
+            store.get().target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
+        let reopened = SettingsStore::open(store.path().to_path_buf());
+        assert_eq!(
+            reopened.get().target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
     }
 
     #[test]
-    fn test_read_settings_command() {
+    fn test_settings_store_imports_legacy_file() {
         let dir = tempdir().unwrap();
-        let file_path = dir.path().join("read_test.json");
-        let file_path_str = file_path.to_string_lossy().to_string();
+        let legacy_path = dir.path().join("data").join("settings.json");
 
         // Create settings file manually
         let json_content = r#"{
@@ -183,15 +1000,450 @@ mod tests {
             "theme": "light",
             "language": "fr"
         }"#;
-        fs::write(&file_path, json_content).unwrap();
+        fs::create_dir_all(legacy_path.parent().unwrap()).unwrap();
+        fs::write(&legacy_path, json_content).unwrap();
 
-        let result = read_settings(file_path_str);
-        assert!(result.is_ok());
-        
-        let settings = result.unwrap();
+        let store = SettingsStore::in_dir(&dir.path().join("config"), Some(&legacy_path));
+
+        let settings = store.get();
         assert_eq!(settings.target_uuid, "test-uuid");
         assert_eq!(settings.rssi_delta_max, -30);
-        assert_eq!(settings.theme, "light");
-        assert_eq!(settings.language, "fr");
+        assert_eq!(settings.theme, Theme::Light);
+        assert_eq!(settings.language, Language::Fr);
+        assert!(store.path().exists());
+    }
+
+    #[test]
+    fn test_settings_store_keeps_unreadable_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        fs::write(&file_path, "{ invalid json }").unwrap();
+
+        let store = SettingsStore::open(file_path.clone());
+
+        assert_eq!(
+            store.get().rssi_delta_max,
+            Settings::default().rssi_delta_max
+        );
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "{ invalid json }");
+    }
+
+    #[test]
+    fn test_save_keeps_previous_version_as_backup() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        let mut settings = create_test_settings();
+        settings.save(&file_path).unwrap();
+        settings.rssi_delta_max = 20;
+
+        settings.save(&file_path).unwrap();
+
+        assert_eq!(Settings::load(&file_path).unwrap().rssi_delta_max, 20);
+        assert_eq!(
+            Settings::load(backup_path(&file_path))
+                .unwrap()
+                .rssi_delta_max,
+            25
+        );
+        assert!(!with_suffix(&file_path, ".tmp").exists());
+    }
+
+    #[test]
+    fn test_save_replaces_leftover_temporary_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        fs::write(with_suffix(&file_path, ".tmp"), "{ partial").unwrap();
+
+        create_test_settings().save(&file_path).unwrap();
+
+        assert!(Settings::load(&file_path).is_ok());
+        assert!(!with_suffix(&file_path, ".tmp").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        fs::write(
+            &file_path,
+            serde_json::to_string(&create_test_settings()).unwrap(),
+        )
+        .unwrap();
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        create_test_settings().save(&file_path).unwrap();
+
+        for path in [file_path.clone(), backup_path(&file_path)] {
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{} should be private", path.display());
+        }
+    }
+
+    #[test]
+    fn test_load_falls_back_to_backup() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        create_test_settings().save(&file_path).unwrap();
+        create_test_settings().save(&file_path).unwrap();
+        fs::write(&file_path, "").unwrap();
+
+        let loaded = Settings::load(&file_path);
+
+        assert_eq!(
+            loaded.unwrap().target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
+    }
+
+    #[test]
+    fn test_save_does_not_back_up_corrupt_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join(SETTINGS_FILE);
+        create_test_settings().save(&file_path).unwrap();
+        create_test_settings().save(&file_path).unwrap();
+        fs::write(&file_path, "{ partial").unwrap();
+
+        Settings::default().save(&file_path).unwrap();
+
+        assert_eq!(
+            Settings::load(backup_path(&file_path)).unwrap().target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
+    }
+
+    #[test]
+    fn test_validate_accepts_valid_settings() {
+        assert!(create_test_settings().validate().is_empty());
+        assert!(Settings::default().validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_each_invalid_field() {
+        let mut settings = create_test_settings();
+        settings.target_uuid = "not-a-uuid".to_string();
+        settings.rssi_delta_max = -50;
+        settings.auto_unlock = Some(AutoUnlock {
+            within_secs: 0,
+            min_rssi: 10,
+            dwell_secs: 5,
+        });
+
+        let errors = settings.validate();
+
+        let fields: Vec<(&str, FieldErrorCode)> = errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.code))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("target_uuid", FieldErrorCode::InvalidUuid),
+                ("rssi_delta_max", FieldErrorCode::OutOfRange),
+                ("auto_unlock.min_rssi", FieldErrorCode::OutOfRange),
+                ("auto_unlock.within_secs", FieldErrorCode::OutOfRange),
+            ]
+        );
+        assert!(errors[0].message.contains("not-a-uuid"));
+    }
+
+    #[test]
+    fn test_settings_error_serialization() {
+        let error = SettingsError::Invalid(vec![FieldError::new(
+            "rssi_delta_max",
+            FieldErrorCode::OutOfRange,
+            "RSSI threshold must be between 1 and 60",
+        )]);
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["invalid"][0]["field"], "rssi_delta_max");
+        assert_eq!(json["invalid"][0]["code"], "out_of_range");
+        assert!(error.to_string().contains("rssi_delta_max"));
+    }
+
+    #[test]
+    fn test_theme_and_language_are_checked_on_load() {
+        let json = r#"{
+            "schema_version": 2,
+            "target_uuid": "",
+            "rssi_delta_max": 15,
+            "theme": "blue",
+            "language": "en"
+        }"#;
+        assert!(serde_json::from_str::<Settings>(json).is_err());
+    }
+
+    fn custom_command(argv: &[&str]) -> CustomLockCommand {
+        CustomLockCommand {
+            argv: argv.iter().map(|arg| arg.to_string()).collect(),
+            env: BTreeMap::new(),
+            timeout_secs: None,
+            exclusive: false,
+        }
+    }
+
+    #[test]
+    fn test_settings_without_custom_lock_command() {
+        let json = r#"{
+            "target_uuid": "test-uuid",
+            "rssi_delta_max": -30,
+            "theme": "light",
+            "language": "fr"
+        }"#;
+        let settings: Settings = serde_json::from_str(json).unwrap();
+        assert!(settings.custom_lock_command.is_none());
+        assert!(settings.pinned_lock_method.is_none());
+        assert!(!settings.dry_run);
+    }
+
+    #[test]
+    fn test_settings_pinned_lock_method_round_trip() {
+        let mut settings = create_test_settings();
+        settings.pinned_lock_method = Some(LockMethod::Swaylock);
+        let json = serde_json::to_string(&settings).unwrap();
+        assert!(json.contains("\"pinned_lock_method\":\"swaylock\""));
+
+        let loaded: Settings = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pinned_lock_method, Some(LockMethod::Swaylock));
+    }
+
+    #[test]
+    fn test_custom_lock_command_deserialization_defaults() {
+        let json = r#"{ "argv": ["swaylock", "-f"] }"#;
+        let command: CustomLockCommand = serde_json::from_str(json).unwrap();
+        assert_eq!(command.argv, vec!["swaylock", "-f"]);
+        assert!(command.env.is_empty());
+        assert_eq!(command.timeout_secs, None);
+        assert!(!command.exclusive);
+    }
+
+    #[test]
+    fn test_custom_lock_command_validate_empty() {
+        let result = custom_command(&[]).validate();
+        assert!(result.unwrap_err().contains("empty"));
+
+        let result = custom_command(&[" "]).validate();
+        assert!(result.unwrap_err().contains("empty"));
+    }
+
+    #[test]
+    fn test_custom_lock_command_validate_zero_timeout() {
+        let mut command = custom_command(&["sh"]);
+        command.timeout_secs = Some(0);
+        assert!(command.validate().unwrap_err().contains("timeout"));
+    }
+
+    #[test]
+    fn test_custom_lock_command_validate_missing_executable() {
+        let result = custom_command(&["lock-4-me-missing-locker"]).validate();
+        assert!(result.unwrap_err().contains("not found"));
+
+        let result = custom_command(&["/nonexistent/bin/locker"]).validate();
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_custom_lock_command_validate_resolves_path() {
+        let resolved = custom_command(&["sh", "-c", "true"]).validate().unwrap();
+        assert!(resolved.is_absolute());
+        assert!(resolved.ends_with("sh"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_executable_rejects_non_executable_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("not-executable");
+        fs::write(&file_path, "").unwrap();
+        assert!(find_executable(file_path.to_str().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_update_settings_rejects_invalid_custom_lock_command() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+
+        let mut settings = create_test_settings();
+        settings.custom_lock_command = Some(custom_command(&["lock-4-me-missing-locker"]));
+        let result = store.update(settings);
+
+        assert!(result.is_err());
+        assert!(
+            !store.path().exists(),
+            "Invalid settings should not be written"
+        );
+        assert_eq!(
+            store.get().target_uuid,
+            "",
+            "Invalid settings should not be kept"
+        );
+    }
+
+    #[test]
+    fn test_update_settings_rejects_invalid_hook() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+
+        let mut settings = create_test_settings();
+        settings.on_return_hooks = vec![Hook {
+            argv: vec!["lock-4-me-missing-hook".to_string()],
+            env: BTreeMap::new(),
+            timeout_secs: None,
+        }];
+        let result = store.update(settings);
+
+        let Err(SettingsError::Invalid(errors)) = result else {
+            panic!("Invalid hook should be rejected: {:?}", result);
+        };
+        assert_eq!(errors[0].field, "on_return_hooks[0]");
+        assert_eq!(errors[0].code, FieldErrorCode::InvalidCommand);
+        assert!(errors[0].message.contains("Hook executable"));
+        assert!(!store.path().exists());
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut target = serde_json::json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1]});
+        merge_patch(
+            &mut target,
+            &serde_json::json!({"a": null, "b": {"c": 4}, "e": [2], "f": "new"}),
+        );
+        assert_eq!(
+            target,
+            serde_json::json!({"b": {"c": 4, "d": 3}, "e": [2], "f": "new"})
+        );
+    }
+
+    #[test]
+    fn test_settings_store_patch_reports_changed_keys() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store.update(create_test_settings()).unwrap();
+
+        let change = store
+            .patch(&serde_json::json!({"rssi_delta_max": 30, "theme": "light", "language": "en"}))
+            .unwrap();
+
+        assert_eq!(change.changed, ["rssi_delta_max", "theme"]);
+        assert!(!change.touches(&crate::lock_pipeline::LockPipeline::SETTINGS_KEYS));
+        assert!(change.touches(&BluetoothListenerHandle::SETTINGS_KEYS));
+        assert_eq!(
+            change.settings.target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
+        let reopened = SettingsStore::open(store.path().to_path_buf());
+        assert_eq!(reopened.get().rssi_delta_max, 30);
+        assert_eq!(reopened.get().theme, Theme::Light);
+    }
+
+    #[test]
+    fn test_settings_store_patch_without_change_does_not_save() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+
+        let change = store
+            .patch(&serde_json::json!({"rssi_delta_max": 15}))
+            .unwrap();
+
+        assert!(change.changed.is_empty());
+        assert!(!store.path().exists());
+    }
+
+    #[test]
+    fn test_settings_store_patch_nested_and_removed_values() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store
+            .patch(&serde_json::json!({
+                "auto_unlock": {"within_secs": 120, "min_rssi": -60, "dwell_secs": 5}
+            }))
+            .unwrap();
+
+        let change = store
+            .patch(&serde_json::json!({"auto_unlock": {"dwell_secs": 10}}))
+            .unwrap();
+        assert_eq!(change.changed, ["auto_unlock"]);
+        assert!(change.touches(&crate::lock_pipeline::LockPipeline::SETTINGS_KEYS));
+        assert!(!change.touches(&BluetoothListenerHandle::SETTINGS_KEYS));
+        assert_eq!(store.get().auto_unlock.unwrap().dwell_secs, 10);
+
+        store
+            .patch(&serde_json::json!({"auto_unlock": null}))
+            .unwrap();
+        assert!(store.get().auto_unlock.is_none());
+    }
+
+    #[test]
+    fn test_settings_store_patch_rejects_invalid_values() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+
+        let result = store.patch(&serde_json::json!({"theme": "blue", "dry_run": true}));
+        let Err(SettingsError::Invalid(errors)) = result else {
+            panic!("Unknown theme should be rejected: {:?}", result);
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "theme");
+        assert_eq!(errors[0].code, FieldErrorCode::InvalidValue);
+        assert!(!store.get().dry_run);
+
+        let result = store.patch(&serde_json::json!({"rssi_delta_max": 0}));
+        let Err(SettingsError::Invalid(errors)) = result else {
+            panic!("Out of range threshold should be rejected: {:?}", result);
+        };
+        assert_eq!(errors[0].code, FieldErrorCode::OutOfRange);
+
+        assert!(store.patch(&serde_json::json!(["theme"])).is_err());
+        assert!(!store.path().exists());
+    }
+
+    #[test]
+    fn test_settings_store_reload_applies_external_edit() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store.update(create_test_settings()).unwrap();
+        let edited = r#"{"schema_version": 2, "target_uuid": "12345678-1234-1234-1234-123456789012",
+            "rssi_delta_max": 35, "theme": "dark", "language": "en"}"#;
+        fs::write(store.path(), edited).unwrap();
+
+        let change = store.reload().unwrap();
+
+        assert_eq!(change.changed, ["rssi_delta_max"]);
+        assert_eq!(store.get().rssi_delta_max, 35);
+        assert_eq!(
+            fs::read_to_string(store.path()).unwrap(),
+            edited,
+            "Reloaded file should not be rewritten"
+        );
+        assert!(store.reload().unwrap().changed.is_empty());
+    }
+
+    #[test]
+    fn test_settings_store_reload_keeps_settings_on_invalid_edit() {
+        let dir = tempdir().unwrap();
+        let store = SettingsStore::in_dir(dir.path(), None);
+        store.update(create_test_settings()).unwrap();
+
+        fs::write(store.path(), r#"{"target_uuid": "#).unwrap();
+        assert!(matches!(store.reload(), Err(SettingsError::Failed(_))));
+
+        fs::write(
+            store.path(),
+            r#"{"schema_version": 2, "target_uuid": "not-a-uuid", "rssi_delta_max": 15,
+                "theme": "dark", "language": "en"}"#,
+        )
+        .unwrap();
+        assert!(matches!(store.reload(), Err(SettingsError::Invalid(_))));
+
+        fs::remove_file(store.path()).unwrap();
+        assert!(matches!(store.reload(), Err(SettingsError::Failed(_))));
+
+        assert_eq!(
+            store.get().target_uuid,
+            "12345678-1234-1234-1234-123456789012"
+        );
+        assert_eq!(store.get().rssi_delta_max, 25);
     }
 }
@@ -0,0 +1,252 @@
+use crate::read_write_settings::{FieldError, FieldErrorCode};
+use chrono::{
+    DateTime, Datelike, Duration, DurationRound, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// How far ahead the next change of the schedule state is looked for
+const LOOKAHEAD_DAYS: i64 = 8;
+
+/// A weekly time window during which automatic locking is active
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduleWindow {
+    /// Days the window starts on, such as `"mon"`
+    pub days: Vec<Weekday>,
+    /// Local time the window starts at, such as `"09:00"`
+    pub start: NaiveTime,
+    /// Local time the window ends at. A window ending at or before its start ends the next day,
+    /// so that `"00:00"` to `"00:00"` covers whole days.
+    pub end: NaiveTime,
+}
+
+impl ScheduleWindow {
+    fn contains(&self, day: Weekday, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.days.contains(&day) && self.start <= time && time < self.end
+        } else {
+            (self.days.contains(&day) && self.start <= time)
+                || (self.days.contains(&day.pred()) && time < self.end)
+        }
+    }
+}
+
+/// A date on which the weekly windows do not apply, such as a public holiday
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduleException {
+    /// Local date, such as `"2026-12-25"`
+    pub date: NaiveDate,
+    /// Whether automatic locking is active for the whole day
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// When automatic locking is active.
+///
+/// Outside of the weekly windows, and on exception dates that are not active, the listener
+/// keeps running but its lock decisions are skipped. Manual locks are never affected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Schedule {
+    /// IANA time zone of the windows and dates, such as `"Europe/Paris"`, unset for the time
+    /// zone of the system
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub windows: Vec<ScheduleWindow>,
+    #[serde(default)]
+    pub exceptions: Vec<ScheduleException>,
+}
+
+/// Whether automatic locking is active, as reported in the listener status
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ScheduleState {
+    pub active: bool,
+    /// When the state next changes, unset when it does not change within a week
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    /// Returns the time zone of the schedule, `None` for the time zone of the system
+    pub fn time_zone(&self) -> Result<Option<Tz>, String> {
+        self.time_zone
+            .as_deref()
+            .map(|name| {
+                name.parse::<Tz>()
+                    .map_err(|_| format!("Unknown time zone '{}'", name))
+            })
+            .transpose()
+    }
+
+    /// Returns whether automatic locking is active at the given instant
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> Result<bool, String> {
+        let time_zone = self.time_zone()?;
+        Ok(self.is_active_locally(local_time(time_zone, now)))
+    }
+
+    /// Returns whether automatic locking is active at the given instant, and until when
+    pub fn state_at(&self, now: DateTime<Utc>) -> Result<ScheduleState, String> {
+        let time_zone = self.time_zone()?;
+        let active = self.is_active_locally(local_time(time_zone, now));
+        // Windows and dates change state on minute boundaries
+        let start = now
+            .duration_trunc(Duration::minutes(1))
+            .map_err(|e| format!("Failed to compute schedule: {}", e))?;
+        let until = (1..=LOOKAHEAD_DAYS * 24 * 60)
+            .map(|minutes| start + Duration::minutes(minutes))
+            .find(|at| self.is_active_locally(local_time(time_zone, *at)) != active);
+        Ok(ScheduleState { active, until })
+    }
+
+    fn is_active_locally(&self, local: NaiveDateTime) -> bool {
+        if let Some(exception) = self
+            .exceptions
+            .iter()
+            .find(|exception| exception.date == local.date())
+        {
+            return exception.active;
+        }
+        self.windows
+            .iter()
+            .any(|window| window.contains(local.weekday(), local.time()))
+    }
+
+    /// Checks the schedule, returning an error for each invalid field
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.time_zone() {
+            errors.push(FieldError::new(
+                "schedule.time_zone",
+                FieldErrorCode::InvalidValue,
+                e,
+            ));
+        }
+        // Without windows automatic locking would never be active, unlike without a schedule
+        if self.windows.is_empty() {
+            errors.push(FieldError::new(
+                "schedule.windows",
+                FieldErrorCode::InvalidValue,
+                "Schedule has no windows, unset the schedule to lock at any time",
+            ));
+        }
+        for (index, window) in self.windows.iter().enumerate() {
+            if window.days.is_empty() {
+                errors.push(FieldError::new(
+                    format!("schedule.windows[{}]", index),
+                    FieldErrorCode::InvalidValue,
+                    "Schedule window has no days",
+                ));
+            }
+        }
+        errors
+    }
+}
+
+fn local_time(time_zone: Option<Tz>, at: DateTime<Utc>) -> NaiveDateTime {
+    match time_zone {
+        Some(time_zone) => at.with_timezone(&time_zone).naive_local(),
+        None => at.with_timezone(&Local).naive_local(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Weekdays from 9:00 to 18:00 in Paris
+    fn office_hours() -> Schedule {
+        serde_json::from_str(
+            r#"{
+                "time_zone": "Europe/Paris",
+                "windows": [
+                    {"days": ["mon", "tue", "wed", "thu", "fri"], "start": "09:00", "end": "18:00"}
+                ],
+                "exceptions": [{"date": "2026-12-25"}]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_weekly_window_in_time_zone() {
+        let schedule = office_hours();
+        // Monday 19 October 2026, Paris is UTC+2
+        assert!(!schedule.is_active_at(utc(2026, 10, 19, 6, 59)).unwrap());
+        assert!(schedule.is_active_at(utc(2026, 10, 19, 7, 0)).unwrap());
+        assert!(schedule.is_active_at(utc(2026, 10, 19, 15, 59)).unwrap());
+        assert!(!schedule.is_active_at(utc(2026, 10, 19, 16, 0)).unwrap());
+        // Saturday
+        assert!(!schedule.is_active_at(utc(2026, 10, 24, 10, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_exception_overrides_windows() {
+        let mut schedule = office_hours();
+        // Friday 25 December 2026
+        assert!(!schedule.is_active_at(utc(2026, 12, 25, 10, 0)).unwrap());
+
+        schedule.exceptions.push(ScheduleException {
+            date: NaiveDate::from_ymd_opt(2026, 10, 24).unwrap(),
+            active: true,
+        });
+        assert!(schedule.is_active_at(utc(2026, 10, 24, 20, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_overnight_window() {
+        let schedule: Schedule = serde_json::from_str(
+            r#"{"time_zone": "UTC", "windows": [{"days": ["fri"], "start": "22:00", "end": "06:00"}]}"#,
+        )
+        .unwrap();
+        // Friday 23 October 2026
+        assert!(!schedule.is_active_at(utc(2026, 10, 23, 21, 59)).unwrap());
+        assert!(schedule.is_active_at(utc(2026, 10, 23, 23, 0)).unwrap());
+        assert!(schedule.is_active_at(utc(2026, 10, 24, 5, 59)).unwrap());
+        assert!(!schedule.is_active_at(utc(2026, 10, 24, 6, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_state_reports_next_change() {
+        let schedule = office_hours();
+
+        let state = schedule.state_at(utc(2026, 10, 19, 12, 30)).unwrap();
+        assert!(state.active);
+        assert_eq!(state.until, Some(utc(2026, 10, 19, 16, 0)));
+
+        // From Friday evening to Monday morning
+        let state = schedule.state_at(utc(2026, 10, 23, 17, 0)).unwrap();
+        assert!(!state.active);
+        assert_eq!(state.until, Some(utc(2026, 10, 26, 8, 0)));
+
+        let never = Schedule::default().state_at(utc(2026, 10, 19, 12, 0));
+        assert_eq!(never.unwrap().until, None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut schedule = office_hours();
+        assert!(schedule.validate().is_empty());
+
+        schedule.time_zone = Some("Mars/Olympus_Mons".to_string());
+        schedule.windows[0].days.clear();
+        let fields: Vec<String> = schedule
+            .validate()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(fields, ["schedule.time_zone", "schedule.windows[0]"]);
+
+        let fields: Vec<String> = Schedule::default()
+            .validate()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(fields, ["schedule.windows"]);
+    }
+}
@@ -0,0 +1,1016 @@
+use crate::read_write_settings::{
+    notify_settings_changed, patched, sync_parent_dir, with_suffix, write_synced, FieldError,
+    FieldErrorCode, Settings, SettingsChange, SettingsError, SettingsStore, SETTINGS_FILE,
+};
+use crate::settings_migration::migrate;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+/// Version of the bundle format written by this version of the app
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Command-line subcommand writing a settings bundle
+pub const EXPORT_COMMAND: &str = "export-settings";
+
+/// Command-line subcommand applying a settings bundle
+pub const IMPORT_COMMAND: &str = "import-settings";
+
+/// Settings tied to the machine they were set up on, left out of bundles unless asked for: the
+/// device paired with the machine, in the settings and in each profile. RSSI baselines are
+/// measured while listening and never saved, so bundles never hold them.
+pub const DEVICE_KEYS: [&str; 1] = ["target_uuid"];
+
+/// Settings holding commands whose `env` maps, which may carry tokens or other secrets, are
+/// left out of bundles unless asked for, in the settings and in each profile
+pub const ENV_KEYS: [&str; 3] = ["custom_lock_command", "pre_lock_hooks", "on_return_hooks"];
+
+/// How a bundle is applied to the settings of the machine
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// The bundle is merged into the settings, which keep what the bundle does not set, such
+    /// as their other profiles
+    #[default]
+    Merge,
+    /// The bundle replaces the settings, those it does not set going back to their defaults
+    Replace,
+}
+
+impl std::str::FromStr for ImportMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "merge" => Ok(Self::Merge),
+            "replace" => Ok(Self::Replace),
+            _ => Err(format!(
+                "Unknown import mode '{}', expected 'merge' or 'replace'",
+                mode
+            )),
+        }
+    }
+}
+
+/// Settings exported to set up other machines the same way
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SettingsBundle {
+    pub bundle_version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// Whether the settings include the [`DEVICE_KEYS`]
+    pub includes_devices: bool,
+    /// Whether the commands of the [`ENV_KEYS`] include their `env` maps
+    #[serde(default)]
+    pub includes_env: bool,
+    /// Settings as saved in the settings file, with their schema version
+    pub settings: Value,
+}
+
+/// A setting changed by an import, with its values before and after
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImportChange {
+    pub key: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// What an import changed, or would change for a dry run
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub changes: Vec<ImportChange>,
+    /// Settings after the import
+    pub settings: Settings,
+}
+
+impl ImportReport {
+    fn new(before: &Settings, change: SettingsChange, dry_run: bool) -> Result<Self, String> {
+        let to_value = |settings: &Settings| {
+            serde_json::to_value(settings)
+                .map_err(|e| format!("Failed to serialize settings: {}", e))
+        };
+        let (before_value, after_value) = (to_value(before)?, to_value(&change.settings)?);
+        let changes = change
+            .changed
+            .into_iter()
+            .map(|key| ImportChange {
+                before: before_value.get(&key).cloned().unwrap_or(Value::Null),
+                after: after_value.get(&key).cloned().unwrap_or(Value::Null),
+                key,
+            })
+            .collect();
+        Ok(ImportReport {
+            dry_run,
+            changes,
+            settings: change.settings,
+        })
+    }
+
+    fn settings_change(&self) -> SettingsChange {
+        SettingsChange {
+            changed: self
+                .changes
+                .iter()
+                .map(|change| change.key.clone())
+                .collect(),
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+impl SettingsBundle {
+    /// Bundles the settings, leaving out the [`DEVICE_KEYS`] unless `include_devices` is set
+    /// and the `env` maps of the [`ENV_KEYS`] unless `include_env` is set
+    pub fn export(
+        settings: &Settings,
+        include_devices: bool,
+        include_env: bool,
+    ) -> Result<Self, String> {
+        let mut value = serde_json::to_value(settings)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        if let Some(settings) = value.as_object_mut() {
+            if !include_devices {
+                remove_devices(settings);
+            }
+            if !include_env {
+                remove_env(settings);
+            }
+        }
+        Ok(SettingsBundle {
+            bundle_version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            includes_devices: include_devices,
+            includes_env: include_env,
+            settings: value,
+        })
+    }
+
+    /// Writes the bundle as a JSON file, only replacing an existing file if it is a bundle.
+    ///
+    /// Like the settings file, the bundle is written to a temporary file only readable by the
+    /// user, flushed to disk and renamed over the destination.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if path.exists() && !is_bundle_file(path) {
+            return Err(format!(
+                "Refusing to overwrite '{}', which is not a settings bundle",
+                path.display()
+            ));
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize settings bundle: {}", e))?;
+        let temp_path = with_suffix(path, ".tmp");
+        if let Err(e) = write_synced(&temp_path, json.as_bytes()) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!(
+                "Failed to write settings bundle '{}': {}",
+                temp_path.display(),
+                e
+            ));
+        }
+        fs::rename(&temp_path, path).map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!(
+                "Failed to write settings bundle '{}': {}",
+                path.display(),
+                e
+            )
+        })?;
+        sync_parent_dir(path);
+        Ok(())
+    }
+
+    /// Reads a bundle from a JSON file, rejecting bundles of a newer format
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read settings bundle '{}': {}", path.display(), e))?;
+        // The error does not quote the file, which may be any file of the user
+        let bundle: Self = serde_json::from_str(&json).map_err(|e| {
+            format!(
+                "Failed to parse settings bundle '{}': not a settings bundle (line {}, column {})",
+                path.display(),
+                e.line(),
+                e.column()
+            )
+        })?;
+        if bundle.bundle_version == 0 || bundle.bundle_version > BUNDLE_VERSION {
+            return Err(format!(
+                "Settings bundle version {} is not supported, expected at most {}",
+                bundle.bundle_version, BUNDLE_VERSION
+            ));
+        }
+        Ok(bundle)
+    }
+
+    /// Returns the settings resulting from applying the bundle to `current`.
+    ///
+    /// Settings from an older schema are upgraded first. When the bundle leaves out the
+    /// [`DEVICE_KEYS`], those of `current` are kept, new profiles getting the device of the
+    /// settings. Likewise, commands of the [`ENV_KEYS`] without their `env` map get the one of
+    /// the command of `current` with the same argv.
+    pub fn apply_to(
+        &self,
+        current: &Settings,
+        mode: ImportMode,
+    ) -> Result<Settings, SettingsError> {
+        let mut patch = self.settings.clone();
+        migrate(&mut patch).map_err(|e| {
+            SettingsError::Invalid(vec![FieldError::new(
+                "schema_version",
+                FieldErrorCode::InvalidValue,
+                e,
+            )])
+        })?;
+        if let Some(settings) = patch.as_object_mut() {
+            keep_devices(settings, current);
+            if !self.includes_env {
+                keep_env(settings, current);
+            }
+        }
+        match mode {
+            ImportMode::Merge => patched(current, &patch),
+            ImportMode::Replace => patched(&Settings::default(), &patch),
+        }
+    }
+
+    /// Applies the bundle to the settings of the store, or only reports what would change when
+    /// `dry_run` is set. Invalid settings are rejected as a whole.
+    pub fn import(
+        &self,
+        store: &SettingsStore,
+        mode: ImportMode,
+        dry_run: bool,
+    ) -> Result<ImportReport, SettingsError> {
+        let mut before = None;
+        let change = if dry_run {
+            let current = store.get();
+            let change = store.preview(self.apply_to(&current, mode)?)?;
+            before = Some(current);
+            change
+        } else {
+            store.modify(|settings| {
+                before = Some(settings.clone());
+                *settings = self.apply_to(settings, mode)?;
+                Ok(())
+            })?
+        };
+        let before = before.unwrap_or_default();
+        ImportReport::new(&before, change, dry_run).map_err(SettingsError::Failed)
+    }
+}
+
+/// Returns whether the file holds a settings bundle, of any version
+fn is_bundle_file(path: &Path) -> bool {
+    fs::read(path)
+        .map(|bytes| serde_json::from_slice::<SettingsBundle>(&bytes).is_ok())
+        .unwrap_or(false)
+}
+
+/// Checks a bundle path received from the webview, which may only point the commands at
+/// `.json` files given by their absolute path
+fn check_bundle_path(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err(format!(
+            "Settings bundle path '{}' is not absolute",
+            path.display()
+        ));
+    }
+    if path.extension() != Some(OsStr::new("json")) {
+        return Err(format!(
+            "Settings bundle '{}' is not a .json file",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn remove_devices(settings: &mut Map<String, Value>) {
+    let profiles = settings.get_mut("profiles").and_then(Value::as_object_mut);
+    for profile in profiles
+        .into_iter()
+        .flat_map(|profiles| profiles.values_mut())
+    {
+        if let Some(profile) = profile.as_object_mut() {
+            for key in DEVICE_KEYS {
+                profile.remove(key);
+            }
+        }
+    }
+    for key in DEVICE_KEYS {
+        settings.remove(key);
+    }
+}
+
+fn remove_env(settings: &mut Map<String, Value>) {
+    let profiles = settings.get_mut("profiles").and_then(Value::as_object_mut);
+    for profile in profiles
+        .into_iter()
+        .flat_map(|profiles| profiles.values_mut())
+    {
+        if let Some(profile) = profile.as_object_mut() {
+            remove_command_env(profile);
+        }
+    }
+    remove_command_env(settings);
+}
+
+fn remove_command_env(settings: &mut Map<String, Value>) {
+    for key in ENV_KEYS {
+        match settings.get_mut(key) {
+            Some(Value::Object(command)) => {
+                command.remove("env");
+            }
+            Some(Value::Array(commands)) => {
+                for command in commands.iter_mut().filter_map(Value::as_object_mut) {
+                    command.remove("env");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn keep_devices(settings: &mut Map<String, Value>, current: &Settings) {
+    if let Some(Value::Object(profiles)) = settings.get_mut("profiles") {
+        for (name, profile) in profiles.iter_mut() {
+            if let Value::Object(profile) = profile {
+                if !profile.contains_key("target_uuid") {
+                    let target_uuid = current
+                        .profiles
+                        .get(name)
+                        .map_or(&current.target_uuid, |profile| &profile.target_uuid);
+                    profile.insert("target_uuid".to_string(), Value::from(target_uuid.clone()));
+                }
+            }
+        }
+    }
+    if !settings.contains_key("target_uuid") {
+        settings.insert(
+            "target_uuid".to_string(),
+            Value::from(current.target_uuid.clone()),
+        );
+    }
+}
+
+fn keep_env(settings: &mut Map<String, Value>, current: &Settings) {
+    let Ok(Value::Object(current)) = serde_json::to_value(current) else {
+        return;
+    };
+    if let Some(Value::Object(profiles)) = settings.get_mut("profiles") {
+        for (name, profile) in profiles.iter_mut() {
+            if let Value::Object(profile) = profile {
+                let local = current
+                    .get("profiles")
+                    .and_then(|profiles| profiles.get(name))
+                    .and_then(Value::as_object)
+                    .unwrap_or(&current);
+                keep_command_env(profile, local);
+            }
+        }
+    }
+    keep_command_env(settings, &current);
+}
+
+fn keep_command_env(settings: &mut Map<String, Value>, local: &Map<String, Value>) {
+    for key in ENV_KEYS {
+        match (settings.get_mut(key), local.get(key)) {
+            (Some(Value::Object(command)), Some(local)) => {
+                keep_env_of(command, std::slice::from_ref(local), 0);
+            }
+            (Some(Value::Array(commands)), Some(Value::Array(local))) => {
+                for (index, command) in commands.iter_mut().enumerate() {
+                    if let Value::Object(command) = command {
+                        keep_env_of(command, local, index);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Gives a command without an `env` map the one of the local command with the same argv,
+/// preferring the one at the same index
+fn keep_env_of(command: &mut Map<String, Value>, local: &[Value], index: usize) {
+    if command.contains_key("env") {
+        return;
+    }
+    let same_argv = |local: &&Value| local.get("argv") == command.get("argv");
+    let env = local
+        .get(index)
+        .filter(same_argv)
+        .or_else(|| local.iter().find(same_argv))
+        .and_then(|local| local.get("env"))
+        .cloned();
+    if let Some(env) = env {
+        command.insert("env".to_string(), env);
+    }
+}
+
+/// Writes the current settings to a bundle file, given by its absolute path with a `.json`
+/// extension. An existing file is only replaced if it is a bundle.
+#[tauri::command]
+pub fn export_settings(
+    store: State<'_, SettingsStore>,
+    path: PathBuf,
+    include_devices: bool,
+    include_env: bool,
+) -> Result<(), String> {
+    check_bundle_path(&path)?;
+    SettingsBundle::export(&store.get(), include_devices, include_env)?.write(&path)
+}
+
+/// Applies a bundle file, given by its absolute path with a `.json` extension, to the
+/// settings, or only reports what would change when `dry_run` is set
+#[tauri::command]
+pub fn import_settings(
+    app_handle: AppHandle,
+    store: State<'_, SettingsStore>,
+    path: PathBuf,
+    mode: ImportMode,
+    dry_run: bool,
+) -> Result<ImportReport, SettingsError> {
+    check_bundle_path(&path).map_err(SettingsError::Failed)?;
+    let bundle = SettingsBundle::read(&path).map_err(SettingsError::Failed)?;
+    let report = bundle.import(&store, mode, dry_run)?;
+    if !dry_run {
+        notify_settings_changed(&app_handle, &report.settings_change());
+    }
+    Ok(report)
+}
+
+/// A settings bundle subcommand given on the command line
+#[derive(Clone, Debug, PartialEq)]
+pub enum CliCommand {
+    Export {
+        path: PathBuf,
+        include_devices: bool,
+        include_env: bool,
+    },
+    Import {
+        path: PathBuf,
+        mode: ImportMode,
+        dry_run: bool,
+    },
+}
+
+impl CliCommand {
+    /// Usage of the subcommands
+    pub const USAGE: &'static str = "Usage:
+  lock-4-me export-settings <path> [--include-devices] [--include-env]
+  lock-4-me import-settings <path> [--mode merge|replace] [--dry-run]";
+
+    /// Parses the arguments following the program name, returning `None` when they do not
+    /// start with a settings bundle subcommand
+    pub fn parse(args: &[String]) -> Option<Result<Self, String>> {
+        let (command, args) = args.split_first()?;
+        if command != EXPORT_COMMAND && command != IMPORT_COMMAND {
+            return None;
+        }
+        let mut path = None;
+        let mut include_devices = false;
+        let mut include_env = false;
+        let mut mode = ImportMode::default();
+        let mut dry_run = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--include-devices" if command == EXPORT_COMMAND => include_devices = true,
+                "--include-env" if command == EXPORT_COMMAND => include_env = true,
+                "--dry-run" if command == IMPORT_COMMAND => dry_run = true,
+                "--mode" if command == IMPORT_COMMAND => {
+                    let Some(value) = args.next() else {
+                        return Some(Err("Missing value for --mode".to_string()));
+                    };
+                    match value.parse() {
+                        Ok(value) => mode = value,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                _ if !arg.starts_with("--") && path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => return Some(Err(format!("Unexpected argument '{}'", arg))),
+            }
+        }
+        let Some(path) = path else {
+            return Some(Err(format!("Missing bundle path for {}", command)));
+        };
+        Some(Ok(if command == EXPORT_COMMAND {
+            Self::Export {
+                path,
+                include_devices,
+                include_env,
+            }
+        } else {
+            Self::Import {
+                path,
+                mode,
+                dry_run,
+            }
+        }))
+    }
+
+    /// Runs the subcommand on the settings of the app with the given identifier, as stored by
+    /// the app itself, returning the process exit code.
+    ///
+    /// A running app picks up imported settings as it watches the settings file.
+    pub fn run(&self, identifier: &str) -> i32 {
+        let store = match (dirs::config_dir(), dirs::data_dir()) {
+            (Some(config_dir), Some(data_dir)) => SettingsStore::in_dir(
+                &config_dir.join(identifier),
+                Some(&data_dir.join(identifier).join(SETTINGS_FILE)),
+            ),
+            _ => {
+                eprintln!("Failed to find the settings directory");
+                return 1;
+            }
+        };
+        match self.run_on(&store) {
+            Ok(output) => {
+                println!("{}", output);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    }
+
+    /// Runs the subcommand on the settings of the store, returning what to print
+    pub fn run_on(&self, store: &SettingsStore) -> Result<String, String> {
+        match self {
+            Self::Export {
+                path,
+                include_devices,
+                include_env,
+            } => {
+                SettingsBundle::export(&store.get(), *include_devices, *include_env)?
+                    .write(path)?;
+                Ok(format!("Exported settings to '{}'", path.display()))
+            }
+            Self::Import {
+                path,
+                mode,
+                dry_run,
+            } => {
+                let report = SettingsBundle::read(path)?
+                    .import(store, *mode, *dry_run)
+                    .map_err(|e| e.to_string())?;
+                let mut lines: Vec<String> = report
+                    .changes
+                    .iter()
+                    .map(|change| {
+                        format!("  {}: {} -> {}", change.key, change.before, change.after)
+                    })
+                    .collect();
+                lines.insert(
+                    0,
+                    match (report.dry_run, report.changes.len()) {
+                        (true, 0) => "No settings would change".to_string(),
+                        (false, 0) => "No settings changed".to_string(),
+                        (true, count) => format!("{} settings would change:", count),
+                        (false, count) => format!("{} settings changed:", count),
+                    },
+                );
+                Ok(lines.join("\n"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::Hook;
+    use crate::profiles::Profile;
+    use crate::read_write_settings::{CustomLockCommand, Theme};
+    use std::collections::BTreeMap;
+    use tempfile::{tempdir, TempDir};
+
+    const HOME_DEVICE: &str = "4f1c2d3e-5a6b-4c7d-8e9f-0a1b2c3d4e5f";
+    const OFFICE_DEVICE: &str = "9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c6d";
+    const OTHER_DEVICE: &str = "0b1c2d3e-4f5a-4b6c-9d7e-8f9a0b1c2d3e";
+
+    /// Settings of the machine a bundle is exported from
+    fn reference_settings() -> Settings {
+        let mut settings = Settings {
+            target_uuid: HOME_DEVICE.to_string(),
+            rssi_delta_max: 25,
+            theme: Theme::Light,
+            min_idle_secs: Some(30),
+            ..Default::default()
+        };
+        settings.create_profile("home").unwrap();
+        settings.profiles.insert(
+            "office".to_string(),
+            Profile {
+                target_uuid: OFFICE_DEVICE.to_string(),
+                rssi_delta_max: 10,
+                ..Profile::of(&settings)
+            },
+        );
+        settings
+    }
+
+    /// A store on another machine, paired with its own device and with a profile of its own
+    fn other_store(dir: &TempDir) -> SettingsStore {
+        let store = SettingsStore::open(dir.path().join(SETTINGS_FILE));
+        store
+            .modify(|settings| {
+                settings.target_uuid = OTHER_DEVICE.to_string();
+                settings.lock_warning_secs = 5;
+                settings.create_profile("travel")
+            })
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn test_export_leaves_out_devices_unless_asked() {
+        let bundle = SettingsBundle::export(&reference_settings(), false, false).unwrap();
+        assert_eq!(bundle.bundle_version, BUNDLE_VERSION);
+        assert!(!bundle.includes_devices);
+        assert!(bundle.settings.get("target_uuid").is_none());
+        assert!(bundle.settings["profiles"]["office"]
+            .get("target_uuid")
+            .is_none());
+        assert_eq!(bundle.settings["rssi_delta_max"], 25);
+
+        let bundle = SettingsBundle::export(&reference_settings(), true, false).unwrap();
+        assert_eq!(bundle.settings["target_uuid"], HOME_DEVICE);
+        assert_eq!(
+            bundle.settings["profiles"]["office"]["target_uuid"],
+            OFFICE_DEVICE
+        );
+    }
+
+    #[test]
+    fn test_export_leaves_out_env_unless_asked() {
+        let env = BTreeMap::from([("API_TOKEN".to_string(), "secret".to_string())]);
+        let hook = Hook {
+            argv: vec!["notify-send".to_string()],
+            env: env.clone(),
+            timeout_secs: None,
+        };
+        let mut settings = reference_settings();
+        settings.custom_lock_command = Some(CustomLockCommand {
+            argv: vec!["xsecurelock".to_string()],
+            env: env.clone(),
+            timeout_secs: None,
+            exclusive: false,
+        });
+        settings.pre_lock_hooks = vec![hook.clone()];
+        settings.on_return_hooks = vec![hook.clone()];
+        settings.profiles.get_mut("office").unwrap().pre_lock_hooks = vec![hook];
+
+        let bundle = SettingsBundle::export(&settings, false, false).unwrap();
+        assert!(!bundle.includes_env);
+        let exported = serde_json::to_string(&bundle.settings).unwrap();
+        assert!(!exported.contains("API_TOKEN"));
+        assert_eq!(
+            bundle.settings["custom_lock_command"]["argv"][0],
+            "xsecurelock"
+        );
+        assert_eq!(
+            bundle.settings["pre_lock_hooks"][0]["argv"][0],
+            "notify-send"
+        );
+
+        let bundle = SettingsBundle::export(&settings, false, true).unwrap();
+        assert_eq!(
+            bundle.settings["custom_lock_command"]["env"]["API_TOKEN"],
+            "secret"
+        );
+        assert_eq!(
+            bundle.settings["on_return_hooks"][0]["env"]["API_TOKEN"],
+            "secret"
+        );
+        assert_eq!(
+            bundle.settings["profiles"]["office"]["pre_lock_hooks"][0]["env"]["API_TOKEN"],
+            "secret"
+        );
+    }
+
+    #[test]
+    fn test_import_keeps_local_env() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bundle.json");
+        let with_env = |value: &str| {
+            let env = BTreeMap::from([("API_TOKEN".to_string(), value.to_string())]);
+            let hook = Hook {
+                argv: vec!["true".to_string()],
+                env: env.clone(),
+                timeout_secs: None,
+            };
+            let command = CustomLockCommand {
+                argv: vec!["true".to_string()],
+                env,
+                timeout_secs: None,
+                exclusive: false,
+            };
+            (hook, command)
+        };
+        let store = other_store(&dir);
+        let (hook, command) = with_env("local");
+        store
+            .modify(|settings| {
+                settings.custom_lock_command = Some(command);
+                settings.pre_lock_hooks = vec![hook.clone()];
+                settings.on_return_hooks = vec![hook.clone()];
+                settings.create_profile("office")
+            })
+            .unwrap();
+        let (hook, command) = with_env("exported");
+        let mut settings = reference_settings();
+        settings.custom_lock_command = Some(command);
+        settings.pre_lock_hooks = vec![hook.clone()];
+        settings.on_return_hooks = vec![hook.clone()];
+        settings.profiles.get_mut("office").unwrap().pre_lock_hooks = vec![hook];
+
+        for mode in [ImportMode::Merge, ImportMode::Replace] {
+            SettingsBundle::export(&settings, false, false)
+                .unwrap()
+                .write(&path)
+                .unwrap();
+            SettingsBundle::read(&path)
+                .unwrap()
+                .import(&store, mode, false)
+                .unwrap();
+            let imported = store.get();
+            let office = &imported.profiles["office"];
+            for env in [
+                &imported.custom_lock_command.as_ref().unwrap().env,
+                &imported.pre_lock_hooks[0].env,
+                &imported.on_return_hooks[0].env,
+                &office.pre_lock_hooks[0].env,
+            ] {
+                assert_eq!(env["API_TOKEN"], "local", "{:?}", mode);
+            }
+        }
+
+        SettingsBundle::export(&settings, false, true)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        SettingsBundle::read(&path)
+            .unwrap()
+            .import(&store, ImportMode::Merge, false)
+            .unwrap();
+        assert_eq!(store.get().pre_lock_hooks[0].env["API_TOKEN"], "exported");
+    }
+
+    #[test]
+    fn test_write_is_private() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bundle.json");
+        SettingsBundle::export(&reference_settings(), false, false)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        assert!(!with_suffix(&path, ".tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_device_and_other_profiles() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bundle.json");
+        SettingsBundle::export(&reference_settings(), false, false)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        let store = other_store(&dir);
+
+        let report = SettingsBundle::read(&path)
+            .unwrap()
+            .import(&store, ImportMode::Merge, false)
+            .unwrap();
+
+        let settings = store.get();
+        assert_eq!(settings.target_uuid, OTHER_DEVICE);
+        assert_eq!(settings.rssi_delta_max, 25);
+        assert_eq!(settings.theme, Theme::Light);
+        assert_eq!(
+            settings.profiles.keys().collect::<Vec<_>>(),
+            ["home", "office", "travel"]
+        );
+        assert_eq!(settings.profiles["office"].target_uuid, OTHER_DEVICE);
+        assert_eq!(Settings::load(store.path()).unwrap().rssi_delta_max, 25);
+        assert!(!report.dry_run);
+        let change = report
+            .changes
+            .iter()
+            .find(|change| change.key == "rssi_delta_max")
+            .unwrap();
+        assert_eq!((&change.before, &change.after), (&15.into(), &25.into()));
+    }
+
+    #[test]
+    fn test_replace_resets_what_the_bundle_does_not_set() {
+        let dir = tempdir().unwrap();
+        let store = other_store(&dir);
+        let mut bundle = SettingsBundle::export(&reference_settings(), false, false).unwrap();
+        bundle
+            .settings
+            .as_object_mut()
+            .unwrap()
+            .remove("min_idle_secs");
+
+        bundle.import(&store, ImportMode::Replace, false).unwrap();
+
+        let settings = store.get();
+        assert_eq!(settings.target_uuid, OTHER_DEVICE);
+        assert_eq!(settings.lock_warning_secs, 0);
+        assert_eq!(settings.min_idle_secs, None);
+        assert_eq!(
+            settings.profiles.keys().collect::<Vec<_>>(),
+            ["home", "office"]
+        );
+
+        // Devices included in the bundle are taken
+        let bundle = SettingsBundle::export(&reference_settings(), true, false).unwrap();
+        bundle.import(&store, ImportMode::Replace, false).unwrap();
+        assert_eq!(store.get().target_uuid, HOME_DEVICE);
+    }
+
+    #[test]
+    fn test_dry_run_reports_without_changing() {
+        let dir = tempdir().unwrap();
+        let store = other_store(&dir);
+        let saved = fs::read_to_string(store.path()).unwrap();
+        let bundle = SettingsBundle::export(&reference_settings(), false, false).unwrap();
+
+        let report = bundle.import(&store, ImportMode::Replace, true).unwrap();
+
+        assert!(report.dry_run);
+        let keys: Vec<&str> = report
+            .changes
+            .iter()
+            .map(|change| change.key.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "lock_warning_secs",
+                "min_idle_secs",
+                "profiles",
+                "rssi_delta_max",
+                "theme"
+            ]
+        );
+        assert_eq!(report.settings.rssi_delta_max, 25);
+        assert_eq!(store.get().rssi_delta_max, 15);
+        assert_eq!(fs::read_to_string(store.path()).unwrap(), saved);
+    }
+
+    #[test]
+    fn test_invalid_bundles_rejected() {
+        let dir = tempdir().unwrap();
+        let store = other_store(&dir);
+        let saved = store.get();
+        let mut bundle = SettingsBundle::export(&reference_settings(), false, false).unwrap();
+        bundle.settings["rssi_delta_max"] = 200.into();
+        bundle.settings["theme"] = "purple".into();
+
+        let error = bundle.import(&store, ImportMode::Merge, true).unwrap_err();
+        let SettingsError::Invalid(errors) = error else {
+            panic!("Expected field errors, got {:?}", error);
+        };
+        assert_eq!(errors[0].field, "theme");
+
+        bundle.settings["theme"] = "light".into();
+        let error = bundle.import(&store, ImportMode::Merge, false).unwrap_err();
+        let SettingsError::Invalid(errors) = error else {
+            panic!("Expected field errors, got {:?}", error);
+        };
+        assert_eq!(errors[0].field, "rssi_delta_max");
+        assert_eq!(store.get().rssi_delta_max, saved.rssi_delta_max);
+
+        bundle.settings["schema_version"] = 99.into();
+        assert!(bundle.import(&store, ImportMode::Merge, true).is_err());
+
+        let path = dir.path().join("newer.json");
+        bundle.bundle_version = BUNDLE_VERSION + 1;
+        bundle.write(&path).unwrap();
+        assert!(SettingsBundle::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_only_bundles_overwritten() {
+        let dir = tempdir().unwrap();
+        let bundle = SettingsBundle::export(&reference_settings(), false, false).unwrap();
+
+        let other = dir.path().join("notes.json");
+        fs::write(&other, "{\"bundle_version\": \"secret\"}").unwrap();
+        assert!(bundle
+            .write(&other)
+            .unwrap_err()
+            .contains("not a settings bundle"));
+        assert!(fs::read_to_string(&other).unwrap().contains("secret"));
+        let error = SettingsBundle::read(&other).unwrap_err();
+        assert!(!error.contains("secret"));
+
+        let path = dir.path().join("fleet.json");
+        bundle.write(&path).unwrap();
+        bundle.write(&path).unwrap();
+
+        assert!(check_bundle_path(&path).is_ok());
+        assert!(check_bundle_path(Path::new("fleet.json")).is_err());
+        assert!(check_bundle_path(&dir.path().join(".ssh").join("id_ed25519")).is_err());
+    }
+
+    #[test]
+    fn test_cli_arguments() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            CliCommand::parse(&args)
+        };
+
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--dry-run"]), None);
+        assert_eq!(
+            parse(&["export-settings", "fleet.json", "--include-devices"]),
+            Some(Ok(CliCommand::Export {
+                path: PathBuf::from("fleet.json"),
+                include_devices: true,
+                include_env: false,
+            }))
+        );
+        assert_eq!(
+            parse(&["export-settings", "--include-env", "fleet.json"]),
+            Some(Ok(CliCommand::Export {
+                path: PathBuf::from("fleet.json"),
+                include_devices: false,
+                include_env: true,
+            }))
+        );
+        assert_eq!(
+            parse(&[
+                "import-settings",
+                "--dry-run",
+                "fleet.json",
+                "--mode",
+                "replace"
+            ]),
+            Some(Ok(CliCommand::Import {
+                path: PathBuf::from("fleet.json"),
+                mode: ImportMode::Replace,
+                dry_run: true,
+            }))
+        );
+        assert!(matches!(parse(&["import-settings"]), Some(Err(_))));
+        assert!(matches!(
+            parse(&["import-settings", "fleet.json", "--mode", "overwrite"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse(&["export-settings", "fleet.json", "--dry-run"]),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn test_cli_import_report() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fleet.json");
+        let store = other_store(&dir);
+        CliCommand::Export {
+            path: path.clone(),
+            include_devices: false,
+            include_env: false,
+        }
+        .run_on(&store)
+        .unwrap();
+
+        let output = CliCommand::Import {
+            path: path.clone(),
+            mode: ImportMode::Merge,
+            dry_run: true,
+        }
+        .run_on(&store)
+        .unwrap();
+        assert_eq!(output, "No settings would change");
+
+        SettingsBundle::export(&reference_settings(), false, false)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        let output = CliCommand::Import {
+            path,
+            mode: ImportMode::Merge,
+            dry_run: false,
+        }
+        .run_on(&store)
+        .unwrap();
+        assert!(output.starts_with("5 settings changed:\n"));
+        assert!(output.contains("\n  rssi_delta_max: 15 -> 25"));
+    }
+}
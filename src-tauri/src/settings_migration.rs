@@ -0,0 +1,145 @@
+use serde_json::{Map, Value};
+
+/// Version of the settings schema written by this version of the app
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades the settings object by one schema version
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// Migrations in order, the one at index `n` upgrading from version `n` to `n + 1`
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Returns the schema version of a settings object, `0` for files written before versioning
+pub fn schema_version(settings: &Value) -> Result<u32, String> {
+    match settings.get("schema_version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("Invalid settings schema version: {}", version)),
+    }
+}
+
+/// Upgrades a settings object to [`CURRENT_SCHEMA_VERSION`], returning the version it had.
+///
+/// Settings written by a newer version of the app are rejected rather than risk losing what
+/// this version does not know about.
+pub fn migrate(settings: &mut Value) -> Result<u32, String> {
+    let from = schema_version(settings)?;
+    if from > CURRENT_SCHEMA_VERSION {
+        return Err(format!(
+            "Settings schema version {} is newer than the supported version {}",
+            from, CURRENT_SCHEMA_VERSION
+        ));
+    }
+    let object = settings
+        .as_object_mut()
+        .ok_or_else(|| "Settings are not a JSON object".to_string())?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(object).map_err(|e| {
+            format!(
+                "Failed to migrate settings from schema version {}: {}",
+                version, e
+            )
+        })?;
+        object.insert("schema_version".to_string(), Value::from(version + 1));
+    }
+    Ok(from)
+}
+
+/// Unversioned files required the device, threshold, theme and language and had optional
+/// lock, hook and listener fields added over time. The required fields get their defaults when
+/// missing or null so that a partial file keeps the values it has.
+fn migrate_v0_to_v1(settings: &mut Map<String, Value>) -> Result<(), String> {
+    let defaults = [
+        ("target_uuid", Value::from("")),
+        ("rssi_delta_max", Value::from(15)),
+        ("theme", Value::from("dark")),
+        ("language", Value::from("en")),
+    ];
+    for (key, default) in defaults {
+        if settings.get(key).is_none_or(Value::is_null) {
+            settings.insert(key.to_string(), default);
+        }
+    }
+    Ok(())
+}
+
+/// Version 1 took any string as theme and language, version 2 only the supported ones. Known
+/// values in another case or with a region (`fr-FR`) are kept, others fall back to the defaults.
+fn migrate_v1_to_v2(settings: &mut Map<String, Value>) -> Result<(), String> {
+    let fields = [
+        ("theme", ["light", "dark"], "dark"),
+        ("language", ["en", "fr"], "en"),
+    ];
+    for (key, supported, default) in fields {
+        let value = settings
+            .get(key)
+            .and_then(Value::as_str)
+            .map(|value| {
+                value
+                    .split(['-', '_'])
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase()
+            })
+            .filter(|value| supported.contains(&value.as_str()))
+            .unwrap_or_else(|| default.to_string());
+        settings.insert(key.to_string(), Value::from(value));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_schema_version_of_unversioned_settings() {
+        assert_eq!(schema_version(&json!({"theme": "dark"})), Ok(0));
+        assert_eq!(schema_version(&json!({"schema_version": 1})), Ok(1));
+        assert!(schema_version(&json!({"schema_version": "one"})).is_err());
+    }
+
+    #[test]
+    fn test_migrate_fills_required_fields() {
+        let mut settings = json!({"target_uuid": "device", "language": null});
+
+        assert_eq!(migrate(&mut settings), Ok(0));
+
+        assert_eq!(settings["schema_version"], CURRENT_SCHEMA_VERSION);
+        assert_eq!(settings["target_uuid"], "device");
+        assert_eq!(settings["rssi_delta_max"], 15);
+        assert_eq!(settings["language"], "en");
+    }
+
+    #[test]
+    fn test_migrate_normalizes_theme_and_language() {
+        let mut settings = json!({"schema_version": 1, "theme": "Light", "language": "fr-FR"});
+        migrate(&mut settings).unwrap();
+        assert_eq!(settings["theme"], "light");
+        assert_eq!(settings["language"], "fr");
+
+        let mut settings = json!({"schema_version": 1, "theme": "", "language": "zh-CN"});
+        migrate(&mut settings).unwrap();
+        assert_eq!(settings["theme"], "dark");
+        assert_eq!(settings["language"], "en");
+    }
+
+    #[test]
+    fn test_migrate_current_settings_unchanged() {
+        let mut settings = json!({"schema_version": CURRENT_SCHEMA_VERSION, "theme": "light"});
+        let original = settings.clone();
+
+        assert_eq!(migrate(&mut settings), Ok(CURRENT_SCHEMA_VERSION));
+        assert_eq!(settings, original);
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_settings() {
+        let mut settings = json!({"schema_version": CURRENT_SCHEMA_VERSION + 1});
+        assert!(migrate(&mut settings).unwrap_err().contains("newer"));
+    }
+}
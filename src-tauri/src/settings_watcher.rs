@@ -0,0 +1,39 @@
+use crate::file_watcher::FileWatcher;
+use crate::read_write_settings::{notify_settings_changed, SettingsStore};
+use log::{error, info};
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// How long the settings file must stay untouched before it is read back, so that an editor
+/// writing it in several steps is only reloaded once
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the settings file for edits made outside the app, creating its directory first as
+/// the settings may not have been saved yet
+pub fn watch_settings(app_handle: AppHandle, path: &Path) -> Result<FileWatcher, String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    }
+    FileWatcher::start(path, RELOAD_DEBOUNCE, move || reload_settings(&app_handle))
+}
+
+/// Applies the settings file after it was edited outside the app, logging edits that are
+/// rejected
+pub fn reload_settings(app_handle: &AppHandle) {
+    let store = app_handle.state::<SettingsStore>();
+    match store.reload() {
+        Ok(change) => {
+            if !change.changed.is_empty() {
+                info!("Reloaded settings edited in '{}'", store.path().display());
+            }
+            notify_settings_changed(app_handle, &change);
+        }
+        Err(e) => error!(
+            "Ignored edit of '{}', keeping the current settings: {}",
+            store.path().display(),
+            e
+        ),
+    }
+}
@@ -0,0 +1,186 @@
+use log::info;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+
+#[cfg(target_os = "linux")]
+use futures::stream::StreamExt;
+#[cfg(target_os = "linux")]
+use log::warn;
+
+/// How long sleep may be delayed while scanning is stopped
+#[cfg(target_os = "linux")]
+const SLEEP_DELAY: Duration = Duration::from_secs(2);
+
+/// Grace period after resuming before a timeout can lock the screen, unless configured
+pub const RESUME_GRACE: Duration = Duration::from_secs(30);
+
+/// A transition of the machine in or out of sleep
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepEvent {
+    Sleeping,
+    Resumed,
+}
+
+/// Tracks suspend and resume of the machine.
+///
+/// On Linux the state follows logind's `PrepareForSleep` signal. A delay inhibitor lock is held
+/// while awake so that scanning can be stopped before the machine sleeps: it is released once
+/// [`SleepMonitor::ready_for_sleep`] is called, or after a short delay. Cloning the monitor
+/// shares the same state.
+#[derive(Clone)]
+pub struct SleepMonitor {
+    sleeping: Arc<AtomicBool>,
+    events: broadcast::Sender<SleepEvent>,
+    #[cfg(target_os = "linux")]
+    inhibitor: Arc<Mutex<Option<zbus::zvariant::OwnedFd>>>,
+    #[cfg(not(target_os = "linux"))]
+    inhibitor: Arc<Mutex<Option<()>>>,
+}
+
+impl Default for SleepMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SleepMonitor {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        SleepMonitor {
+            sleeping: Arc::new(AtomicBool::new(false)),
+            events,
+            inhibitor: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns whether the machine is about to sleep or sleeping
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping.load(Ordering::SeqCst)
+    }
+
+    /// Subscribes to sleep and resume events
+    pub fn subscribe(&self) -> broadcast::Receiver<SleepEvent> {
+        self.events.subscribe()
+    }
+
+    /// Records the sleep state, broadcasting an event only when it changes
+    pub fn set_sleeping(&self, sleeping: bool) {
+        if self.sleeping.swap(sleeping, Ordering::SeqCst) == sleeping {
+            return;
+        }
+        let event = if sleeping {
+            SleepEvent::Sleeping
+        } else {
+            SleepEvent::Resumed
+        };
+        info!("Sleep state changed: {:?}", event);
+        let _ = self.events.send(event);
+    }
+
+    /// Lets the machine sleep by releasing the inhibitor lock
+    pub fn ready_for_sleep(&self) {
+        if self.inhibitor.lock().unwrap().take().is_some() {
+            info!("Sleep inhibitor released");
+        }
+    }
+
+    /// Returns whether an inhibitor lock is delaying sleep
+    pub fn is_inhibiting(&self) -> bool {
+        self.inhibitor.lock().unwrap().is_some()
+    }
+
+    /// Takes a logind delay inhibitor lock for sleep
+    #[cfg(target_os = "linux")]
+    async fn inhibit(&self, manager: &crate::logind::LoginManagerProxy<'_>) -> Result<(), String> {
+        let fd = manager
+            .inhibit(
+                "sleep",
+                "Lock-4-me",
+                "Stop Bluetooth scanning before sleep",
+                "delay",
+            )
+            .await
+            .map_err(|e| format!("Failed to take sleep inhibitor: {}", e))?;
+        *self.inhibitor.lock().unwrap() = Some(fd);
+        Ok(())
+    }
+
+    /// Starts watching logind's `PrepareForSleep` signal on the system bus
+    #[cfg(target_os = "linux")]
+    pub async fn watch_logind(&self, system_bus: &zbus::Connection) -> Result<(), String> {
+        let manager = crate::logind::LoginManagerProxy::new(system_bus)
+            .await
+            .map_err(|e| format!("Failed to reach logind: {}", e))?;
+        let mut signals = manager
+            .receive_prepare_for_sleep()
+            .await
+            .map_err(|e| format!("Failed to subscribe to PrepareForSleep: {}", e))?;
+        if let Err(e) = self.inhibit(&manager).await {
+            warn!("{}, scanning may still run when sleeping", e);
+        }
+
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = signals.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+                monitor.set_sleeping(args.start);
+                if args.start {
+                    // Let the machine sleep even if nobody reports being ready
+                    let releaser = monitor.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(SLEEP_DELAY).await;
+                        releaser.ready_for_sleep();
+                    });
+                } else if let Err(e) = monitor.inhibit(&manager).await {
+                    warn!("{}, scanning may still run when sleeping", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Starts watching for sleep when supported, logging when it is unavailable
+    pub async fn start(&self) {
+        #[cfg(target_os = "linux")]
+        match zbus::Connection::system().await {
+            Ok(system_bus) => {
+                if let Err(e) = self.watch_logind(&system_bus).await {
+                    warn!("Sleep events from logind unavailable: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to connect to system bus: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor_starts_awake() {
+        let monitor = SleepMonitor::new();
+        assert!(!monitor.is_sleeping());
+        assert!(!monitor.is_inhibiting());
+    }
+
+    #[test]
+    fn test_set_sleeping_emits_on_change_only() {
+        let monitor = SleepMonitor::new();
+        let mut events = monitor.subscribe();
+
+        monitor.set_sleeping(true);
+        monitor.set_sleeping(true);
+        monitor.set_sleeping(false);
+
+        assert_eq!(events.try_recv().unwrap(), SleepEvent::Sleeping);
+        assert_eq!(events.try_recv().unwrap(), SleepEvent::Resumed);
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the automatic unlock, run against a private bus with a stub logind
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::auto_unlock::AutoUnlock;
+use lock_4_me_lib::dbus_lock::unlock_logind_session;
+use lock_4_me_lib::lock_pipeline::{
+    LockContext, LockOutcome, LockPipeline, LockPipelineEvent, LockReason,
+};
+use lock_4_me_lib::lock_state::LockStateMonitor;
+use lock_4_me_lib::read_write_settings::{CustomLockCommand, Settings};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::time::Duration;
+use zbus::zvariant::OwnedObjectPath;
+
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/_32";
+
+struct StubManager;
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubManager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+}
+
+struct StubSession {
+    unlocks: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl StubSession {
+    fn unlock(&self) {
+        self.unlocks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> bool {
+        false
+    }
+}
+
+async fn serve_logind(bus: &PrivateBus) -> (zbus::Connection, Arc<AtomicUsize>) {
+    let unlocks = Arc::new(AtomicUsize::new(0));
+    let logind = bus
+        .builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubManager)
+        .unwrap()
+        .serve_at(
+            SESSION_PATH,
+            StubSession {
+                unlocks: unlocks.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    (logind, unlocks)
+}
+
+/// A pipeline locking with a slow no-op command and unlocking through the stub logind, with a
+/// monitor watching the stub session
+async fn setup(bus: &PrivateBus, dwell_secs: u64) -> (LockStateMonitor, LockPipeline) {
+    let client = bus.connect().await;
+    let monitor = LockStateMonitor::new();
+    monitor.watch_logind(&client).await.unwrap();
+    let pipeline = LockPipeline::new(monitor.clone(), false).with_system_bus(client);
+    pipeline.apply_settings(&Settings {
+        custom_lock_command: Some(CustomLockCommand {
+            argv: vec!["sleep".to_string(), "1".to_string()],
+            env: Default::default(),
+            timeout_secs: None,
+            exclusive: true,
+        }),
+        auto_unlock: Some(AutoUnlock {
+            within_secs: 60,
+            min_rssi: -60,
+            dwell_secs,
+        }),
+        ..Default::default()
+    });
+    (monitor, pipeline)
+}
+
+/// Requests a lock, reporting the session as locked while the lock command runs
+async fn lock(monitor: &LockStateMonitor, pipeline: &LockPipeline, reason: LockReason) {
+    let request = tokio::spawn({
+        let pipeline = pipeline.clone();
+        async move { pipeline.request_lock(LockContext::new(reason)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    monitor.set_locked(true);
+    assert_eq!(request.await.unwrap(), Ok(LockOutcome::Locked));
+}
+
+#[tokio::test]
+async fn test_unlock_logind_session_calls_session_unlock() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, unlocks) = serve_logind(&bus).await;
+    let client = bus.connect().await;
+
+    let result = unlock_logind_session(&client).await;
+
+    assert!(result.is_ok(), "logind unlock should succeed: {:?}", result);
+    assert_eq!(unlocks.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_auto_unlock_after_dwell() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, unlocks) = serve_logind(&bus).await;
+    let (monitor, pipeline) = setup(&bus, 1).await;
+    let mut events = pipeline.subscribe();
+
+    lock(&monitor, &pipeline, LockReason::OverDelta).await;
+    assert!(pipeline.may_auto_unlock());
+
+    assert_eq!(pipeline.auto_unlock(-50).await, Ok(false));
+    // A weak reading restarts the dwell period
+    assert_eq!(pipeline.auto_unlock(-70).await, Ok(false));
+    assert_eq!(pipeline.auto_unlock(-50).await, Ok(false));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(pipeline.auto_unlock(-55).await, Ok(true));
+
+    assert_eq!(unlocks.load(Ordering::SeqCst), 1);
+    assert!(!pipeline.may_auto_unlock());
+    match events.try_recv().unwrap() {
+        LockPipelineEvent::AutoUnlocked(context) => assert_eq!(context.rssi, Some(-55)),
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_no_auto_unlock_after_user_relocked() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, unlocks) = serve_logind(&bus).await;
+    let (monitor, pipeline) = setup(&bus, 0).await;
+
+    lock(&monitor, &pipeline, LockReason::Timeout).await;
+    // The user unlocks, then the session gets locked by something else
+    monitor.set_locked(false);
+    monitor.set_locked(true);
+
+    assert_eq!(pipeline.auto_unlock(-50).await, Ok(false));
+    assert!(!pipeline.may_auto_unlock());
+    assert_eq!(unlocks.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_no_auto_unlock_after_manual_lock() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, unlocks) = serve_logind(&bus).await;
+    let (monitor, pipeline) = setup(&bus, 0).await;
+
+    lock(&monitor, &pipeline, LockReason::Manual).await;
+
+    assert!(!pipeline.may_auto_unlock());
+    assert_eq!(pipeline.auto_unlock(-50).await, Ok(false));
+    assert_eq!(unlocks.load(Ordering::SeqCst), 0);
+}
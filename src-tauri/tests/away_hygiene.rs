@@ -0,0 +1,108 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for locking the Secret Service collections, run against a private bus with a
+// stub Secret Service
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::away_hygiene::lock_secret_collections;
+use std::sync::{Arc, Mutex};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+struct StubSecretService {
+    collections: Vec<OwnedObjectPath>,
+    locked: Arc<Mutex<Vec<OwnedObjectPath>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Secret.Service")]
+impl StubSecretService {
+    fn lock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        self.locked.lock().unwrap().extend(objects.iter().cloned());
+        (objects, ObjectPath::from_static_str_unchecked("/").into())
+    }
+
+    #[zbus(property)]
+    fn collections(&self) -> Vec<OwnedObjectPath> {
+        self.collections.clone()
+    }
+}
+
+async fn serve(
+    bus: &PrivateBus,
+    collections: &[&'static str],
+) -> (zbus::Connection, Arc<Mutex<Vec<OwnedObjectPath>>>) {
+    let locked = Arc::new(Mutex::new(Vec::new()));
+    let service = StubSecretService {
+        collections: collections
+            .iter()
+            .map(|path| ObjectPath::from_static_str_unchecked(path).into())
+            .collect(),
+        locked: locked.clone(),
+    };
+    let connection = bus
+        .builder()
+        .name("org.freedesktop.secrets")
+        .unwrap()
+        .serve_at("/org/freedesktop/secrets", service)
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    (connection, locked)
+}
+
+#[tokio::test]
+async fn test_lock_secret_collections() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_service, locked) = serve(
+        &bus,
+        &[
+            "/org/freedesktop/secrets/collection/login",
+            "/org/freedesktop/secrets/collection/work",
+        ],
+    )
+    .await;
+    let client = bus.connect().await;
+
+    let count = lock_secret_collections(&client).await.unwrap();
+
+    assert_eq!(count, 2);
+    let locked: Vec<String> = locked
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    assert_eq!(
+        locked,
+        vec![
+            "/org/freedesktop/secrets/collection/login",
+            "/org/freedesktop/secrets/collection/work"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_lock_without_collections() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_service, locked) = serve(&bus, &[]).await;
+    let client = bus.connect().await;
+
+    assert_eq!(lock_secret_collections(&client).await, Ok(0));
+    assert!(locked.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_lock_without_secret_service() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let client = bus.connect().await;
+
+    assert!(lock_secret_collections(&client).await.is_err());
+}
@@ -0,0 +1,125 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the away action plan, run against a private bus with a stub screen saver
+// standing in for the session bus
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::away_actions::{AwayAction, AwayActionPlan, AwayStep};
+use lock_4_me_lib::lock_methods::LockMethod;
+use lock_4_me_lib::lock_pipeline::{LockContext, LockOutcome, LockPipeline, LockReason};
+use lock_4_me_lib::lock_state::LockStateMonitor;
+use lock_4_me_lib::read_write_settings::Settings;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+use zbus::object_server::SignalEmitter;
+
+const SCREENSAVER_PATH: &str = "/org/freedesktop/ScreenSaver";
+
+struct StubScreenSaver {
+    active: bool,
+    locked: Arc<AtomicBool>,
+}
+
+#[zbus::interface(name = "org.freedesktop.ScreenSaver")]
+impl StubScreenSaver {
+    fn get_active(&self) -> bool {
+        self.active
+    }
+
+    async fn set_active(
+        &mut self,
+        active: bool,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> bool {
+        self.active = active;
+        Self::active_changed(&emitter, active).await.unwrap();
+        true
+    }
+
+    fn lock(&self) {
+        self.locked.store(true, Ordering::SeqCst);
+    }
+
+    #[zbus(signal)]
+    async fn active_changed(emitter: &SignalEmitter<'_>, active: bool) -> zbus::Result<()>;
+}
+
+#[tokio::test]
+async fn test_lock_step_after_blank_locks() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    // The away actions and lock backends connect to the session bus on their own
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+    let locked = Arc::new(AtomicBool::new(false));
+    let screensaver = bus
+        .builder()
+        .name("org.freedesktop.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            SCREENSAVER_PATH,
+            StubScreenSaver {
+                active: false,
+                locked: locked.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    // Without logind, the lock state follows the screen saver, which blanking activates
+    let monitor = LockStateMonitor::new();
+    let client = bus.connect().await;
+    monitor.watch_screensaver(&client).await.unwrap();
+    let pipeline = LockPipeline::new(monitor.clone(), false);
+    pipeline.apply_settings(&Settings {
+        pinned_lock_method: Some(LockMethod::ScreenSaver),
+        away_action_plan: Some(AwayActionPlan {
+            steps: vec![
+                AwayStep {
+                    action: AwayAction::Blank,
+                    delay_secs: 0,
+                },
+                AwayStep {
+                    action: AwayAction::Lock,
+                    delay_secs: 0,
+                },
+            ],
+        }),
+        ..Settings::default()
+    });
+
+    let request = tokio::spawn({
+        let pipeline = pipeline.clone();
+        async move {
+            pipeline
+                .request_lock(LockContext::new(LockReason::OverDelta))
+                .await
+        }
+    });
+    timeout(Duration::from_secs(5), async {
+        while !locked.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("Lock step after blank should call the lock backend");
+    assert!(monitor.is_screensaver_active());
+    assert!(pipeline.is_away());
+
+    // Waking the screen saver unlocks the session and ends the plan
+    let emitter = SignalEmitter::new(&screensaver, SCREENSAVER_PATH).unwrap();
+    StubScreenSaver::active_changed(&emitter, false)
+        .await
+        .unwrap();
+    let outcome = timeout(Duration::from_secs(5), request)
+        .await
+        .expect("Away plan should end when the session is unlocked")
+        .unwrap();
+    assert_eq!(outcome, Ok(LockOutcome::PlanCompleted));
+    assert!(!pipeline.is_away());
+}
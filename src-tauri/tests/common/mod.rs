@@ -0,0 +1,84 @@
+// Shared helpers for the D-Bus integration tests
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use tempfile::TempDir;
+
+/// A private `dbus-daemon` instance listening on a socket in a temporary directory.
+///
+/// The daemon is killed when the value is dropped.
+pub struct PrivateBus {
+    child: Child,
+    pub address: String,
+    _dir: TempDir,
+}
+
+impl PrivateBus {
+    /// Starts a private bus, or returns `None` when `dbus-daemon` is not installed.
+    pub fn start() -> Option<Self> {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("bus.conf");
+        let config = format!(
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+            dir.path().display()
+        );
+        std::fs::write(&config_path, config).unwrap();
+
+        let mut child = match Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config_path.display()))
+            .arg("--nofork")
+            .arg("--print-address")
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Skipping D-Bus test, dbus-daemon unavailable: {}", e);
+                return None;
+            }
+        };
+
+        let mut address = String::new();
+        let stdout = child.stdout.take().unwrap();
+        BufReader::new(stdout).read_line(&mut address).unwrap();
+
+        Some(PrivateBus {
+            child,
+            address: address.trim().to_string(),
+            _dir: dir,
+        })
+    }
+
+    /// Opens a new client connection to the private bus.
+    pub async fn connect(&self) -> zbus::Connection {
+        zbus::connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+
+    /// Returns a connection builder for serving stub objects on the private bus.
+    pub fn builder(&self) -> zbus::connection::Builder<'_> {
+        zbus::connection::Builder::address(self.address.as_str()).unwrap()
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
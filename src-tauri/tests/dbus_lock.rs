@@ -0,0 +1,448 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the D-Bus lock backend, run against a private bus with stub services
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::dbus_lock::{
+    blank_screen, lock_all_logind_sessions, lock_logind_session, lock_screensaver,
+};
+use lock_4_me_lib::logind::suspend;
+use std::os::unix::fs::MetadataExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use zbus::zvariant::OwnedObjectPath;
+
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/_32";
+
+#[derive(Default)]
+struct StubManager {
+    suspends: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubManager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+
+    fn suspend(&self, interactive: bool) {
+        assert!(!interactive);
+        self.suspends.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct StubSession {
+    locks: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl StubSession {
+    fn lock(&self) {
+        self.locks.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct StubScreenSaver {
+    locks: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.freedesktop.ScreenSaver")]
+impl StubScreenSaver {
+    fn lock(&self) {
+        self.locks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn set_active(&self, active: bool) -> bool {
+        // Count activations with the same counter as locks
+        if active {
+            self.locks.fetch_add(1, Ordering::SeqCst);
+        }
+        active
+    }
+}
+
+struct StubGnomeScreenSaver {
+    locks: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.gnome.ScreenSaver")]
+impl StubGnomeScreenSaver {
+    fn lock(&self) {
+        self.locks.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_lock_logind_session_calls_session_lock() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let locks = Arc::new(AtomicUsize::new(0));
+    let _logind = bus
+        .builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubManager::default())
+        .unwrap()
+        .serve_at(
+            SESSION_PATH,
+            StubSession {
+                locks: locks.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let client = bus.connect().await;
+    let result = lock_logind_session(&client).await;
+
+    assert!(result.is_ok(), "logind lock should succeed: {:?}", result);
+    assert_eq!(locks.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_lock_logind_session_without_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let client = bus.connect().await;
+    let result = lock_logind_session(&client).await;
+
+    assert!(result.is_err());
+    assert!(result
+        .unwrap_err()
+        .contains("Failed to resolve logind session"));
+}
+
+#[tokio::test]
+async fn test_lock_screensaver_prefers_freedesktop() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let freedesktop_locks = Arc::new(AtomicUsize::new(0));
+    let gnome_locks = Arc::new(AtomicUsize::new(0));
+    let _freedesktop = bus
+        .builder()
+        .name("org.freedesktop.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/ScreenSaver",
+            StubScreenSaver {
+                locks: freedesktop_locks.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let _gnome = bus
+        .builder()
+        .name("org.gnome.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/gnome/ScreenSaver",
+            StubGnomeScreenSaver {
+                locks: gnome_locks.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let client = bus.connect().await;
+    let result = lock_screensaver(&client).await;
+
+    assert!(
+        result.is_ok(),
+        "screen saver lock should succeed: {:?}",
+        result
+    );
+    assert_eq!(freedesktop_locks.load(Ordering::SeqCst), 1);
+    assert_eq!(gnome_locks.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_lock_screensaver_falls_back_to_gnome() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let locks = Arc::new(AtomicUsize::new(0));
+    let _gnome = bus
+        .builder()
+        .name("org.gnome.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/gnome/ScreenSaver",
+            StubGnomeScreenSaver {
+                locks: locks.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let client = bus.connect().await;
+    let result = lock_screensaver(&client).await;
+
+    assert!(
+        result.is_ok(),
+        "GNOME fallback should succeed: {:?}",
+        result
+    );
+    assert_eq!(locks.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_lock_screensaver_without_services() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let client = bus.connect().await;
+    let result = lock_screensaver(&client).await;
+
+    assert!(result.is_err());
+    let error = result.unwrap_err();
+    assert!(error.contains("Failed to lock screen saver"));
+    assert!(error.contains("org.gnome.ScreenSaver"));
+}
+
+#[tokio::test]
+async fn test_blank_screen_activates_screensaver() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let activations = Arc::new(AtomicUsize::new(0));
+    let _screensaver = bus
+        .builder()
+        .name("org.freedesktop.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/ScreenSaver",
+            StubScreenSaver {
+                locks: activations.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let client = bus.connect().await;
+    let result = blank_screen(&client).await;
+
+    assert!(result.is_ok(), "Blanking should succeed: {:?}", result);
+    assert_eq!(activations.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_blank_screen_without_services() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let client = bus.connect().await;
+    let result = blank_screen(&client).await;
+
+    assert!(result.unwrap_err().contains("Failed to blank screen"));
+}
+
+#[tokio::test]
+async fn test_suspend_calls_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let suspends = Arc::new(AtomicUsize::new(0));
+    let _logind = bus
+        .builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/login1",
+            StubManager {
+                suspends: suspends.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let client = bus.connect().await;
+    let result = suspend(&client).await;
+
+    assert!(result.is_ok(), "Suspend should succeed: {:?}", result);
+    assert_eq!(suspends.load(Ordering::SeqCst), 1);
+}
+
+/// A logind session with the properties used to pick the graphical session
+struct StubUserSession {
+    id: &'static str,
+    session_type: &'static str,
+    class: &'static str,
+    active: bool,
+    locks: Arc<AtomicUsize>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl StubUserSession {
+    fn lock(&self) {
+        self.locks.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    #[zbus(property, name = "Type")]
+    fn session_type(&self) -> String {
+        self.session_type.to_string()
+    }
+
+    #[zbus(property)]
+    fn class(&self) -> String {
+        self.class.to_string()
+    }
+
+    #[zbus(property)]
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    #[zbus(property)]
+    fn remote(&self) -> bool {
+        false
+    }
+}
+
+/// A logind manager started from a console session of a user with several sessions
+struct StubMultiSessionManager {
+    uid: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubMultiSessionManager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from("/org/freedesktop/login1/session/c1").unwrap()
+    }
+
+    fn list_sessions(&self) -> Vec<(String, u32, String, String, OwnedObjectPath)> {
+        [
+            ("c1", self.uid),
+            ("greeter", self.uid),
+            ("2", self.uid),
+            ("3", self.uid),
+            ("other", self.uid + 1),
+        ]
+        .into_iter()
+        .map(|(id, uid)| {
+            (
+                id.to_string(),
+                uid,
+                "user".to_string(),
+                "seat0".to_string(),
+                OwnedObjectPath::try_from(format!("/org/freedesktop/login1/session/{}", id))
+                    .unwrap(),
+            )
+        })
+        .collect()
+    }
+}
+
+/// Serves the sessions of [`StubMultiSessionManager`], returning their lock counters by id
+async fn serve_user_sessions(
+    bus: &PrivateBus,
+) -> (zbus::Connection, Vec<(&'static str, Arc<AtomicUsize>)>) {
+    let uid = std::fs::metadata("/proc/self").unwrap().uid();
+    let sessions = [
+        ("c1", "tty", "user", true),
+        ("greeter", "x11", "greeter", false),
+        ("2", "x11", "user", false),
+        ("3", "wayland", "user", true),
+        ("other", "wayland", "user", true),
+    ];
+    let mut builder = bus
+        .builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubMultiSessionManager { uid })
+        .unwrap();
+    let mut counters = Vec::new();
+    for (id, session_type, class, active) in sessions {
+        let locks = Arc::new(AtomicUsize::new(0));
+        builder = builder
+            .serve_at(
+                format!("/org/freedesktop/login1/session/{}", id),
+                StubUserSession {
+                    id,
+                    session_type,
+                    class,
+                    active,
+                    locks: locks.clone(),
+                },
+            )
+            .unwrap();
+        counters.push((id, locks));
+    }
+    (builder.build().await.unwrap(), counters)
+}
+
+fn locked_sessions(counters: &[(&'static str, Arc<AtomicUsize>)]) -> Vec<&'static str> {
+    counters
+        .iter()
+        .filter(|(_, locks)| locks.load(Ordering::SeqCst) > 0)
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_lock_logind_session_picks_active_graphical_session() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, counters) = serve_user_sessions(&bus).await;
+
+    let client = bus.connect().await;
+    let result = lock_logind_session(&client).await;
+
+    assert!(result.is_ok(), "logind lock should succeed: {:?}", result);
+    assert_eq!(locked_sessions(&counters), ["3"]);
+}
+
+#[tokio::test]
+async fn test_lock_all_logind_sessions_locks_graphical_sessions_of_user() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_logind, counters) = serve_user_sessions(&bus).await;
+
+    let client = bus.connect().await;
+    let result = lock_all_logind_sessions(&client).await;
+
+    assert_eq!(result, Ok(2));
+    assert_eq!(locked_sessions(&counters), ["2", "3"]);
+}
+
+#[tokio::test]
+async fn test_lock_all_logind_sessions_without_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let client = bus.connect().await;
+    let result = lock_all_logind_sessions(&client).await;
+
+    assert!(result
+        .unwrap_err()
+        .contains("Failed to list logind sessions"));
+}
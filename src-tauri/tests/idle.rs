@@ -0,0 +1,220 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for reading the session idle time and gating weak-signal locks on it, run
+// against a private bus with stub logind and screen saver services
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::idle::{logind_idle_secs, screensaver_idle_secs, IdleMonitor};
+use lock_4_me_lib::lock_pipeline::{
+    LockContext, LockOutcome, LockPipeline, LockPipelineEvent, LockReason,
+};
+use lock_4_me_lib::lock_state::LockStateMonitor;
+use lock_4_me_lib::read_write_settings::Settings;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zbus::zvariant::OwnedObjectPath;
+
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/_32";
+
+struct StubManager;
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubManager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+}
+
+struct StubSession {
+    idle_hint: bool,
+    idle_since_hint: u64,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl StubSession {
+    #[zbus(property)]
+    fn idle_hint(&self) -> bool {
+        self.idle_hint
+    }
+
+    #[zbus(property)]
+    fn idle_since_hint(&self) -> u64 {
+        self.idle_since_hint
+    }
+}
+
+struct StubScreenSaver {
+    idle_secs: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.ScreenSaver")]
+impl StubScreenSaver {
+    fn get_session_idle_time(&self) -> u32 {
+        self.idle_secs
+    }
+}
+
+/// Microseconds since the epoch, `secs_ago` seconds ago
+fn usec_ago(secs_ago: u64) -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    (now.as_secs() - secs_ago) * 1_000_000
+}
+
+async fn serve_logind(bus: &PrivateBus, idle_hint: bool, idle_since_hint: u64) -> zbus::Connection {
+    bus.builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubManager)
+        .unwrap()
+        .serve_at(
+            SESSION_PATH,
+            StubSession {
+                idle_hint,
+                idle_since_hint,
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+async fn serve_screensaver(bus: &PrivateBus, idle_secs: u32) -> zbus::Connection {
+    bus.builder()
+        .name("org.freedesktop.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/ScreenSaver",
+            StubScreenSaver { idle_secs },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+/// A dry-run pipeline reading the idle time from the private bus
+async fn idle_gated_pipeline(bus: &PrivateBus, min_idle_secs: u64) -> LockPipeline {
+    let client = bus.connect().await;
+    let idle = IdleMonitor::with_connections(Some(client.clone()), Some(client));
+    let pipeline = LockPipeline::new(LockStateMonitor::new(), true).with_idle_monitor(idle);
+    pipeline.apply_settings(&Settings {
+        min_idle_secs: Some(min_idle_secs),
+        ..Default::default()
+    });
+    pipeline
+}
+
+#[tokio::test]
+async fn test_screensaver_idle_time() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _screensaver = serve_screensaver(&bus, 42).await;
+    let client = bus.connect().await;
+
+    assert_eq!(screensaver_idle_secs(&client).await, Ok(42));
+}
+
+#[tokio::test]
+async fn test_logind_idle_time() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _logind = serve_logind(&bus, true, usec_ago(120)).await;
+    let client = bus.connect().await;
+
+    let idle_secs = logind_idle_secs(&client).await.unwrap();
+    assert!(
+        (120..125).contains(&idle_secs),
+        "Unexpected idle time {}",
+        idle_secs
+    );
+}
+
+#[tokio::test]
+async fn test_logind_not_idle() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _logind = serve_logind(&bus, false, usec_ago(120)).await;
+    let client = bus.connect().await;
+
+    assert_eq!(logind_idle_secs(&client).await, Ok(0));
+}
+
+#[tokio::test]
+async fn test_idle_monitor_falls_back_to_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _logind = serve_logind(&bus, true, usec_ago(300)).await;
+    let client = bus.connect().await;
+    let idle = IdleMonitor::with_connections(Some(client.clone()), Some(client));
+
+    assert!(idle.idle_secs().await.unwrap() >= 300);
+}
+
+#[tokio::test]
+async fn test_active_user_gets_warning_instead_of_lock() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _screensaver = serve_screensaver(&bus, 5).await;
+    let pipeline = idle_gated_pipeline(&bus, 60).await;
+    let mut events = pipeline.subscribe();
+
+    let outcome = pipeline
+        .request_lock(LockContext::new(LockReason::OverDelta))
+        .await;
+    assert_eq!(outcome, Ok(LockOutcome::UserActive));
+    match events.try_recv().unwrap() {
+        LockPipelineEvent::UserActive(warning) => {
+            assert_eq!(warning.idle_secs, 5);
+            assert_eq!(warning.min_idle_secs, 60);
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    // The warning is only given once until the device returns
+    let outcome = pipeline
+        .request_lock(LockContext::new(LockReason::Timeout))
+        .await;
+    assert_eq!(outcome, Ok(LockOutcome::UserActive));
+    assert!(events.try_recv().is_err());
+
+    // Manual locks are never gated
+    let outcome = pipeline
+        .request_lock(LockContext::new(LockReason::Manual))
+        .await;
+    assert_eq!(outcome, Ok(LockOutcome::Simulated));
+}
+
+#[tokio::test]
+async fn test_idle_user_gets_locked() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _screensaver = serve_screensaver(&bus, 120).await;
+    let pipeline = idle_gated_pipeline(&bus, 60).await;
+
+    let outcome = pipeline
+        .request_lock(LockContext::new(LockReason::OverDelta))
+        .await;
+    assert_eq!(outcome, Ok(LockOutcome::Simulated));
+}
+
+#[tokio::test]
+async fn test_lock_when_idle_time_unavailable() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let pipeline = idle_gated_pipeline(&bus, 60).await;
+
+    let outcome = pipeline
+        .request_lock(LockContext::new(LockReason::OverDelta))
+        .await;
+    assert_eq!(outcome, Ok(LockOutcome::Simulated));
+}
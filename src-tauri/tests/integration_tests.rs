@@ -1,3 +1,4 @@
+use lock_4_me_lib::read_write_settings::{Language, Theme};
 use std::fs;
 use tempfile::tempdir;
 
@@ -6,42 +7,37 @@ use tempfile::tempdir;
 #[cfg(test)]
 mod integration_tests {
     use super::*;
-    use lock_4_me_lib;
 
     #[test]
     fn test_settings_workflow() {
         // Test the complete settings workflow
         let dir = tempdir().unwrap();
-        let settings_path = dir.path().join("integration_settings.json");
-        let settings_path_str = settings_path.to_string_lossy().to_string();
+        let store = lock_4_me_lib::read_write_settings::SettingsStore::in_dir(dir.path(), None);
+        let settings_path = store.path().to_path_buf();
 
         // Create test settings
         let original_settings = lock_4_me_lib::read_write_settings::Settings {
             target_uuid: "12345678-1234-1234-1234-123456789012".to_string(),
-            rssi_delta_max: -40,
-            theme: "dark".to_string(),
-            language: "en".to_string(),
+            rssi_delta_max: 40,
+            theme: Theme::Dark,
+            language: Language::En,
+            ..Default::default()
         };
 
         // Test write operation
-        let write_result = lock_4_me_lib::read_write_settings::write_settings(
-            original_settings,
-            settings_path_str.clone()
-        );
+        let write_result = store.update(original_settings);
         assert!(write_result.is_ok(), "Write settings should succeed");
 
         // Verify file was created
         assert!(settings_path.exists(), "Settings file should exist");
 
         // Test read operation
-        let read_result = lock_4_me_lib::read_write_settings::read_settings(settings_path_str);
-        assert!(read_result.is_ok(), "Read settings should succeed");
-
-        let loaded_settings = read_result.unwrap();
+        let reopened = lock_4_me_lib::read_write_settings::SettingsStore::open(settings_path);
+        let loaded_settings = reopened.get();
         assert_eq!(loaded_settings.target_uuid, "12345678-1234-1234-1234-123456789012");
-        assert_eq!(loaded_settings.rssi_delta_max, -40);
-        assert_eq!(loaded_settings.theme, "dark");
-        assert_eq!(loaded_settings.language, "en");
+        assert_eq!(loaded_settings.rssi_delta_max, 40);
+        assert_eq!(loaded_settings.theme, Theme::Dark);
+        assert_eq!(loaded_settings.language, Language::En);
     }
 
     #[test]
@@ -80,8 +76,9 @@ mod integration_tests {
         let settings = Settings {
             target_uuid: test_uuid.to_string(),
             rssi_delta_max: -50,
-            theme: "light".to_string(),
-            language: "fr".to_string(),
+            theme: Theme::Light,
+            language: Language::Fr,
+            ..Default::default()
         };
 
         // Test that the UUID in settings can be parsed by Bluetooth module
@@ -103,8 +100,9 @@ mod integration_tests {
         let settings = lock_4_me_lib::read_write_settings::Settings {
             target_uuid: "test-uuid".to_string(),
             rssi_delta_max: 0, // Edge case: zero delta
-            theme: "".to_string(), // Edge case: empty string
-            language: "zh-CN".to_string(), // Multi-part language code
+            theme: Theme::Light,
+            language: Language::Fr,
+            ..Default::default()
         };
 
         let save_result = settings.save(settings_file.to_str().unwrap());
@@ -116,8 +114,8 @@ mod integration_tests {
 
         let loaded = load_result.unwrap();
         assert_eq!(loaded.rssi_delta_max, 0);
-        assert_eq!(loaded.theme, "");
-        assert_eq!(loaded.language, "zh-CN");
+        assert_eq!(loaded.theme, Theme::Light);
+        assert_eq!(loaded.language, Language::Fr);
     }
 
     #[test]
@@ -140,6 +138,7 @@ mod integration_tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_platform_specific_functionality() {
         // Test that we can access the lock_screen module
         // Note: get_lock_screen_command is only available in test mode
@@ -0,0 +1,231 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the lock state monitor, run against a private bus with stub services
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::lock_state::{LockStateEvent, LockStateMonitor};
+use tokio::time::{timeout, Duration};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedObjectPath;
+
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/_32";
+
+struct StubManager;
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubManager {
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, _pid: u32) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(SESSION_PATH).unwrap()
+    }
+}
+
+struct StubSession {
+    locked_hint: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl StubSession {
+    #[zbus(property)]
+    fn locked_hint(&self) -> bool {
+        self.locked_hint
+    }
+}
+
+struct StubScreenSaver {
+    active: bool,
+}
+
+#[zbus::interface(name = "org.freedesktop.ScreenSaver")]
+impl StubScreenSaver {
+    fn get_active(&self) -> bool {
+        self.active
+    }
+
+    #[zbus(signal)]
+    async fn active_changed(emitter: &SignalEmitter<'_>, active: bool) -> zbus::Result<()>;
+}
+
+async fn serve_logind(bus: &PrivateBus, locked_hint: bool) -> zbus::Connection {
+    bus.builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", StubManager)
+        .unwrap()
+        .serve_at(SESSION_PATH, StubSession { locked_hint })
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+async fn serve_screensaver(bus: &PrivateBus) -> zbus::Connection {
+    bus.builder()
+        .name("org.freedesktop.ScreenSaver")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/ScreenSaver",
+            StubScreenSaver { active: false },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+async fn set_locked_hint(logind: &zbus::Connection, locked_hint: bool) {
+    let session = logind
+        .object_server()
+        .interface::<_, StubSession>(SESSION_PATH)
+        .await
+        .unwrap();
+    session.get_mut().await.locked_hint = locked_hint;
+    session
+        .get()
+        .await
+        .locked_hint_changed(session.signal_emitter())
+        .await
+        .unwrap();
+}
+
+async fn next_event(
+    events: &mut tokio::sync::broadcast::Receiver<LockStateEvent>,
+) -> LockStateEvent {
+    timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("Timed out waiting for lock state event")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_watch_logind_reads_initial_locked_hint() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _logind = serve_logind(&bus, true).await;
+
+    let monitor = LockStateMonitor::new();
+    let client = bus.connect().await;
+    let result = monitor.watch_logind(&client).await;
+
+    assert!(
+        result.is_ok(),
+        "Watching logind should succeed: {:?}",
+        result
+    );
+    assert!(monitor.is_watching());
+    assert!(monitor.is_locked());
+}
+
+#[tokio::test]
+async fn test_watch_logind_follows_locked_hint_changes() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let logind = serve_logind(&bus, false).await;
+
+    let monitor = LockStateMonitor::new();
+    let mut events = monitor.subscribe();
+    let client = bus.connect().await;
+    monitor.watch_logind(&client).await.unwrap();
+    assert!(!monitor.is_locked());
+
+    set_locked_hint(&logind, true).await;
+    assert_eq!(next_event(&mut events).await, LockStateEvent::Locked);
+    assert!(monitor.is_locked());
+
+    set_locked_hint(&logind, false).await;
+    assert_eq!(next_event(&mut events).await, LockStateEvent::Unlocked);
+    assert!(!monitor.is_locked());
+}
+
+#[tokio::test]
+async fn test_watch_logind_without_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let monitor = LockStateMonitor::new();
+    let client = bus.connect().await;
+    let result = monitor.watch_logind(&client).await;
+
+    assert!(result.is_err());
+    assert!(!monitor.is_watching());
+}
+
+#[tokio::test]
+async fn test_watch_screensaver_follows_active_changed() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let screensaver = serve_screensaver(&bus).await;
+
+    let monitor = LockStateMonitor::new();
+    let mut events = monitor.subscribe();
+    let client = bus.connect().await;
+    let result = monitor.watch_screensaver(&client).await;
+    assert!(
+        result.is_ok(),
+        "Watching screen saver should succeed: {:?}",
+        result
+    );
+    assert!(monitor.is_watching());
+
+    let emitter = SignalEmitter::new(&screensaver, "/org/freedesktop/ScreenSaver").unwrap();
+    StubScreenSaver::active_changed(&emitter, true)
+        .await
+        .unwrap();
+    assert_eq!(next_event(&mut events).await, LockStateEvent::Locked);
+
+    StubScreenSaver::active_changed(&emitter, false)
+        .await
+        .unwrap();
+    assert_eq!(next_event(&mut events).await, LockStateEvent::Unlocked);
+}
+
+#[tokio::test]
+async fn test_screensaver_does_not_override_logind() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let _logind = serve_logind(&bus, false).await;
+    let screensaver = serve_screensaver(&bus).await;
+
+    let monitor = LockStateMonitor::new();
+    let mut events = monitor.subscribe();
+    let client = bus.connect().await;
+    monitor.watch_logind(&client).await.unwrap();
+    monitor.watch_screensaver(&client).await.unwrap();
+
+    // A blanked screen is not a locked session
+    let emitter = SignalEmitter::new(&screensaver, "/org/freedesktop/ScreenSaver").unwrap();
+    StubScreenSaver::active_changed(&emitter, true)
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(5), async {
+        while !monitor.is_screensaver_active() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Timed out waiting for the screen saver to be active");
+
+    assert!(!monitor.is_locked());
+    assert!(events.try_recv().is_err(), "Blanking should not emit");
+}
+
+#[tokio::test]
+async fn test_watch_screensaver_without_services() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+
+    let monitor = LockStateMonitor::new();
+    let client = bus.connect().await;
+    let result = monitor.watch_screensaver(&client).await;
+
+    assert!(result.is_err());
+    assert!(result.unwrap_err().contains("No screen saver service"));
+}
@@ -0,0 +1,164 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the pending lock notifications, run against a private bus with a stub
+// notification service
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::lock_pipeline::{
+    LockContext, LockOutcome, LockPipeline, LockPipelineEvent, LockReason, PendingLockCancel,
+};
+use lock_4_me_lib::lock_state::LockStateMonitor;
+use lock_4_me_lib::notifications::{watch_pending_locks, CANCEL_ACTION};
+use lock_4_me_lib::read_write_settings::Settings;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedValue;
+
+const NOTIFICATION_ID: u32 = 7;
+
+#[derive(Debug, PartialEq)]
+enum Call {
+    Notify { actions: Vec<String> },
+    Close(u32),
+}
+
+struct StubNotifications {
+    calls: mpsc::UnboundedSender<Call>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl StubNotifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        _app_name: String,
+        _replaces_id: u32,
+        _app_icon: String,
+        _summary: String,
+        _body: String,
+        actions: Vec<String>,
+        _hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        let _ = self.calls.send(Call::Notify { actions });
+        NOTIFICATION_ID
+    }
+
+    fn close_notification(&self, id: u32) {
+        let _ = self.calls.send(Call::Close(id));
+    }
+
+    #[zbus(signal)]
+    async fn action_invoked(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Starts a stub notification service and a dry-run pipeline with a long warning countdown
+async fn setup(
+    bus: &PrivateBus,
+) -> (
+    zbus::Connection,
+    mpsc::UnboundedReceiver<Call>,
+    LockPipeline,
+) {
+    let (calls, received) = mpsc::unbounded_channel();
+    let service = bus
+        .builder()
+        .name("org.freedesktop.Notifications")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/Notifications",
+            StubNotifications { calls },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    let pipeline = LockPipeline::new(LockStateMonitor::new(), true);
+    pipeline.apply_settings(&Settings {
+        lock_warning_secs: 60,
+        ..Default::default()
+    });
+    let client = bus.connect().await;
+    watch_pending_locks(&client, pipeline.clone())
+        .await
+        .unwrap();
+    (service, received, pipeline)
+}
+
+async fn next_call(calls: &mut mpsc::UnboundedReceiver<Call>) -> Call {
+    timeout(Duration::from_secs(5), calls.recv())
+        .await
+        .expect("Timed out waiting for notification call")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_notification_action_cancels_pending_lock() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (service, mut calls, pipeline) = setup(&bus).await;
+
+    let request = tokio::spawn({
+        let pipeline = pipeline.clone();
+        async move {
+            pipeline
+                .request_lock(LockContext::new(LockReason::OverDelta))
+                .await
+        }
+    });
+    assert_eq!(
+        next_call(&mut calls).await,
+        Call::Notify {
+            actions: vec![CANCEL_ACTION.to_string(), "Cancel".to_string()]
+        }
+    );
+
+    let emitter = SignalEmitter::new(&service, "/org/freedesktop/Notifications").unwrap();
+    StubNotifications::action_invoked(&emitter, NOTIFICATION_ID, CANCEL_ACTION)
+        .await
+        .unwrap();
+
+    let outcome = timeout(Duration::from_secs(5), request)
+        .await
+        .expect("Pending lock was not cancelled")
+        .unwrap();
+    assert_eq!(outcome, Ok(LockOutcome::Cancelled));
+}
+
+#[tokio::test]
+async fn test_notification_closed_when_device_returns() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (_service, mut calls, pipeline) = setup(&bus).await;
+    let mut events = pipeline.subscribe();
+
+    let request = tokio::spawn({
+        let pipeline = pipeline.clone();
+        async move {
+            pipeline
+                .request_lock(LockContext::new(LockReason::Timeout))
+                .await
+        }
+    });
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        LockPipelineEvent::Pending(_)
+    ));
+    assert!(matches!(next_call(&mut calls).await, Call::Notify { .. }));
+
+    assert!(pipeline.cancel_pending_lock(PendingLockCancel::DeviceReturned));
+
+    assert_eq!(request.await.unwrap(), Ok(LockOutcome::Cancelled));
+    assert_eq!(next_call(&mut calls).await, Call::Close(NOTIFICATION_ID));
+}
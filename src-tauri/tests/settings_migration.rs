@@ -0,0 +1,165 @@
+// Upgrade of settings files written by each earlier version of the schema, from the fixtures in
+// tests/fixtures/settings
+
+use lock_4_me_lib::away_actions::AwayAction;
+use lock_4_me_lib::lock_methods::LockMethod;
+use lock_4_me_lib::read_write_settings::{
+    migration_backup_path, Language, Settings, SettingsStore, Theme, SETTINGS_FILE,
+};
+use lock_4_me_lib::settings_migration::CURRENT_SCHEMA_VERSION;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const TARGET_UUID: &str = "4f1c2d3e-5a6b-4c7d-8e9f-0a1b2c3d4e5f";
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/settings")
+        .join(name);
+    fs::read_to_string(path).unwrap()
+}
+
+/// Opens a settings store over a copy of the fixture, returning the store and the original
+/// fixture content
+fn open_fixture(dir: &TempDir, name: &str) -> (SettingsStore, String) {
+    let original = fixture(name);
+    let path: PathBuf = dir.path().join(SETTINGS_FILE);
+    fs::write(&path, &original).unwrap();
+    (SettingsStore::open(path), original)
+}
+
+/// Checks that the file was upgraded in place, with the original kept as a backup
+fn assert_upgraded(store: &SettingsStore, original: &str, from_version: u32) {
+    let backup = migration_backup_path(store.path(), from_version);
+    assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+    let (saved, version) = Settings::load_versioned(store.path()).unwrap();
+    assert_eq!(version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(saved.schema_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(saved.target_uuid, TARGET_UUID);
+}
+
+#[test]
+fn test_migrate_v0_baseline() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v0_baseline.json");
+
+    let settings = store.get();
+    assert_eq!(settings.target_uuid, TARGET_UUID);
+    assert_eq!(settings.rssi_delta_max, 20);
+    assert_eq!(settings.theme, Theme::Light);
+    assert_eq!(settings.language, Language::Fr);
+    assert!(settings.custom_lock_command.is_none());
+    assert!(!settings.dry_run);
+    assert_upgraded(&store, &original, 0);
+}
+
+#[test]
+fn test_migrate_v0_lock_command() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v0_lock_command.json");
+
+    let settings = store.get();
+    assert_eq!(
+        settings.custom_lock_command.unwrap().argv,
+        ["swaylock", "-f"]
+    );
+    assert_eq!(settings.pinned_lock_method, Some(LockMethod::Swaylock));
+    assert!(settings.dry_run);
+    assert_eq!(settings.lock_warning_secs, 0);
+    assert_upgraded(&store, &original, 0);
+}
+
+#[test]
+fn test_migrate_v0_away_plan() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v0_away_plan.json");
+
+    let settings = store.get();
+    assert_eq!(settings.lock_warning_secs, 10);
+    let steps = settings.away_action_plan.unwrap().steps;
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].action, AwayAction::Lock);
+    assert_eq!(settings.pre_lock_hooks[0].timeout_secs, Some(5));
+    assert!(!settings.away_hygiene.lock_keyrings);
+    assert_upgraded(&store, &original, 0);
+}
+
+#[test]
+fn test_migrate_v0_listener() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v0_listener.json");
+
+    let settings = store.get();
+    assert!(settings.lock_all_sessions);
+    assert!(settings.away_hygiene.lock_keyrings && settings.away_hygiene.clear_clipboard);
+    assert_eq!(settings.min_idle_secs, Some(60));
+    assert_eq!(settings.resume_grace_secs, Some(45));
+    assert_eq!(settings.unlock_grace_secs, Some(5));
+    assert!(settings.recompute_baseline_on_unlock);
+    assert_eq!(settings.auto_unlock.unwrap().dwell_secs, 10);
+    assert_upgraded(&store, &original, 0);
+}
+
+#[test]
+fn test_migrate_v0_partial_keeps_device() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v0_partial.json");
+
+    let settings = store.get();
+    assert_eq!(settings.target_uuid, TARGET_UUID);
+    assert_eq!(settings.rssi_delta_max, 20);
+    assert_eq!(settings.theme, Theme::Dark);
+    assert_eq!(settings.language, Language::En);
+    assert_upgraded(&store, &original, 0);
+}
+
+#[test]
+fn test_migrate_v1() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v1.json");
+
+    let settings = store.get();
+    assert_eq!(settings.theme, Theme::Light);
+    assert_eq!(settings.language, Language::Fr);
+    assert_upgraded(&store, &original, 1);
+}
+
+#[test]
+fn test_migrate_v1_free_strings() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v1_free_strings.json");
+
+    let settings = store.get();
+    assert_eq!(settings.theme, Theme::Light);
+    assert_eq!(settings.language, Language::En);
+    assert!(settings.dry_run);
+    assert_upgraded(&store, &original, 1);
+}
+
+#[test]
+fn test_current_version_not_migrated() {
+    let dir = TempDir::new().unwrap();
+    let (store, original) = open_fixture(&dir, "v2.json");
+
+    assert_eq!(store.get().target_uuid, TARGET_UUID);
+    assert!(!migration_backup_path(store.path(), 1).exists());
+    assert_eq!(fs::read_to_string(store.path()).unwrap(), original);
+}
+
+#[test]
+fn test_newer_version_left_untouched() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    let newer = format!(
+        r#"{{"schema_version": {}, "target_uuid": "{}"}}"#,
+        CURRENT_SCHEMA_VERSION + 1,
+        TARGET_UUID
+    );
+    fs::write(&path, &newer).unwrap();
+
+    let store = SettingsStore::open(path);
+
+    assert_eq!(store.get().target_uuid, "");
+    assert_eq!(fs::read_to_string(store.path()).unwrap(), newer);
+}
@@ -0,0 +1,139 @@
+#![cfg(target_os = "linux")]
+
+// Integration tests for the sleep monitor, run against a private bus with a stub logind manager
+
+mod common;
+
+use common::PrivateBus;
+use lock_4_me_lib::sleep::{SleepEvent, SleepMonitor};
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use tokio::time::{timeout, Duration};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedFd;
+
+/// Stub logind manager handing out one end of a socket pair as inhibitor lock, keeping the
+/// other end to find out when the lock is released
+struct StubManager {
+    inhibitors: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl StubManager {
+    fn inhibit(&self, what: &str, _who: &str, _why: &str, mode: &str) -> OwnedFd {
+        assert_eq!((what, mode), ("sleep", "delay"));
+        let (kept, handed_out) = UnixStream::pair().unwrap();
+        self.inhibitors.lock().unwrap().push(kept);
+        std::os::fd::OwnedFd::from(handed_out).into()
+    }
+
+    #[zbus(signal)]
+    async fn prepare_for_sleep(emitter: &SignalEmitter<'_>, start: bool) -> zbus::Result<()>;
+}
+
+async fn setup(bus: &PrivateBus) -> (zbus::Connection, Arc<Mutex<Vec<UnixStream>>>, SleepMonitor) {
+    let inhibitors = Arc::new(Mutex::new(Vec::new()));
+    let logind = bus
+        .builder()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/login1",
+            StubManager {
+                inhibitors: inhibitors.clone(),
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let monitor = SleepMonitor::new();
+    monitor.watch_logind(&bus.connect().await).await.unwrap();
+    (logind, inhibitors, monitor)
+}
+
+async fn prepare_for_sleep(logind: &zbus::Connection, start: bool) {
+    let emitter = SignalEmitter::new(logind, "/org/freedesktop/login1").unwrap();
+    StubManager::prepare_for_sleep(&emitter, start)
+        .await
+        .unwrap();
+}
+
+async fn next_event(events: &mut tokio::sync::broadcast::Receiver<SleepEvent>) -> SleepEvent {
+    timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("Timed out waiting for sleep event")
+        .unwrap()
+}
+
+/// Returns whether the peer of the kept socket end was closed
+fn released(kept: &mut UnixStream) -> bool {
+    kept.set_nonblocking(true).unwrap();
+    matches!(kept.read(&mut [0u8; 1]), Ok(0))
+}
+
+#[tokio::test]
+async fn test_sleep_and_resume_events() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (logind, inhibitors, monitor) = setup(&bus).await;
+    let mut events = monitor.subscribe();
+    assert!(monitor.is_inhibiting());
+
+    prepare_for_sleep(&logind, true).await;
+    assert_eq!(next_event(&mut events).await, SleepEvent::Sleeping);
+    assert!(monitor.is_sleeping());
+
+    prepare_for_sleep(&logind, false).await;
+    assert_eq!(next_event(&mut events).await, SleepEvent::Resumed);
+    assert!(!monitor.is_sleeping());
+
+    // A new inhibitor lock is taken after resuming
+    timeout(Duration::from_secs(5), async {
+        while inhibitors.lock().unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("Inhibitor lock not taken again after resume");
+}
+
+#[tokio::test]
+async fn test_inhibitor_released_when_ready_for_sleep() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (logind, inhibitors, monitor) = setup(&bus).await;
+    let mut events = monitor.subscribe();
+
+    prepare_for_sleep(&logind, true).await;
+    assert_eq!(next_event(&mut events).await, SleepEvent::Sleeping);
+    assert!(!released(&mut inhibitors.lock().unwrap()[0]));
+
+    monitor.ready_for_sleep();
+
+    assert!(!monitor.is_inhibiting());
+    assert!(released(&mut inhibitors.lock().unwrap()[0]));
+}
+
+#[tokio::test]
+async fn test_inhibitor_released_after_delay() {
+    let Some(bus) = PrivateBus::start() else {
+        return;
+    };
+    let (logind, _inhibitors, monitor) = setup(&bus).await;
+    let mut events = monitor.subscribe();
+
+    prepare_for_sleep(&logind, true).await;
+    assert_eq!(next_event(&mut events).await, SleepEvent::Sleeping);
+
+    timeout(Duration::from_secs(5), async {
+        while monitor.is_inhibiting() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Inhibitor lock not released");
+}
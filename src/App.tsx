@@ -5,29 +5,23 @@ import { listen } from '@tauri-apps/api/event';
 import HomeView from './views/HomeView';
 import SettingsView from './views/SettingsView';
 import { ThemeProvider, useTheme } from './hooks/ThemeContext';
-import { useEffect, useMemo } from 'react';
+import { useEffect } from 'react';
 import { useTranslation } from 'react-i18next';
 import { useSettingsStore } from './store/settingsStore';
 import { useAppStore } from './store/appStore';
 import LoadingSpinner from './components/LoadingSpinner';
 import { useBluetoothStore } from './store/bluetoothStore';
-import { DiscoveredDevice } from './types';
+import { DiscoveredDevice, SettingsChange } from './types';
 
-async function listen_bluetooth(targetUuid?: string, rssiDeltaMax?: number) {
-  await invoke('listen_bluetooth', {
-    target_uuid: targetUuid,
-    rssi_delta_max: rssiDeltaMax
-  });
-}
-
-async function lockScreen() {
-  await invoke('lock_screen');
+// The listener reads its settings in the backend, which restarts it when they change
+async function listen_bluetooth() {
+  await invoke('listen_bluetooth');
 }
 
 function App() {
   const { colors, setTheme } = useTheme();
   const { i18n } = useTranslation();
-  const { settings, loadSettings } = useSettingsStore();
+  const { settings, loadSettings, receiveSettings } = useSettingsStore();
   const { setIsLoading, isLoading } = useAppStore();
   const { addEvent, events } = useBluetoothStore();
 
@@ -50,6 +44,16 @@ function App() {
     loadSettings().finally(() => setIsLoading(false));
   }, [loadSettings, setIsLoading]);
 
+  useEffect(() => {
+    // Effects below depend on single settings, so only what changed is reapplied
+    const unlisten = listen<SettingsChange>('settings-changed', (event) =>
+      receiveSettings(event.payload.settings)
+    );
+    return () => {
+      unlisten.then((unlisten) => unlisten());
+    };
+  }, [receiveSettings]);
+
   useEffect(() => {
     if (settings.theme) {
       setTheme(settings.theme);
@@ -59,27 +63,17 @@ function App() {
     }
   }, [settings.theme, settings.language, i18n, setTheme]);
 
-  const { target_uuid, rssi_delta_max } = useMemo(
-    () => ({
-      target_uuid: settings.target_uuid || undefined,
-      rssi_delta_max: settings.target_uuid ? settings.rssi_delta_max : undefined
-    }),
-    [settings.target_uuid, settings.rssi_delta_max]
-  );
-
   useEffect(() => {
-    listen_bluetooth(target_uuid, rssi_delta_max);
+    listen_bluetooth();
 
     const unlistenPromises = [
-      listen('bluetooth-event', (event) => addEvent(event.payload as DiscoveredDevice)),
-      listen('bluetooth-refresh-timeout', () => lockScreen()),
-      listen('bluetooth-over-delta-rssi', () => lockScreen())
+      listen('bluetooth-event', (event) => addEvent(event.payload as DiscoveredDevice))
     ];
 
     return () => {
       unlistenPromises.forEach((promise) => promise.then((unlisten) => unlisten()));
     };
-  }, [target_uuid, rssi_delta_max, addEvent]);
+  }, [addEvent]);
 
   return (
     <BrowserRouter>
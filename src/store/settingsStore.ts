@@ -1,11 +1,16 @@
 import { create } from 'zustand';
-import { Settings } from '../types';
+import { FieldError, Settings, SettingsError } from '../types';
 import { invoke } from '@tauri-apps/api/core';
-import { appDataDir, join } from '@tauri-apps/api/path';
 
 interface SettingsStore {
   settings: Settings;
+  /** Fields rejected by the last update, shown next to each field */
+  fieldErrors: FieldError[];
   setSettings: (settings: Partial<Settings>) => void;
+  /** Runs a profile command (`create_profile`, `switch_profile`...) with its arguments */
+  updateProfiles: (command: string, args: Record<string, string>) => Promise<void>;
+  /** Takes in settings changed by the backend */
+  receiveSettings: (settings: Settings) => void;
   loadSettings: () => Promise<void>;
 }
 
@@ -18,29 +23,37 @@ const defaultSettings: Settings = {
 
 export const useSettingsStore = create<SettingsStore>((set, get) => ({
   settings: defaultSettings,
+  fieldErrors: [],
   setSettings: async (newSettings) => {
-    const updated = { ...get().settings, ...newSettings };
+    const previous = get().settings;
+    const updated = { ...previous, ...newSettings };
     set({ settings: updated });
-    // Persists to disk
-    const dir = await appDataDir();
-    const filePath = await join(dir, 'settings.json');
-    await invoke('write_settings', { file_path: filePath, settings: updated });
-  },
-  loadSettings: async () => {
-    const dir = await appDataDir();
-    const filePath = await join(dir, 'settings.json');
+    // Merged, persisted and applied by the backend, which returns the saved settings
     try {
-      const _settings = await invoke<Settings>('read_settings', {
-        file_path: filePath
+      const saved = await invoke<Settings>('update_settings', { patch: newSettings });
+      set({ settings: saved, fieldErrors: [] });
+    } catch (error) {
+      const settingsError = error as SettingsError;
+      set({
+        settings: previous,
+        fieldErrors: 'invalid' in settingsError ? settingsError.invalid : []
       });
-      set({ settings: _settings });
-    } catch (_) {
-      // If not found, write default
-      await invoke('write_settings', {
-        file_path: filePath,
-        settings: defaultSettings
-      });
-      set({ settings: defaultSettings });
+      throw error;
+    }
+  },
+  updateProfiles: async (command, args) => {
+    try {
+      const saved = await invoke<Settings>(command, args);
+      set({ settings: saved, fieldErrors: [] });
+    } catch (error) {
+      const settingsError = error as SettingsError;
+      set({ fieldErrors: 'invalid' in settingsError ? settingsError.invalid : [] });
+      throw error;
     }
+  },
+  receiveSettings: (settings) => set({ settings }),
+  loadSettings: async () => {
+    const _settings = await invoke<Settings>('get_settings');
+    set({ settings: _settings });
   }
 }));
@@ -2,11 +2,141 @@ export type Language = 'en' | 'fr';
 
 export type Theme = 'light' | 'dark';
 
+export type CustomLockCommand = {
+  argv: string[];
+  env?: Record<string, string>;
+  timeout_secs?: number | null;
+  exclusive?: boolean;
+};
+
+export type LockMethod =
+  | 'custom'
+  | 'logind'
+  | 'screen_saver'
+  | 'loginctl'
+  | 'swaylock'
+  | 'hyprlock'
+  | 'i3lock'
+  | 'gnome_screensaver_command'
+  | 'xdg_screensaver'
+  | 'qdbus'
+  | 'cinnamon_screensaver_command'
+  | 'mate_screensaver_command'
+  | 'xflock4'
+  | 'osascript'
+  | 'lock_work_station';
+
+export type AwayAction = 'notify' | 'blank' | 'lock' | 'suspend';
+
+export type AwayActionPlan = {
+  steps: { action: AwayAction; delay_secs: number }[];
+};
+
+export type AwayHygiene = {
+  lock_keyrings?: boolean;
+  flush_ssh_agent?: boolean;
+  clear_clipboard?: boolean;
+};
+
+export type AutoUnlock = {
+  within_secs: number;
+  min_rssi: number;
+  dwell_secs: number;
+};
+
+export type Hook = {
+  argv: string[];
+  env?: Record<string, string>;
+  timeout_secs?: number | null;
+};
+
+export type Weekday = 'mon' | 'tue' | 'wed' | 'thu' | 'fri' | 'sat' | 'sun';
+
+/** When automatic locking is active, times as `HH:MM` and dates as `YYYY-MM-DD` */
+export type Schedule = {
+  time_zone?: string | null;
+  windows?: { days: Weekday[]; start: string; end: string }[];
+  exceptions?: { date: string; active?: boolean }[];
+};
+
+/** Suppressing or relaxing automatic locks during events of local `.ics` files */
+export type CalendarSettings = {
+  files: string[];
+  keywords: string[];
+  action?: 'suppress' | { relax: { warning_secs: number } };
+};
+
+/** Settings that depend on where the user is, switched at once */
+export type Profile = {
+  target_uuid: string;
+  rssi_delta_max: number;
+  min_idle_secs?: number | null;
+  lock_warning_secs?: number;
+  away_action_plan?: AwayActionPlan | null;
+  pre_lock_hooks?: Hook[];
+  on_return_hooks?: Hook[];
+  auto_unlock?: AutoUnlock | null;
+};
+
 export type Settings = {
+  schema_version?: number;
   target_uuid: string;
   rssi_delta_max: number;
   theme: Theme;
   language: Language;
+  custom_lock_command?: CustomLockCommand | null;
+  pinned_lock_method?: LockMethod | null;
+  lock_all_sessions?: boolean;
+  dry_run?: boolean;
+  lock_warning_secs?: number;
+  away_action_plan?: AwayActionPlan | null;
+  pre_lock_hooks?: Hook[];
+  on_return_hooks?: Hook[];
+  away_hygiene?: AwayHygiene;
+  min_idle_secs?: number | null;
+  resume_grace_secs?: number | null;
+  unlock_grace_secs?: number | null;
+  recompute_baseline_on_unlock?: boolean;
+  auto_unlock?: AutoUnlock | null;
+  profiles?: Record<string, Profile>;
+  active_profile?: string | null;
+  schedule?: Schedule | null;
+  calendar?: CalendarSettings | null;
+};
+
+export type FieldErrorCode =
+  | 'invalid_uuid'
+  | 'out_of_range'
+  | 'invalid_command'
+  | 'invalid_value';
+
+export type FieldError = {
+  field: string;
+  code: FieldErrorCode;
+  message: string;
+};
+
+export type SettingsError = { invalid: FieldError[] } | { failed: string };
+
+/** Payload of the `settings-changed` event */
+export type SettingsChange = {
+  changed: (keyof Settings)[];
+  settings: Settings;
+};
+
+export type ImportMode = 'merge' | 'replace';
+
+/** A setting changed by a settings bundle import, with its values before and after */
+export type ImportChange = {
+  key: keyof Settings;
+  before: unknown;
+  after: unknown;
+};
+
+export type ImportReport = {
+  dry_run: boolean;
+  changes: ImportChange[];
+  settings: Settings;
 };
 
 export interface DiscoveredDevice {
@@ -26,3 +156,37 @@ export type Colors = {
   disabledColor: string;
   borderColor: string;
 };
+
+export type LockReason = 'over_delta' | 'timeout' | 'adapter_lost' | 'manual';
+
+export type HookResult = {
+  argv: string[];
+  exit_code: number | null;
+  success: boolean;
+  error: string | null;
+};
+
+export type SkipReason =
+  | { kind: 'outside_schedule' }
+  | { kind: 'calendar_event'; summary: string }
+  | { kind: 'user_active'; idle_secs: number; min_idle_secs: number }
+  | { kind: 'cancelled'; cause: 'user' | 'device_returned' };
+
+export type LockRecord = {
+  timestamp_ms: number;
+  event: 'lock' | 'return' | 'unlock';
+  reason: LockReason;
+  device_id: string | null;
+  rssi: number | null;
+  filtered_rssi: number | null;
+  baseline_rssi: number | null;
+  diff_rssi: number | null;
+  rssi_delta_max: number | null;
+  dry_run: boolean;
+  backend: LockMethod | null;
+  success: boolean;
+  error: string | null;
+  skipped: SkipReason | null;
+  hooks: HookResult[];
+  hygiene_errors: string[];
+};